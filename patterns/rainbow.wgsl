@@ -0,0 +1,11 @@
+// A rainbow that circles the cube's vertical axis.
+
+fn hue(h: f32) -> vec3<f32> {
+    let k = vec3(0.0, 2.0, 4.0) / 3.0;
+    return clamp(abs(fract(h + k) * 6.0 - 3.0) - 1.0, vec3(0.0), vec3(1.0));
+}
+
+fn pattern(in: PatternInput) -> vec3<f32> {
+    let angle = atan2(in.position.z, in.position.x) / TAU;
+    return hue(angle + 0.1 * in.position.y - 0.2 * in.time);
+}
@@ -0,0 +1,9 @@
+// Rings spreading from the top and bottom corners.
+
+fn pattern(in: PatternInput) -> vec3<f32> {
+    let d0 = distance(in.position, vec3(1.0, 1.0, 1.0));
+    let d1 = distance(in.position, vec3(-1.0, -1.0, -1.0));
+    let w0 = 0.5 + 0.5 * sin(8.0 * d0 - 3.0 * in.time);
+    let w1 = 0.5 + 0.5 * sin(8.0 * d1 - 2.0 * in.time);
+    return vec3(w0 * w0, 0.2 * w0 * w1, w1 * w1);
+}
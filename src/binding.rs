@@ -10,6 +10,7 @@ impl StaticBindings {
     const FLOOR_DECAL: u32 = 3;
     const FLOOR_DECAL_SAMPLER: u32 = 4;
     const GLOW_UNIFORM: u32 = 5;
+    const TWEAKS_UNIFORM: u32 = 6;
 
     pub fn new(device: &wgpu::Device) -> Self {
         let layout =
@@ -79,6 +80,16 @@ impl StaticBindings {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::TWEAKS_UNIFORM,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
         Self { layout }
@@ -87,12 +98,7 @@ impl StaticBindings {
     pub fn create_bind_group(
         &self,
         device: &wgpu::Device,
-        face_decal: wgpu::BindingResource,
-        camera_uniform: wgpu::BindingResource,
-        lights_uniform: wgpu::BindingResource,
-        floor_decal: wgpu::BindingResource,
-        floor_decal_sampler: wgpu::BindingResource,
-        glow_uniform: wgpu::BindingResource,
+        resources: StaticResources<'_>,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("static_bind_group"),
@@ -100,33 +106,48 @@ impl StaticBindings {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: Self::FACE_DECAL,
-                    resource: face_decal,
+                    resource: resources.face_decal,
                 },
                 wgpu::BindGroupEntry {
                     binding: Self::CAMERA_UNIFORM,
-                    resource: camera_uniform,
+                    resource: resources.camera_uniform,
                 },
                 wgpu::BindGroupEntry {
                     binding: Self::LIGHTS_UNIFORM,
-                    resource: lights_uniform,
+                    resource: resources.lights_uniform,
                 },
                 wgpu::BindGroupEntry {
                     binding: Self::FLOOR_DECAL,
-                    resource: floor_decal,
+                    resource: resources.floor_decal,
                 },
                 wgpu::BindGroupEntry {
                     binding: Self::FLOOR_DECAL_SAMPLER,
-                    resource: floor_decal_sampler,
+                    resource: resources.floor_decal_sampler,
                 },
                 wgpu::BindGroupEntry {
                     binding: Self::GLOW_UNIFORM,
-                    resource: glow_uniform,
+                    resource: resources.glow_uniform,
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::TWEAKS_UNIFORM,
+                    resource: resources.tweaks_uniform,
                 },
             ],
         })
     }
 }
 
+// What the static bind group binds, one resource per binding.
+pub struct StaticResources<'a> {
+    pub face_decal: wgpu::BindingResource<'a>,
+    pub camera_uniform: wgpu::BindingResource<'a>,
+    pub lights_uniform: wgpu::BindingResource<'a>,
+    pub floor_decal: wgpu::BindingResource<'a>,
+    pub floor_decal_sampler: wgpu::BindingResource<'a>,
+    pub glow_uniform: wgpu::BindingResource<'a>,
+    pub tweaks_uniform: wgpu::BindingResource<'a>,
+}
+
 pub struct FrameBindings {
     pub layout: wgpu::BindGroupLayout,
 }
@@ -533,3 +554,66 @@ impl CompositePassBindings {
         })
     }
 }
+
+pub struct PatternPassBindings {
+    pub layout: wgpu::BindGroupLayout,
+}
+
+impl PatternPassBindings {
+    pub const GROUP_INDEX: u32 = 0;
+    const PATTERN_UNIFORM: u32 = 0;
+    const PATTERN_TEXTURE: u32 = 1;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("pattern_pass_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::PATTERN_UNIFORM,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::PATTERN_TEXTURE,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba8Uint,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        Self { layout }
+    }
+
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        pattern_uniform: wgpu::BindingResource,
+        pattern_texture: wgpu::BindingResource,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pattern_pass_bind_group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: Self::PATTERN_UNIFORM,
+                    resource: pattern_uniform,
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::PATTERN_TEXTURE,
+                    resource: pattern_texture,
+                },
+            ],
+        })
+    }
+}
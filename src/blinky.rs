@@ -1,28 +1,60 @@
+use std::time::Instant;
+
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::pattern_shader::PatternShaders;
+use crate::prelude::*;
+use crate::recording::Recorder;
 use crate::test_pattern;
 use crate::traits::Renderable;
 
+const DEFAULT_PATTERN: &str = "stripes";
+
 pub struct Blinky {
-    test_pattern: test_pattern::TestPattern,
+    registry: PatternRegistry,
+    pattern_index: usize,
+    pattern: Box<dyn PatternSource>,
+    start_time: Instant,
+    frame_number: usize,
+    frame_info: FrameInfo,
+    shaders: PatternShaders,
+    shader_frame: Vec<u8>, // read back from the GPU
+    shader_ran: bool,
     blinky_texture: wgpu::Texture,
     blinky_texture_view: wgpu::TextureView,
+    recorder: Option<Recorder>,
 }
 
 impl Blinky {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let test_pattern = test_pattern::TestPattern::new();
+    pub fn new(
+        device: &wgpu::Device,
+        registry: PatternRegistry,
+        pattern_name: Option<&str>,
+        face_xforms: &[Mat4],
+    ) -> Self {
+        let pattern_index = match pattern_name {
+            Some(name) => registry.index_of(name).unwrap_or_else(|| {
+                eprintln!(
+                    "unknown pattern \"{}\"; choose one of: {}",
+                    name,
+                    registry.names().collect::<Vec<_>>().join(", "),
+                );
+                registry.index_of(DEFAULT_PATTERN).unwrap()
+            }),
+            None => registry.index_of(DEFAULT_PATTERN).unwrap(),
+        };
+        let pattern = registry.create(pattern_index);
         let blinky_texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("blinky_texture"),
-            size: wgpu::Extent3d {
-                width: 6 * 64,
-                height: 64,
-                depth_or_array_layers: 1,
-            },
+            size: texture_size(),
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8Uint,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST,
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING,
             view_formats: &[],
         });
         let blinky_texture_view =
@@ -30,11 +62,26 @@ impl Blinky {
                 label: Some("blinky_texture_view"),
                 ..Default::default()
             });
+        let shaders =
+            PatternShaders::new(device, &blinky_texture_view, face_xforms);
 
         Self {
-            test_pattern,
+            registry,
+            pattern_index,
+            pattern,
+            start_time: Instant::now(),
+            frame_number: 0,
+            frame_info: FrameInfo {
+                time: Default::default(),
+                frame_number: 0,
+                down: -Vec3::unit_y(),
+            },
+            shaders,
+            shader_frame: vec![0; panel().bytes()],
+            shader_ran: false,
             blinky_texture,
             blinky_texture_view,
+            recorder: None,
         }
     }
 
@@ -42,12 +89,99 @@ impl Blinky {
         wgpu::BindingResource::TextureView(&self.blinky_texture_view)
     }
 
-    pub fn update(&mut self) {
-        self.test_pattern.next_frame();
+    pub fn pattern_name(&self) -> &str {
+        self.pattern.name()
+    }
+
+    // Record every frame shown from now on, whatever the pattern.
+    pub fn record_to(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    // Start the animation over from `now`.
+    pub fn restart(&mut self, now: Instant) {
+        self.start_time = now;
+        self.frame_number = 0;
+    }
+
+    // Switch to the next registered pattern and restart the animation.
+    pub fn next_pattern(&mut self) {
+        self.switch_to((self.pattern_index + 1) % self.registry.len());
+    }
+
+    // Switch to the named pattern and restart the animation.
+    pub fn select_pattern(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(index) = self.registry.index_of(name) else {
+            anyhow::bail!("unknown pattern \"{}\"", name);
+        };
+        self.switch_to(index);
+        Ok(())
+    }
+
+    fn switch_to(&mut self, index: usize) {
+        self.pattern_index = index;
+        self.pattern = self.registry.create(index);
+        self.start_time = Instant::now();
+        self.frame_number = 0;
+        self.shader_frame.fill(0);
+        self.shaders.forget_pipeline();
+    }
+
+    // `down` is gravity in cube space.
+    pub fn update(&mut self, now: Instant, down: Vec3) {
+        self.frame_number += 1;
+        self.frame_info = FrameInfo {
+            time: now.duration_since(self.start_time),
+            frame_number: self.frame_number,
+            down,
+        };
+        self.pattern.next_frame(&self.frame_info);
+        // A shader pattern's frame arrives a frame or two late.
+        if let Some(recorder) = &mut self.recorder {
+            let frame = match self.pattern.shader() {
+                Some(_) => &self.shader_frame,
+                None => self.pattern.current_frame(),
+            };
+            if let Err(e) = recorder.record(now, frame) {
+                eprintln!("recording stopped: {:#}", e);
+                self.recorder = None;
+            }
+        }
     }
 
     pub fn current_frame(&self) -> &test_pattern::PixelArray {
-        self.test_pattern.current_frame()
+        match self.pattern.shader() {
+            Some(_) => &self.shader_frame,
+            None => self.pattern.current_frame(),
+        }
+    }
+
+    // Run a shader pattern.  Call before the forward pass.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.shader_ran = false;
+        if let Some(path) = self.pattern.shader() {
+            self.shaders.finish_readback(device, &mut self.shader_frame);
+            self.shader_ran = self.shaders.encode(
+                device,
+                queue,
+                encoder,
+                &self.blinky_texture,
+                path,
+                &self.frame_info,
+            );
+        }
+    }
+
+    // Call after the frame's commands have been submitted.
+    pub fn after_submit(&mut self) {
+        if self.shader_ran {
+            self.shaders.start_readback();
+        }
     }
 }
 
@@ -65,19 +199,94 @@ impl Renderable<BlinkyAttributes, BlinkyPreparedData> for Blinky {
         _: &mut wgpu::RenderPass,
         _: &BlinkyPreparedData,
     ) {
+        if self.shader_ran {
+            return;
+        }
         queue.write_texture(
             self.blinky_texture.as_image_copy(),
-            self.test_pattern.current_frame(),
+            self.pattern.current_frame(),
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(6 * 64 * 4),
+                bytes_per_row: Some(
+                    (panel().frame_width() * test_pattern::CHANNEL_COUNT)
+                        as u32,
+                ),
                 rows_per_image: None,
             },
-            wgpu::Extent3d {
-                width: 6 * 64,
-                height: 64,
-                depth_or_array_layers: 1,
-            },
+            texture_size(),
+        );
+    }
+}
+
+// The blinky texture holds one frame.
+fn texture_size() -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: panel().frame_width() as u32,
+        height: panel().frame_height() as u32,
+        depth_or_array_layers: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube_model::CubeModel;
+    use crate::pattern_shader;
+
+    #[test]
+    fn recompiles_shaders_after_a_switch() {
+        let instance = wgpu::Instance::new(
+            &wgpu::InstanceDescriptor::from_env_or_default(),
+        );
+        let options = wgpu::RequestAdapterOptions::default();
+        let Ok(adapter) =
+            pollster::block_on(instance.request_adapter(&options))
+        else {
+            eprintln!("skipping shader switches: no graphics adapter");
+            return;
+        };
+        let (device, queue) = pollster::block_on(
+            adapter.request_device(&wgpu::DeviceDescriptor::default()),
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir()
+            .join(format!("cube-pattern-shaders-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("edited.wgsl");
+        std::fs::write(&path, "fn pattern(in: PatternInput) -> f32 {}")
+            .unwrap();
+        let mut registry = PatternRegistry::with_builtins();
+        pattern_shader::register_dir(&mut registry, &dir).unwrap();
+        let mut blinky = Blinky::new(
+            &device,
+            registry,
+            Some("edited"),
+            &CubeModel::make_face_xforms(),
         );
+
+        let run = |blinky: &mut Blinky| {
+            let mut encoder = device.create_command_encoder(
+                &wgpu::CommandEncoderDescriptor::default(),
+            );
+            blinky.encode(&device, &queue, &mut encoder);
+            queue.submit([encoder.finish()]);
+            blinky.after_submit();
+            blinky.shader_ran
+        };
+        assert!(!run(&mut blinky));
+
+        // Fixed, it runs once the pattern comes around again.
+        std::fs::write(
+            &path,
+            "fn pattern(in: PatternInput) -> vec3<f32> { return vec3(1.0); }",
+        )
+        .unwrap();
+        assert!(!run(&mut blinky));
+        blinky.next_pattern();
+        blinky.select_pattern("edited").unwrap();
+        let ran = run(&mut blinky);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(ran);
     }
 }
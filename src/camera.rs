@@ -25,7 +25,7 @@ struct CameraUniformRaw {
     view_position: [f32; 4],
     world_to_clip: [[f32; 4]; 4],
     framebuffer_to_texture: [f32; 2],
-    _padding: [f32; 2],
+    framebuffer_offset: [f32; 2],
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -34,6 +34,32 @@ pub struct Configuration {
     pub height: u32,
 }
 
+// One tile of an image `count` times the framebuffer's size each way.
+// Columns count from the left and rows from the top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub column: u32,
+    pub row: u32,
+    pub count: u32,
+}
+
+impl Tile {
+    // Narrow clip space to the tile's part of the view.
+    fn clip_matrix(&self) -> Mat4 {
+        let n = self.count as f32;
+        let x = n - 1.0 - 2.0 * self.column as f32;
+        let y = 2.0 * self.row as f32 + 1.0 - n;
+        #[rustfmt::skip]
+        let m = Mat4::new(
+            n,    0.0,  0.0,  0.0,
+            0.0,  n,    0.0,  0.0,
+            0.0,  0.0,  1.0,  0.0,
+            x,    y,    0.0,  1.0,
+        );
+        m
+    }
+}
+
 pub struct Camera {
     config: Configuration,
     eye: Point3,
@@ -44,6 +70,7 @@ pub struct Camera {
     znear: f32,
     zfar: f32,
     world_hand: Hand,
+    tile: Option<Tile>,
 
     uniform_buffer: wgpu::Buffer,
 }
@@ -54,15 +81,13 @@ impl Camera {
         config: &Configuration,
         world_hand: Hand,
     ) -> Self {
-        let f2p: [f32; 2] = [
-            1.0 / config.width as f32,
-            1.0 / config.height as f32,
-        ];
+        let f2p: [f32; 2] =
+            [1.0 / config.width as f32, 1.0 / config.height as f32];
         let uniform_raw = CameraUniformRaw {
             view_position: [0.0, 0.0, 0.0, 0.0],
             world_to_clip: Mat4::identity().into(),
             framebuffer_to_texture: f2p,
-            _padding: [0.0, 0.0],
+            framebuffer_offset: [0.0, 0.0],
         };
         let uniform_buffer =
             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -84,6 +109,7 @@ impl Camera {
             znear: 100.0,
             zfar: backup * 1000.0,
             world_hand: world_hand,
+            tile: None,
             uniform_buffer,
         }
     }
@@ -101,6 +127,15 @@ impl Camera {
         self.config = *config;
     }
 
+    // Show only one tile of the view, or with None, all of it.
+    pub fn set_tile(&mut self, tile: Option<Tile>) {
+        self.tile = tile;
+    }
+
+    pub fn tile(&self) -> Option<Tile> {
+        self.tile
+    }
+
     fn build_view_projection_matrix(&self) -> Mat4 {
         let view = Mat4::look_at_rh(self.eye, self.target, self.up);
         let proj = cgmath::perspective(
@@ -114,7 +149,10 @@ impl Camera {
             Hand::Right => OPENGL_TO_WGPU_MATRIX,
         };
 
-        convert * proj * view
+        match self.tile {
+            Some(tile) => tile.clip_matrix() * convert * proj * view,
+            None => convert * proj * view,
+        }
     }
 }
 
@@ -126,16 +164,25 @@ pub struct CameraPreparedData {
 
 impl Renderable<CameraAttributes, CameraPreparedData> for Camera {
     fn prepare(&self, _: &CameraAttributes) -> CameraPreparedData {
+        // Screen space textures cover the whole view, not the tile.
+        let (count, column, row) = match self.tile {
+            Some(tile) => (tile.count, tile.column, tile.row),
+            None => (1, 0, 0),
+        };
         let f2p: [f32; 2] = [
-            1.0 / self.config.width as f32,
-            1.0 / self.config.height as f32,
+            1.0 / (count * self.config.width) as f32,
+            1.0 / (count * self.config.height) as f32,
+        ];
+        let offset = [
+            (column * self.config.width) as f32,
+            (row * self.config.height) as f32,
         ];
         CameraPreparedData {
             camera_uniform: CameraUniformRaw {
                 view_position: self.eye.to_homogeneous().into(),
                 world_to_clip: self.build_view_projection_matrix().into(),
                 framebuffer_to_texture: f2p,
-                _padding: [0.0, 0.0],
+                framebuffer_offset: offset,
             },
         }
     }
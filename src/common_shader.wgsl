@@ -6,6 +6,7 @@ struct CameraUniform {
     view_position: vec4<f32>,
     world_to_clip: mat4x4<f32>,
     framebuffer_to_texture: vec2<f32>,
+    framebuffer_offset: vec2<f32>, // the tile's place in the view
 }
 @group(0) @binding(1)
 var<uniform> camera: CameraUniform;
@@ -25,9 +26,24 @@ struct LightsUniform {
 @group(0) @binding(2)
 var<uniform> lights: LightsUniform;
 
+// Values to tune while the cube runs.  See tweaks.rs.
+struct TweaksUniform {
+    edge_color: vec4<f32>,
+    led_brightness: f32,
+    bloom: f32,             // only the post shaders use this
+    bloom_threshold: f32,
+    white_point: f32,       // and this
+    material: Material,     // material_defaults()
+}
+@group(0) @binding(6)
+var<uniform> tweaks: TweaksUniform;
+
 struct CubeUniform {
     cube_to_world: mat4x4<f32>,
     decal_visibility: f32,
+    led_r2: f32,             // LED dot radius squared, mm^2
+    led_count: vec2<f32>,    // LEDs across and down a face
+    led_pitch: vec2<f32>,    // mm between LED centers
 }
 @group(1) @binding(1)
 var<uniform> cube: CubeUniform;
@@ -41,8 +57,10 @@ var<uniform> shadow: ShadowUniform;
 const TAU: f32 = 6.283185307179586;
 const PI: f32 = 3.141592653589793;
 
-const USE_BRDF_FLAG: bool = true;
-const PRECOMPUTE_GLOW_FLAG: bool = true;
+// Shading options, set when the pipelines are made.  See shading.rs.
+override USE_BRDF_FLAG: bool = true;        // else Lambert/Blinn-Phong
+override PRECOMPUTE_GLOW_FLAG: bool = true; // else every pixel, every face
+override SHADOW_SAMPLES: u32 = 16u;         // 1, 4 or 16
 
 // ==== ==== ==== ==== ==== ==== ==== ==== ==== ==== ==== ==== ==== ====
 // ====  Vertex Shaders
@@ -63,6 +81,8 @@ struct CubeFaceInstanceInput {
     @location(7) face_to_cube_2: vec4<f32>,
     @location(8) face_to_cube_3: vec4<f32>,
     @location(9) decal_offset: vec2<f32>,
+    @location(10) decal_xform_0: vec3<f32>,
+    @location(11) decal_xform_1: vec3<f32>,
 }
 
 struct CubeFaceVertexInput {
@@ -76,6 +96,8 @@ struct CubeFaceVertexOutput {
     @location(0) @interpolate(perspective, sample) world_position: vec4<f32>,
     @location(1) @interpolate(perspective, sample) world_normal: vec3<f32>,
     @location(2) @interpolate(perspective, sample) decal_coords: vec2<f32>,
+    // where the decal printed on the panel lands, given the wiring
+    @location(3) @interpolate(perspective, sample) print_coords: vec2<f32>,
 }
 
 @vertex
@@ -100,12 +122,18 @@ fn vs_cube_face_main(
     let world_normal = extract3x3(cube.cube_to_world) * cube_normal;
 
     let decal_coords = instance.decal_offset + model.decal_coords;
+    let decal_xy1 = vec3<f32>(model.decal_coords, 1.0);
+    let print_coords = vec2<f32>(
+        dot(instance.decal_xform_0, decal_xy1),
+        dot(instance.decal_xform_1, decal_xy1),
+    );
 
     var out: CubeFaceVertexOutput;
     out.clip_position = clip_pos;
     out.world_position = world_pos;
     out.world_normal = world_normal;
     out.decal_coords = decal_coords;
+    out.print_coords = print_coords;
     return out;
 }
 
@@ -243,19 +271,7 @@ struct Material {
 }
 
 fn material_defaults() -> Material {
-    var out: Material;
-    out.base_color = vec3<f32>(0.82, 0.67, 0.16);
-    out.subsurface = 0.0;
-    out.metallic = 0.0;
-    out.specular = 0.5;
-    out.specular_tint = 0.0;
-    out.roughness = 0.5;
-    out.anisotropic = 0.0;
-    out.sheen = 0.5;
-    out.sheen_tint = 0.5;
-    out.clearcoat = 0.0;
-    out.clearcoat_gloss = 1.0;
-    return out;
+    return tweaks.material;
 }
 
 fn sqr(x: f32) -> f32 {
@@ -560,6 +576,21 @@ fn fetch_shadow4(light_index: u32, homogeneous_coords: vec4<f32>) -> f32 {
     return 0.25 * (s0 + s1 + s2 + s3);
 }
 
+// The shadow with SHADOW_SAMPLES samples.
+fn fetch_shadow_n(light_index: u32, homogeneous_coords: vec4<f32>) -> f32 {
+    switch SHADOW_SAMPLES {
+        case 1u: {
+            return fetch_shadow(light_index, homogeneous_coords);
+        }
+        case 4u: {
+            return fetch_shadow4(light_index, homogeneous_coords);
+        }
+        default: {
+            return fetch_shadow16(light_index, homogeneous_coords);
+        }
+    }
+}
+
 // Nice looking, expensive soft shadow w/ 16 shadow map samples
 fn fetch_shadow16(light_index: u32, homogeneous_coords: vec4<f32>) -> f32 {
 
@@ -632,11 +663,9 @@ const cube_face_base_color: vec4<f32> = vec4<f32>(0.02, 0.02, 0.02, 1.0);
 // 0.06 is more realistic.  0.0 has higher contrast.
 // let led_base_color: vec4<f32> = vec4<f32>(0.06, 0.06, 0.06, 1.0);
 const led_base_color: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 1.0);
-const led_r2: f32 = 0.15;
-const led_brightness: f32 = 2.0;
 
 fn face_color_brdf(
-    tex_index: vec2<i32>,
+    decal_index: vec2<i32>,
     N: vec3<f32>,
     V: vec3<f32>,
     world_pos: vec4<f32>,
@@ -644,7 +673,7 @@ fn face_color_brdf(
     let X = normalize(cross(vec3<f32>(0.0, 1.0, 0.0), N));
     let Y = normalize(cross(N, X));
 
-    let decal_pixel = vec4<f32>(textureLoad(t_decal, tex_index, 0));
+    let decal_pixel = vec4<f32>(textureLoad(t_decal, decal_index, 0));
 
     var material = material_defaults();
     material.base_color = vec3<f32>(0.2);
@@ -678,12 +707,12 @@ fn face_color_brdf(
 }
 
 fn face_color_classic(
-    tex_index: vec2<i32>,
+    decal_index: vec2<i32>,
     N: vec3<f32>,
     V: vec3<f32>,
     world_pos: vec4<f32>,
 ) -> vec4<f32> {
-    let decal_pixel = vec4<f32>(textureLoad(t_decal, tex_index, 0));
+    let decal_pixel = vec4<f32>(textureLoad(t_decal, decal_index, 0));
     var material_color = cube_face_base_color.rgb;
     material_color = max(material_color, decal_pixel.rgb);
     var color = vec3<f32>(0.0);
@@ -727,7 +756,7 @@ fn led_color(
     tex_index: vec2<i32>,
 ) -> vec4<f32> {
     let blinky_color = vec4<f32>(textureLoad(t_blinky, tex_index, 0)) / 255.0;
-    let led_color = max(led_base_color, led_brightness * blinky_color);
+    let led_color = max(led_base_color, tweaks.led_brightness * blinky_color);
     return led_color;
 }
 
@@ -739,27 +768,37 @@ struct CubeFaceFragmentOutput {
 @fragment
 fn fs_cube_face_main(in: CubeFaceVertexOutput) -> CubeFaceFragmentOutput {
     let t_coord = vec2<f32>(in.decal_coords.x, 1.0 - in.decal_coords.y);
-    let pix_coord = t_coord * 64.0;
+    let pix_coord = t_coord * cube.led_count;
     let pix_center = floor(pix_coord) + 0.5;
     let tex_index = vec2<i32>(pix_center);
+    // The decal is drawn at its own resolution, whatever the panel's.
+    let d_coord = vec2<f32>(
+        in.print_coords.x,
+        1.0 - in.print_coords.y,
+    );
+    let decal_size = vec2<f32>(textureDimensions(t_decal));
+    let decal_index = min(
+        vec2<i32>(d_coord * decal_size / vec2<f32>(6.0, 1.0)),
+        vec2<i32>(decal_size) - 1,
+    );
 
     let world_pos = in.world_position;
     let N = normalize(in.world_normal);
     let V = normalize(camera.view_position.xyz - world_pos.xyz);
 
-    let pix_pos = pix_coord - pix_center;
+    let pix_pos = (pix_coord - pix_center) * cube.led_pitch;
     let pix_r2: f32 = pix_pos.x * pix_pos.x + pix_pos.y * pix_pos.y;
     var color: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 1.0);
     var bright_color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    if (pix_r2 < led_r2) {
+    if (pix_r2 < cube.led_r2) {
         color = led_color(tex_index);
-        if (max(max(color.r, color.g), color.b) > 1.0) {
+        if (max(max(color.r, color.g), color.b) > tweaks.bloom_threshold) {
             bright_color = color;
         }
     } else if (USE_BRDF_FLAG) {
-        color = face_color_brdf(tex_index, N, V, world_pos);
+        color = face_color_brdf(decal_index, N, V, world_pos);
     } else {
-        color = face_color_classic(tex_index, N, V, world_pos);
+        color = face_color_classic(decal_index, N, V, world_pos);
     }
     var out: CubeFaceFragmentOutput;
     out.color = color;
@@ -770,9 +809,8 @@ fn fs_cube_face_main(in: CubeFaceVertexOutput) -> CubeFaceFragmentOutput {
 
 // ----  Cube Edge Fragment Shader  - ---- ---- ---- ---- ---- ---- ----
 
-// let cube_edge_material_color = vec4<f32>(0.718, 0.055, 0.0, 1.0);
-// let cube_edge_material_color = vec4<f32>(0.0, 0.99, 1.0, 1.0);
-const cube_edge_material_color = vec4<f32>(0.05, 0.05, 0.05, 1.0);
+// The edge color is tweaks.edge_color, by default (0.05, 0.05, 0.05, 1).
+// (0.718, 0.055, 0.0) and (0.0, 0.99, 1.0) have been tried.
 const cube_edge_material_roughness = 0.1;
 
 fn edge_color_brdf(
@@ -812,7 +850,7 @@ fn edge_color_classic(
     world_pos: vec4<f32>,
 ) -> vec4<f32> {
     var color = vec3<f32>(0.0);
-    var material_color = cube_edge_material_color.rgb;
+    var material_color = tweaks.edge_color.rgb;
 
     // Ambient
     color = color + lights.lights[0].color.rgb * material_color;
@@ -835,7 +873,7 @@ fn edge_color_classic(
 
         color = color + shadow * material_color * (diffuse + specular);
     }
-    return vec4<f32>(color, cube_edge_material_color.a);
+    return vec4<f32>(color, tweaks.edge_color.a);
 }
 
 struct CubeEdgeFragmentOutput {
@@ -971,7 +1009,7 @@ fn floor_color_brdf(
         let light = lights.lights[i];
         let L = normalize(light.direction.xyz);
 
-        let shadow = fetch_shadow16(i, light.proj * world_pos);
+        let shadow = fetch_shadow_n(i, light.proj * world_pos);
 
         let b = max(vec3<f32>(0.0), disney_brdf(material, L, V, N, X, Y));
         color = color + shadow * dot(L, N) * light.color.rgb * b;
@@ -1056,7 +1094,7 @@ fn floor_color_classic(
         let L = normalize(light.direction.xyz);
         let H = normalize(V + L);
 
-        let shadow = fetch_shadow16(i, light.proj * world_pos);
+        let shadow = fetch_shadow_n(i, light.proj * world_pos);
 
         let diffuse = lambert_diffuse(light.color.rgb, N, L);
         // let diffuse = burley_diffuse(floor_material_roughness, N, L, V, H);
@@ -1116,7 +1154,8 @@ fn fs_prefloor_main(in: FloorVertexOutput) -> @location(0) vec4<f32> {
 @fragment
 fn fs_floor_main(in: FloorVertexOutput) -> FloorFragmentOutput {
     let t_coord = vec2<f32>(in.decal_coords.x, 1.0 - in.decal_coords.y);
-    let image_coords = in.clip_position.xy * camera.framebuffer_to_texture;
+    let image_coords = (in.clip_position.xy + camera.framebuffer_offset)
+        * camera.framebuffer_to_texture;
 
     let N = normalize(in.world_normal);
     let V = normalize(camera.view_position.xyz - in.world_position.xyz);
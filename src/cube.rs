@@ -1,9 +1,11 @@
 use wgpu::util::DeviceExt;
 
 use crate::cube_model;
+use crate::panel::panel;
 use crate::prelude::*;
 use crate::texture;
 use crate::traits::Renderable;
+use crate::wiring::wiring;
 
 pub const CUBE_BOUNDS_WORLD: cgmath::Ortho<f32> = cgmath::Ortho {
     left: -120.0,
@@ -14,12 +16,18 @@ pub const CUBE_BOUNDS_WORLD: cgmath::Ortho<f32> = cgmath::Ortho {
     near: 120.0,
 };
 
+// LED dot radius squared, as a fraction of the LED pitch squared
+const LED_R2: f32 = 0.15;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct CubeUniformRaw {
     cube_to_world: [[f32; 4]; 4],
     decal_visibility: f32,
-    _padding: [u32; 3],
+    led_r2: f32,
+    led_count: [f32; 2],
+    led_pitch: [f32; 2],
+    _padding: [u32; 2],
 }
 
 #[repr(C)]
@@ -27,15 +35,18 @@ struct CubeUniformRaw {
 pub struct FaceStaticInstanceRaw {
     face_to_cube: [[f32; 4]; 4],
     decal_offset: [f32; 2],
+    decal_xform: [[f32; 3]; 2],
 }
 
 impl FaceStaticInstanceRaw {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
         5 => Float32x4,         // face_to_cube: mat4<f32>
         6 => Float32x4,
         7 => Float32x4,
         8 => Float32x4,
-        9 => Float32x2          // decal_offset: vec2<f32>
+        9 => Float32x2,         // decal_offset: vec2<f32>
+        10 => Float32x3,        // decal_xform: two rows of vec3<f32>
+        11 => Float32x3
     ];
 
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -117,10 +128,9 @@ impl Cube {
                 .enumerate()
                 .map(|(i, xform)| FaceStaticInstanceRaw {
                     face_to_cube: (*xform as Mat4).into(),
-                    decal_offset: [
-                        (face_instance_count - i as u32 - 1) as f32,
-                        0.0,
-                    ],
+                    decal_offset: [wiring().slot(i) as f32, 0.0],
+                    // The decal is printed on the panel.
+                    decal_xform: wiring().decal_xform(i),
                 })
                 .collect::<Vec<FaceStaticInstanceRaw>>();
             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -224,11 +234,19 @@ impl Renderable<CubeFaceAttributes, CubeFacePreparedData> for Cube {
         } else {
             (1.0 - frac) * (1.0 - frac)
         };
+        // Each LED's dot covers the same share of the smaller pitch
+        // whatever the panel's resolution.
+        let panel = panel();
+        let (pitch_x, pitch_y) = panel.pitch_mm();
+        let min_pitch = pitch_x.min(pitch_y);
         CubeFacePreparedData {
             cube_uniform: CubeUniformRaw {
                 cube_to_world: self.cube_to_world.into(),
                 decal_visibility: brightness,
-                _padding: [0, 0, 0],
+                led_r2: LED_R2 * min_pitch * min_pitch,
+                led_count: [panel.width as f32, panel.height as f32],
+                led_pitch: [pitch_x, pitch_y],
+                _padding: [0, 0],
             },
         }
     }
@@ -6,8 +6,8 @@ use stringreader::StringReader;
 use crate::prelude::*;
 
 const FACE_COUNT: u32 = 6;
-const FACE_LENGTH_MM: f32 = 128.0;
-const FACE_DISPLACEMENT_MM: f32 = 3.6;
+pub const FACE_LENGTH_MM: f32 = 128.0;
+pub const FACE_DISPLACEMENT_MM: f32 = 3.6;
 
 #[derive(Debug)]
 pub struct CubeModel {
@@ -49,8 +49,8 @@ impl FaceVertex {
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct EdgeVertex {
-    position: [f32; 3],
-    normal: [f32; 3],
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
 }
 
 impl EdgeVertex {
@@ -130,6 +130,19 @@ impl CubeModel {
         out.face_indices.push(2);
         out.face_indices.push(3);
 
+        out.face_xforms = Self::make_face_xforms();
+
+        out.edge_vertices = edge_vertices;
+        out.edge_indices = models[0].mesh.indices.clone();
+
+        out
+    }
+
+    // Transforms from each face's coordinates to the cube's.  A face
+    // lies in its z = 0 plane, centered at the origin, facing +z.
+    pub fn make_face_xforms() -> Vec<Mat4> {
+        const HFL: f32 = FACE_LENGTH_MM / 2.0; // half face length
+        let mut xforms = Vec::new();
         let z = Vec3::unit_z();
         let tran = Mat4::from_translation((HFL + FACE_DISPLACEMENT_MM) * z);
 
@@ -137,42 +150,38 @@ impl CubeModel {
             // 1: left
             let rot1 = Mat4::from_angle_z(Deg(180.0));
             let rot2 = Mat4::from_angle_y(Deg(-90.0));
-            out.face_xforms.push(rot2 * rot1 * tran);
+            xforms.push(rot2 * rot1 * tran);
         }
         {
             // 2: front
             let rot1 = Mat4::from_angle_z(Deg(180.0));
-            out.face_xforms.push(rot1 * tran);
+            xforms.push(rot1 * tran);
         }
         {
             // 3: right
             let rot1 = Mat4::from_angle_z(Deg(180.0));
             let rot2 = Mat4::from_angle_y(Deg(90.0));
-            out.face_xforms.push(rot2 * rot1 * tran);
+            xforms.push(rot2 * rot1 * tran);
         }
         {
             // 4: bottom
             let rot1 = Mat4::from_angle_z(Deg(90.0));
             let rot2 = Mat4::from_angle_x(Deg(90.0));
-            out.face_xforms.push(rot2 * rot1 * tran);
+            xforms.push(rot2 * rot1 * tran);
         }
         {
             // 5: back
             let rot1 = Mat4::from_angle_z(Deg(90.0));
             let rot2 = Mat4::from_angle_x(Deg(180.0));
-            out.face_xforms.push(rot2 * rot1 * tran);
+            xforms.push(rot2 * rot1 * tran);
         }
         {
             // 6: top
             let rot1 = Mat4::from_angle_z(Deg(90.0));
             let rot2 = Mat4::from_angle_x(Deg(-90.0));
-            out.face_xforms.push(rot2 * rot1 * tran);
+            xforms.push(rot2 * rot1 * tran);
         }
-
-        out.edge_vertices = edge_vertices;
-        out.edge_indices = models[0].mesh.indices.clone();
-
-        out
+        xforms
     }
 
     pub fn corners(&self) -> Vec<Point3> {
@@ -0,0 +1,479 @@
+// Shows the buffers the renderer draws on the way to the finished
+// frame: the shadow maps, the depth buffer, the prefloor's glow, the
+// bright color from the forward pass and the blur's ping and pong.
+// Drawn over the finished frame, either one buffer filling the view or
+// all of them in a grid down its right side.
+//
+// V steps through the views and X through the channels.  Each cell's
+// values are stretched to fill 0 to 1, so dim glow and the narrow
+// range of depth that the scene covers show up.  See debug_view.wgsl.
+
+use std::fmt;
+
+use crate::lights;
+
+const MAX_CELLS: usize = 16; // debug_view.wgsl's
+const CELLS_PER_COLUMN: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Buffer {
+    ShadowMap(u32), // of the light with this index
+    Depth,
+    Glow,
+    Bright,
+    Ping,
+    Pong,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Off,
+    Grid,
+    Full(Buffer),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    #[default]
+    Rgb,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+// What the buffers are this frame.
+pub struct Sources<'a> {
+    pub shadow_maps: wgpu::BindingResource<'a>,
+    pub shadow_layers: Vec<u32>, // the lights that have shadows
+    pub depth: &'a wgpu::TextureView,
+    pub depth_empty: f32, // what the depth buffer is cleared to
+    pub glow: wgpu::BindingResource<'a>,
+    pub ping: &'a wgpu::TextureView,
+    pub pong: &'a wgpu::TextureView,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CellRaw {
+    rect: [f32; 4],
+    source: u32,
+    layer: u32,
+    channel: u32,
+    empty: f32,
+}
+
+pub struct DebugView {
+    pub mode: Mode,
+    pub channel: Channel,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    // The bright color, copied before the blur draws over it.
+    bright: Option<wgpu::Texture>,
+}
+
+// The buffers there are to show.
+fn buffers(shadow_layers: &[u32]) -> Vec<Buffer> {
+    let shadow_maps = shadow_layers.iter().map(|&i| Buffer::ShadowMap(i));
+    shadow_maps
+        .chain([
+            Buffer::Depth,
+            Buffer::Glow,
+            Buffer::Bright,
+            Buffer::Ping,
+            Buffer::Pong,
+        ])
+        .collect()
+}
+
+impl Mode {
+    // Off, the grid, then each buffer in turn.
+    pub fn next(self, shadow_layers: &[u32]) -> Self {
+        let buffers = buffers(shadow_layers);
+        match self {
+            Mode::Off => Mode::Grid,
+            Mode::Grid => Mode::Full(buffers[0]),
+            Mode::Full(buffer) => {
+                match buffers.iter().position(|&b| b == buffer) {
+                    Some(i) if i + 1 < buffers.len() => {
+                        Mode::Full(buffers[i + 1])
+                    }
+                    _ => Mode::Off,
+                }
+            }
+        }
+    }
+
+    // The buffers shown, and where, as left, bottom, right and top in
+    // clip space.
+    fn cells(self, shadow_layers: &[u32]) -> Vec<(Buffer, [f32; 4])> {
+        match self {
+            Mode::Off => Vec::new(),
+            Mode::Full(buffer) => vec![(buffer, [-1.0, -1.0, 1.0, 1.0])],
+            Mode::Grid => {
+                let size = 2.0 / CELLS_PER_COLUMN as f32;
+                buffers(shadow_layers)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, buffer)| {
+                        let column = (i / CELLS_PER_COLUMN) as f32;
+                        let row = (i % CELLS_PER_COLUMN) as f32;
+                        let right = 1.0 - column * size;
+                        let top = 1.0 - row * size;
+                        (buffer, [right - size, top - size, right, top])
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn shows(self, shown: Buffer) -> bool {
+        match self {
+            Mode::Off => false,
+            Mode::Grid => true,
+            Mode::Full(buffer) => buffer == shown,
+        }
+    }
+
+    pub fn shows_depth(self) -> bool {
+        self.shows(Buffer::Depth)
+    }
+}
+
+impl Channel {
+    pub fn next(self) -> Self {
+        match self {
+            Channel::Rgb => Channel::Red,
+            Channel::Red => Channel::Green,
+            Channel::Green => Channel::Blue,
+            Channel::Blue => Channel::Alpha,
+            Channel::Alpha => Channel::Rgb,
+        }
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Buffer::ShadowMap(i) => write!(f, "shadow map {}", i),
+            Buffer::Depth => write!(f, "depth"),
+            Buffer::Glow => write!(f, "glow"),
+            Buffer::Bright => write!(f, "bright color"),
+            Buffer::Ping => write!(f, "ping"),
+            Buffer::Pong => write!(f, "pong"),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Channel::Rgb => "rgb",
+            Channel::Red => "red",
+            Channel::Green => "green",
+            Channel::Blue => "blue",
+            Channel::Alpha => "alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Off => write!(f, "off"),
+            Mode::Grid => write!(f, "grid"),
+            Mode::Full(buffer) => write!(f, "{}", buffer),
+        }
+    }
+}
+
+impl DebugView {
+    // `sample_count` is the depth buffer's.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let multisampled = sample_count > 1;
+        let depth_type = match multisampled {
+            true => "texture_multisampled_2d<f32>",
+            false => "texture_2d<f32>",
+        };
+        let source = format!(
+            "alias DepthTexture = {};\n{}",
+            depth_type,
+            include_str!("debug_view.wgsl")
+        );
+        let shader =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("debug_view.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_view_uniform_buffer"),
+            size: (MAX_CELLS * std::mem::size_of::<CellRaw>()) as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let texture_entry =
+            |binding, sample_type, view_dimension, multisampled| {
+                wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type,
+                        view_dimension,
+                        multisampled,
+                    },
+                    count: None,
+                }
+            };
+        let float = wgpu::TextureSampleType::Float { filterable: false };
+        let d2 = wgpu::TextureViewDimension::D2;
+        let d2_array = wgpu::TextureViewDimension::D2Array;
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("debug_view_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_entry(1, float, d2_array, false),
+                    texture_entry(2, float, d2, multisampled),
+                    texture_entry(3, float, d2, false),
+                    texture_entry(4, float, d2, false),
+                    texture_entry(5, float, d2, false),
+                    texture_entry(6, float, d2, false),
+                ],
+            });
+        let layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("debug_view_pipeline_layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("debug_view_pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_debug_view_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_debug_view_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            });
+        Self {
+            mode: Mode::Off,
+            channel: Channel::Rgb,
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            bright: None,
+        }
+    }
+
+    // Keep the bright color of the frame being encoded.  Call between
+    // the forward pass and the blur, whenever the view is on.
+    pub fn copy_bright(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bright: &wgpu::Texture,
+    ) {
+        let copy = match &self.bright {
+            Some(copy) if copy.size() == bright.size() => copy,
+            _ => self.bright.insert(device.create_texture(
+                &wgpu::TextureDescriptor {
+                    label: Some("debug_view_bright_texture"),
+                    size: bright.size(),
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: bright.format(),
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                },
+            )),
+        };
+        encoder.copy_texture_to_texture(
+            bright.as_image_copy(),
+            copy.as_image_copy(),
+            bright.size(),
+        );
+    }
+
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        sources: Sources,
+    ) {
+        let Some(bright) = &self.bright else {
+            return;
+        };
+        let cells = self.mode.cells(&sources.shadow_layers);
+        if cells.is_empty() {
+            return;
+        }
+        let mut raw: [CellRaw; MAX_CELLS] = bytemuck::Zeroable::zeroed();
+        for ((buffer, rect), raw) in cells.iter().zip(&mut raw) {
+            let (source, layer, empty) = match *buffer {
+                Buffer::ShadowMap(layer) => (0, layer, 0.0),
+                Buffer::Depth => (1, 0, sources.depth_empty),
+                Buffer::Glow => (2, 0, 0.0),
+                Buffer::Bright => (3, 0, 0.0),
+                Buffer::Ping => (4, 0, 0.0),
+                Buffer::Pong => (5, 0, 0.0),
+            };
+            *raw = CellRaw {
+                rect: *rect,
+                source,
+                layer,
+                channel: self.channel as u32,
+                empty,
+            };
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&raw));
+
+        // The buffers are remade when the window is resized, so bind
+        // them afresh.
+        let bright = bright.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug_view_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: sources.shadow_maps,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(sources.depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: sources.glow,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&bright),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(sources.ping),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(sources.pong),
+                },
+            ],
+        });
+
+        let mut render_pass =
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("debug_view_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..4, 0..cells.len() as u32);
+    }
+}
+
+// The lights whose shadow maps there are to show.
+pub fn shadow_layers(lights: &lights::Lights) -> Vec<u32> {
+    (0..lights::MAX_LIGHTS)
+        .filter(|&i| lights.light_has_shadow(i))
+        .map(|i| i as u32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_through_every_buffer() {
+        let layers = [1, 2];
+        let mut mode = Mode::Off;
+        let mut seen = Vec::new();
+        loop {
+            mode = mode.next(&layers);
+            if mode == Mode::Off {
+                break;
+            }
+            seen.push(mode.to_string());
+        }
+        assert_eq!(
+            seen,
+            [
+                "grid",
+                "shadow map 1",
+                "shadow map 2",
+                "depth",
+                "glow",
+                "bright color",
+                "ping",
+                "pong"
+            ]
+        );
+        // A light that's lost its shadow ends the cycle.
+        assert_eq!(Mode::Full(Buffer::ShadowMap(2)).next(&[1]), Mode::Off);
+    }
+
+    #[test]
+    fn grid_fills_columns_from_the_right() {
+        let cells = Mode::Grid.cells(&[0]);
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells[0], (Buffer::ShadowMap(0), [0.5, 0.5, 1.0, 1.0]));
+        assert_eq!(cells[3], (Buffer::Bright, [0.5, -1.0, 1.0, -0.5]));
+        assert_eq!(cells[4], (Buffer::Ping, [0.0, 0.5, 0.5, 1.0]));
+        assert!(Mode::Grid.cells(&[0, 1, 2, 3, 4, 5, 6, 7]).len() <= MAX_CELLS);
+    }
+}
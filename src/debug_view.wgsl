@@ -0,0 +1,139 @@
+// Shows the renderer's intermediate buffers.  See debug_view.rs, which
+// puts an alias for `DepthTexture` ahead of this: multisampled or not,
+// as the depth buffer is.  Depth is bound as unfilterable floats, not
+// depth textures, which GLSL can't load texels from.
+//
+// Each instance is a cell showing one buffer.  Its vertices find the
+// range of what it shows from a grid of samples, leaving out depth
+// that was never drawn, and its pixels are remapped from that range to
+// 0 to 1.  Colors' ranges start at 0.
+
+const MAX_CELLS: u32 = 16u;
+const GRID: u32 = 32u; // samples each way when finding a range
+
+// Sources.
+const SHADOW_MAPS: u32 = 0u;
+const DEPTH: u32 = 1u;
+const GLOW: u32 = 2u;
+const BRIGHT: u32 = 3u;
+const PING: u32 = 4u;
+
+// Channels; the others are red, green, blue and alpha.
+const RGB: u32 = 0u;
+
+struct Cell {
+    rect: vec4<f32>, // left, bottom, right, top in clip space
+    source: u32,
+    layer: u32,      // of the shadow maps
+    channel: u32,
+    empty: f32,      // what depth is where nothing was drawn
+};
+
+struct Cells {
+    cells: array<Cell, MAX_CELLS>,
+};
+
+@group(0) @binding(0) var<uniform> debug: Cells;
+@group(0) @binding(1) var shadow_maps: texture_2d_array<f32>;
+@group(0) @binding(2) var depth: DepthTexture;
+@group(0) @binding(3) var glow: texture_2d<f32>;
+@group(0) @binding(4) var bright: texture_2d<f32>;
+@group(0) @binding(5) var ping: texture_2d<f32>;
+@group(0) @binding(6) var pong: texture_2d<f32>;
+
+fn is_depth(cell: Cell) -> bool {
+    return cell.source == SHADOW_MAPS || cell.source == DEPTH;
+}
+
+fn size_of(cell: Cell) -> vec2<u32> {
+    switch cell.source {
+        case SHADOW_MAPS: { return textureDimensions(shadow_maps); }
+        case DEPTH: { return textureDimensions(depth); }
+        case GLOW: { return textureDimensions(glow); }
+        case BRIGHT: { return textureDimensions(bright); }
+        case PING: { return textureDimensions(ping); }
+        default: { return textureDimensions(pong); }
+    }
+}
+
+// The texel at `coords`, with depth in every channel.
+fn load(cell: Cell, coords: vec2<u32>) -> vec4<f32> {
+    switch cell.source {
+        case SHADOW_MAPS: {
+            return textureLoad(shadow_maps, coords, cell.layer, 0).rrrr;
+        }
+        case DEPTH: { return textureLoad(depth, coords, 0).rrrr; }
+        case GLOW: { return textureLoad(glow, coords, 0); }
+        case BRIGHT: { return textureLoad(bright, coords, 0); }
+        case PING: { return textureLoad(ping, coords, 0); }
+        default: { return textureLoad(pong, coords, 0); }
+    }
+}
+
+// What's shown of a texel: the color, or one channel as grey.
+fn pick(cell: Cell, texel: vec4<f32>) -> vec3<f32> {
+    if is_depth(cell) || cell.channel == RGB {
+        return texel.rgb;
+    }
+    return vec3<f32>(texel[cell.channel - 1u]);
+}
+
+fn range_of(cell: Cell) -> vec2<f32> {
+    let size = size_of(cell);
+    var lo = 3.4e38;
+    var hi = 0.0;
+    for (var y = 0u; y < GRID; y++) {
+        for (var x = 0u; x < GRID; x++) {
+            let coords = (vec2<u32>(x, y) * size + size / 2u) / GRID;
+            let texel = load(cell, coords);
+            if is_depth(cell) && texel.r == cell.empty {
+                continue;
+            }
+            let value = pick(cell, texel);
+            lo = min(lo, min(value.r, min(value.g, value.b)));
+            hi = max(hi, max(value.r, max(value.g, value.b)));
+        }
+    }
+    if !is_depth(cell) {
+        lo = 0.0;
+    }
+    return vec2<f32>(lo, hi);
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) cell: u32,
+    @location(2) @interpolate(flat) range: vec2<f32>,
+};
+
+@vertex
+fn vs_debug_view_main(
+    @builtin(vertex_index) vertex: u32,
+    @builtin(instance_index) instance: u32,
+) -> VertexOutput {
+    let cell = debug.cells[instance];
+    let corner = vec2<f32>(f32(vertex & 1u), f32(vertex >> 1u));
+    var out: VertexOutput;
+    let xy = mix(cell.rect.xy, cell.rect.zw, corner);
+    out.position = vec4<f32>(xy, 0.0, 1.0);
+    // Textures count rows from the top.
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.cell = instance;
+    out.range = range_of(cell);
+    return out;
+}
+
+@fragment
+fn fs_debug_view_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = debug.cells[in.cell];
+    let size = size_of(cell);
+    let coords = min(vec2<u32>(in.uv * vec2<f32>(size)), size - 1u);
+    let texel = load(cell, coords);
+    if is_depth(cell) && texel.r == cell.empty {
+        return vec4<f32>(0.0, 0.0, 0.15, 1.0);
+    }
+    let span = max(in.range.y - in.range.x, 1e-6);
+    let value = (pick(cell, texel) - in.range.x) / span;
+    return vec4<f32>(clamp(value, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,547 @@
+// Art-Net and sACN (E1.31) receivers.
+//
+// Both protocols carry DMX universes of up to 512 channels over UDP.
+// Each universe holds 170 RGB pixels, so with 64x64 panels the cube's
+// 24,576 pixels fill 145 consecutive universes starting at the
+// configured first universe.  Pixels are numbered like an OPC channel
+// 0 message: panel 0 through 5 in chain order, each in its own scan
+// order (see wiring.rs).
+//
+// Senders may hold output until a sync packet (ArtSync, or an E1.31
+// sync packet for data packets with a nonzero sync address).  Held
+// universes are written to a pending frame that is published on sync.
+// When no data arrives for `timeout`, the "dmx" pattern falls back to
+// the built-in stripes.
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::test_pattern::{self, black_frame, PixelArray};
+use crate::wiring::wiring;
+
+pub const ARTNET_PORT: u16 = 6454;
+pub const SACN_PORT: u16 = 5568;
+pub const PATTERN_NAME: &str = "dmx";
+
+const PIXELS_PER_UNIVERSE: usize = 170;
+
+// Art-Net receivers leave synchronous mode when ArtSync stops.
+const ARTNET_SYNC_TIMEOUT: Duration = Duration::from_secs(4);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    ArtNet,
+    Sacn,
+}
+
+impl Protocol {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Protocol::ArtNet => ARTNET_PORT,
+            Protocol::Sacn => SACN_PORT,
+        }
+    }
+
+    // Art-Net numbers universes from 0, sACN from 1.
+    pub fn default_first_universe(self) -> u16 {
+        match self {
+            Protocol::ArtNet => 0,
+            Protocol::Sacn => 1,
+        }
+    }
+}
+
+// The order in which a pixel's three channels arrive, e.g. GRB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelOrder([usize; 3]);
+
+impl Default for PixelOrder {
+    fn default() -> Self {
+        Self([0, 1, 2])
+    }
+}
+
+impl PixelOrder {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let order: Vec<usize> = spec
+            .to_ascii_lowercase()
+            .chars()
+            .map(|c| "rgb".find(c).unwrap_or(usize::MAX))
+            .collect();
+        let mut sorted = order.clone();
+        sorted.sort();
+        if sorted != [0, 1, 2] {
+            bail!("pixel order \"{}\" is not a permutation of rgb", spec);
+        }
+        Ok(Self([order[0], order[1], order[2]]))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    pub protocol: Protocol,
+    pub address: String,
+    pub first_universe: u16,
+    pub pixel_order: PixelOrder,
+    pub timeout: Duration,
+}
+
+impl Configuration {
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            address: format!("0.0.0.0:{}", protocol.default_port()),
+            first_universe: protocol.default_first_universe(),
+            pixel_order: PixelOrder::default(),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    // "artnet" or "sacn", optionally followed by ":address:port".
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (protocol, address) = match spec.split_once(':') {
+            Some((protocol, address)) => (protocol, Some(address)),
+            None => (spec, None),
+        };
+        let protocol = match protocol.to_ascii_lowercase().as_str() {
+            "artnet" => Protocol::ArtNet,
+            "sacn" => Protocol::Sacn,
+            _ => bail!("unknown DMX protocol \"{}\"", protocol),
+        };
+        let mut config = Self::new(protocol);
+        if let Some(address) = address {
+            config.address = address.to_string();
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Packet<'a> {
+    Dmx {
+        universe: u16,
+        data: &'a [u8], // starting at DMX channel 1
+        synced: bool,   // hold until the next sync packet
+    },
+    Sync,
+}
+
+// Returns `None` for packets that don't carry pixel data.
+fn parse_artnet(buf: &[u8]) -> Option<Packet<'_>> {
+    const OP_DMX: u16 = 0x5000;
+    const OP_SYNC: u16 = 0x5200;
+    if buf.len() < 12 || &buf[..8] != b"Art-Net\0" {
+        return None;
+    }
+    match u16::from_le_bytes([buf[8], buf[9]]) {
+        OP_SYNC => Some(Packet::Sync),
+        OP_DMX if buf.len() >= 18 => {
+            let universe = u16::from_le_bytes([buf[14], buf[15]]) & 0x7fff;
+            let length = u16::from_be_bytes([buf[16], buf[17]]) as usize;
+            let data = &buf[18..buf.len().min(18 + length)];
+            Some(Packet::Dmx {
+                universe,
+                data,
+                synced: false,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_sacn(buf: &[u8]) -> Option<Packet<'_>> {
+    const ACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+    const VECTOR_ROOT_DATA: u32 = 0x04;
+    const VECTOR_ROOT_EXTENDED: u32 = 0x08;
+    const VECTOR_EXTENDED_SYNC: u32 = 0x01;
+    let u32_at = |i: usize| {
+        u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]])
+    };
+    let u16_at = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+    if buf.len() < 44 || &buf[4..16] != ACN_ID {
+        return None;
+    }
+    match u32_at(18) {
+        VECTOR_ROOT_EXTENDED if u32_at(40) == VECTOR_EXTENDED_SYNC => {
+            Some(Packet::Sync)
+        }
+        // Only start code 0 carries levels.
+        VECTOR_ROOT_DATA if buf.len() >= 126 && buf[125] == 0 => {
+            let count = u16_at(123) as usize;
+            let data = &buf[126..buf.len().min(125 + count)];
+            Some(Packet::Dmx {
+                universe: u16_at(113),
+                data,
+                synced: u16_at(109) != 0,
+            })
+        }
+        _ => None,
+    }
+}
+
+struct Frames {
+    front: Vec<u8>,   // what the "dmx" pattern shows
+    pending: Vec<u8>, // held until the next sync
+    dirty: bool,
+    received: usize, // packets
+    last_data: Option<Instant>,
+    last_artnet_sync: Option<Instant>,
+}
+
+impl Frames {
+    fn receive(&mut self, packet: Packet, config: &Configuration) {
+        let now = Instant::now();
+        self.received += 1;
+        match packet {
+            Packet::Sync => {
+                if config.protocol == Protocol::ArtNet {
+                    self.last_artnet_sync = Some(now);
+                }
+                self.front.copy_from_slice(&self.pending[..]);
+                self.dirty = true;
+            }
+            Packet::Dmx {
+                universe,
+                data,
+                synced,
+            } => {
+                self.last_data = Some(now);
+                let Some(offset) = universe.checked_sub(config.first_universe)
+                else {
+                    return;
+                };
+                let first_pixel = offset as usize * PIXELS_PER_UNIVERSE;
+                if first_pixel >= panel().led_count() {
+                    return;
+                }
+                let synced = synced
+                    || self.last_artnet_sync.is_some_and(|t| {
+                        now.duration_since(t) < ARTNET_SYNC_TIMEOUT
+                    });
+                let order = config.pixel_order;
+                write_pixels(&mut self.pending, first_pixel, data, order);
+                if !synced {
+                    write_pixels(&mut self.front, first_pixel, data, order);
+                    self.dirty = true;
+                }
+            }
+        }
+    }
+}
+
+fn write_pixels(
+    frame: &mut PixelArray,
+    first_pixel: usize,
+    data: &[u8],
+    order: PixelOrder,
+) {
+    let panel = panel();
+    let pixels = data.chunks_exact(3).take(PIXELS_PER_UNIVERSE);
+    for (p, channels) in (first_pixel..panel.led_count()).zip(pixels) {
+        let (face, row, col) = wiring().locate(p);
+        let i = panel.index(face, row, col);
+        for (k, &value) in channels.iter().enumerate() {
+            frame[i + order.0[k]] = value;
+        }
+        frame[i + 3] = 255;
+    }
+}
+
+pub struct DmxReceiver {
+    address: SocketAddr,
+    timeout: Duration,
+    frames: Arc<Mutex<Frames>>,
+}
+
+impl DmxReceiver {
+    // Bind a UDP socket and receive on a thread that runs until the
+    // program exits.
+    pub fn start(config: Configuration) -> Result<Self> {
+        let socket = UdpSocket::bind(&config.address)
+            .with_context(|| format!("can't listen on {}", config.address))?;
+        let address = socket.local_addr()?;
+        if config.protocol == Protocol::Sacn && address.ip().is_unspecified() {
+            join_sacn_groups(&socket, &config);
+        }
+        let frames = Arc::new(Mutex::new(Frames {
+            front: black_frame(),
+            pending: black_frame(),
+            dirty: false,
+            received: 0,
+            last_data: None,
+            last_artnet_sync: None,
+        }));
+        let thread_frames = frames.clone();
+        let timeout = config.timeout;
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 1500];
+            loop {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e) => {
+                        eprintln!("dmx: {}", e);
+                        return;
+                    }
+                };
+                let packet = match config.protocol {
+                    Protocol::ArtNet => parse_artnet(&buf[..len]),
+                    Protocol::Sacn => parse_sacn(&buf[..len]),
+                };
+                if let Some(packet) = packet {
+                    thread_frames.lock().unwrap().receive(packet, &config);
+                }
+            }
+        });
+        Ok(Self {
+            address,
+            timeout,
+            frames,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Register a "dmx" pattern that shows the received universes.
+    pub fn register(&self, registry: &mut PatternRegistry) {
+        let frames = self.frames.clone();
+        let timeout = self.timeout;
+        registry.register(PATTERN_NAME, move || {
+            Box::new(DmxSource {
+                frames: frames.clone(),
+                timeout,
+                fallback: Box::new(test_pattern::Stripes::new()),
+                data: black_frame(),
+                live: false,
+            })
+        });
+    }
+}
+
+// sACN is usually multicast to 239.255.<universe>.  Hosts limit the
+// number of groups a socket may join; unicast still works past that.
+fn join_sacn_groups(socket: &UdpSocket, config: &Configuration) {
+    let pixels = panel().led_count();
+    let universes = pixels.div_ceil(PIXELS_PER_UNIVERSE) as u16;
+    for universe in config.first_universe..config.first_universe + universes {
+        let [hi, lo] = universe.to_be_bytes();
+        let group = Ipv4Addr::new(239, 255, hi, lo);
+        if let Err(e) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+        {
+            eprintln!(
+                "dmx: can't join multicast group for universe {}; \
+                 later universes must be unicast: {}",
+                universe, e
+            );
+            return;
+        }
+    }
+}
+
+pub struct DmxSource {
+    frames: Arc<Mutex<Frames>>,
+    timeout: Duration,
+    fallback: Box<dyn PatternSource>,
+    data: Vec<u8>,
+    live: bool,
+}
+
+impl PatternSource for DmxSource {
+    fn name(&self) -> &str {
+        PATTERN_NAME
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let mut frames = self.frames.lock().unwrap();
+        self.live =
+            frames.last_data.is_some_and(|t| t.elapsed() < self.timeout);
+        if !self.live {
+            drop(frames);
+            return self.fallback.next_frame(info);
+        }
+        if frames.dirty {
+            self.data.copy_from_slice(&frames.front[..]);
+            frames.dirty = false;
+        }
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        match self.live {
+            true => &self.data,
+            false => self.fallback.current_frame(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    fn pixel(
+        frame: &PixelArray,
+        face: usize,
+        row: usize,
+        col: usize,
+    ) -> [u8; 4] {
+        let i = panel().index(face, row, col);
+        frame[i..i + 4].try_into().unwrap()
+    }
+
+    fn artnet_dmx(universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = b"Art-Net\0".to_vec();
+        packet.extend_from_slice(&0x5000u16.to_le_bytes());
+        packet.extend_from_slice(&[0, 14, 0, 0]);
+        packet.extend_from_slice(&universe.to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn artnet_sync() -> Vec<u8> {
+        let mut packet = b"Art-Net\0".to_vec();
+        packet.extend_from_slice(&0x5200u16.to_le_bytes());
+        packet.extend_from_slice(&[0, 14, 0, 0]);
+        packet
+    }
+
+    fn sacn_dmx(universe: u16, sync_address: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 126];
+        packet[1] = 0x10;
+        packet[4..16].copy_from_slice(b"ASC-E1.17\0\0\0");
+        packet[18..22].copy_from_slice(&4u32.to_be_bytes());
+        packet[40..44].copy_from_slice(&2u32.to_be_bytes());
+        packet[109..111].copy_from_slice(&sync_address.to_be_bytes());
+        packet[113..115].copy_from_slice(&universe.to_be_bytes());
+        let count = data.len() as u16 + 1;
+        packet[123..125].copy_from_slice(&count.to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn sacn_sync() -> Vec<u8> {
+        let mut packet = vec![0u8; 49];
+        packet[4..16].copy_from_slice(b"ASC-E1.17\0\0\0");
+        packet[18..22].copy_from_slice(&8u32.to_be_bytes());
+        packet[40..44].copy_from_slice(&1u32.to_be_bytes());
+        packet
+    }
+
+    fn receiver(mut config: Configuration) -> (DmxReceiver, UdpSocket) {
+        config.address = "127.0.0.1:0".to_string();
+        let receiver = DmxReceiver::start(config).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(receiver.address()).unwrap();
+        (receiver, sender)
+    }
+
+    // Send packets and wait until they have been received.
+    fn send(receiver: &DmxReceiver, sender: &UdpSocket, packets: &[Vec<u8>]) {
+        let expected = receiver.frames.lock().unwrap().received + packets.len();
+        for packet in packets {
+            sender.send(packet).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while receiver.frames.lock().unwrap().received < expected {
+            assert!(Instant::now() < deadline, "packets were lost");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn dmx_source(receiver: &DmxReceiver) -> Box<dyn PatternSource> {
+        let mut registry = PatternRegistry::new();
+        receiver.register(&mut registry);
+        registry.create(registry.index_of(PATTERN_NAME).unwrap())
+    }
+
+    fn next(source: &mut Box<dyn PatternSource>) -> Vec<u8> {
+        let info = FrameInfo {
+            time: Duration::ZERO,
+            frame_number: 0,
+            down: -Vec3::unit_y(),
+        };
+        source.next_frame(&info).to_vec()
+    }
+
+    #[test]
+    fn artnet_universes_map_to_pixels() {
+        let mut config = Configuration::new(Protocol::ArtNet);
+        config.first_universe = 3;
+        config.pixel_order = PixelOrder::parse("grb").unwrap();
+        let (receiver, sender) = receiver(config);
+        let mut source = dmx_source(&receiver);
+        // Universe 4 starts at pixel 170: face 0, row 2, column 42.
+        send(&receiver, &sender, &[artnet_dmx(4, &[1, 2, 3, 4, 5, 6])]);
+        let frame = next(&mut source);
+        assert_eq!(pixel(&frame, 0, 2, 42), [2, 1, 3, 255]);
+        assert_eq!(pixel(&frame, 0, 2, 43), [5, 4, 6, 255]);
+        assert_eq!(pixel(&frame, 0, 0, 0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn artnet_sync_holds_data() {
+        let (receiver, sender) = receiver(Configuration::new(Protocol::ArtNet));
+        let mut source = dmx_source(&receiver);
+        send(
+            &receiver,
+            &sender,
+            &[artnet_sync(), artnet_dmx(0, &[9, 9, 9])],
+        );
+        assert_eq!(pixel(&next(&mut source), 0, 0, 0), [0, 0, 0, 255]);
+        send(&receiver, &sender, &[artnet_sync()]);
+        assert_eq!(pixel(&next(&mut source), 0, 0, 0), [9, 9, 9, 255]);
+    }
+
+    #[test]
+    fn sacn_sync_address_holds_data() {
+        let (receiver, sender) = receiver(Configuration::new(Protocol::Sacn));
+        let mut source = dmx_source(&receiver);
+        // The last universe reaches the last pixel of face 5.
+        let pixels = panel().led_count();
+        let last = 1 + (pixels / PIXELS_PER_UNIVERSE) as u16;
+        let mut data = vec![0u8; 3 * PIXELS_PER_UNIVERSE];
+        data[3 * (pixels % PIXELS_PER_UNIVERSE - 1)] = 7;
+        send(&receiver, &sender, &[sacn_dmx(last, 0, &data)]);
+        let frame = next(&mut source);
+        let (row, col) = (panel().height - 1, panel().width - 1);
+        assert_eq!(pixel(&frame, 5, row, col), [7, 0, 0, 255]);
+
+        send(&receiver, &sender, &[sacn_dmx(1, 100, &[8, 8, 8])]);
+        assert_eq!(pixel(&next(&mut source), 0, 0, 0), [0, 0, 0, 255]);
+        send(&receiver, &sender, &[sacn_sync()]);
+        assert_eq!(pixel(&next(&mut source), 0, 0, 0), [8, 8, 8, 255]);
+    }
+
+    #[test]
+    fn falls_back_when_stream_stops() {
+        let mut config = Configuration::new(Protocol::ArtNet);
+        config.timeout = Duration::from_millis(50);
+        let (receiver, sender) = receiver(config);
+        let mut source = dmx_source(&receiver);
+        let mut stripes = test_pattern::Stripes::new();
+        let info = FrameInfo {
+            time: Duration::ZERO,
+            frame_number: 0,
+            down: -Vec3::unit_y(),
+        };
+        assert!(next(&mut source) == stripes.next_frame(&info));
+        send(&receiver, &sender, &[artnet_dmx(0, &[])]);
+        assert!(next(&mut source) != stripes.current_frame());
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(next(&mut source) == stripes.next_frame(&info));
+    }
+
+    #[test]
+    fn pixel_order_must_be_a_permutation() {
+        assert_eq!(PixelOrder::parse("BGR").unwrap(), PixelOrder([2, 1, 0]));
+        assert!(PixelOrder::parse("rrg").is_err());
+        assert!(PixelOrder::parse("rgbw").is_err());
+    }
+}
@@ -0,0 +1,1210 @@
+// Animations that use the cube's geometry.
+//
+// The volumetric effects (plasma, fire, starfield, shells, planes)
+// color each LED by its position in cube space, so they are seamless
+// across the edges.  Rain, Life and sand run on the face grid and use
+// the topology to cross from face to face.  Fire and sand follow
+// gravity, so they react when the cube is turned.
+//
+// Every effect takes the same typed parameters.
+//  - speed:   1.0 is the designed pace.
+//  - palette: maps 0..1 to a color.
+//  - density: 0..1, how much of the cube is lit or populated.
+//
+// Each effect has defaults of its own, and the settings' `effects`
+// table changes them (see settings.rs):
+//
+//     [effects.fire]
+//     speed = 0.5
+//     palette = "ocean"    # rainbow, fire, ocean, matrix, or "#rrggbb"
+//                          # for shades of one color
+//     density = 0.8
+
+use anyhow::{anyhow, bail, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::cube_model::{FACE_DISPLACEMENT_MM, FACE_LENGTH_MM};
+use crate::leds;
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::prelude::*;
+use crate::settings::settings;
+use crate::test_pattern::{black_frame, PixelArray, FACE_COUNT};
+use crate::topology::{self, Cell, Direction};
+
+// Distance from the cube's center to the LEDs, mm
+const HALF_CUBE_MM: f32 = FACE_LENGTH_MM / 2.0 + FACE_DISPLACEMENT_MM;
+
+pub const NAMES: [&str; 8] = [
+    "plasma",
+    "fire",
+    "rain",
+    "starfield",
+    "shells",
+    "planes",
+    "life",
+    "sand",
+];
+
+pub fn register(registry: &mut PatternRegistry) {
+    registry.register("plasma", || {
+        Box::new(Plasma::new(params("plasma", Plasma::DEFAULTS)))
+    });
+    registry.register("fire", || {
+        Box::new(Fire::new(params("fire", Fire::DEFAULTS)))
+    });
+    registry.register("rain", || {
+        Box::new(Rain::new(params("rain", Rain::DEFAULTS)))
+    });
+    registry.register("starfield", || {
+        Box::new(Starfield::new(params("starfield", Starfield::DEFAULTS)))
+    });
+    registry.register("shells", || {
+        Box::new(Shells::new(params("shells", Shells::DEFAULTS)))
+    });
+    registry.register("planes", || {
+        Box::new(Planes::new(params("planes", Planes::DEFAULTS)))
+    });
+    registry.register("life", || {
+        Box::new(Life::new(params("life", Life::DEFAULTS)))
+    });
+    registry.register("sand", || {
+        Box::new(Sand::new(params("sand", Sand::DEFAULTS)))
+    });
+}
+
+// The effect `name`'s parameters: `defaults` with the settings' on top.
+fn params(name: &str, defaults: Params) -> Params {
+    match settings().effects.get(name) {
+        Some(settings) => settings.apply(defaults),
+        None => defaults,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Palette {
+    Rainbow,
+    Fire,
+    Ocean,
+    Matrix,
+    Mono([u8; 3]),
+}
+
+impl Palette {
+    // `t` wraps, except for the black-to-hot palettes which clamp.
+    pub fn color(self, t: f32) -> Vec3 {
+        let ramp = |stops: &[[f32; 3]], t: f32| {
+            let t = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+            let i = (t as usize).min(stops.len() - 2);
+            let (a, b) = (Vec3::from(stops[i]), Vec3::from(stops[i + 1]));
+            a.lerp(b, t - i as f32)
+        };
+        match self {
+            Palette::Rainbow => {
+                let h = t.rem_euclid(1.0) * 6.0;
+                let channel = |k: f32| {
+                    let x = (h + k).rem_euclid(6.0);
+                    ((x - 3.0).abs() - 1.0).clamp(0.0, 1.0)
+                };
+                Vec3::new(channel(0.0), channel(4.0), channel(2.0))
+            }
+            Palette::Fire => ramp(
+                &[
+                    [0.0, 0.0, 0.0],
+                    [0.6, 0.0, 0.0],
+                    [1.0, 0.4, 0.0],
+                    [1.0, 0.9, 0.2],
+                    [1.0, 1.0, 1.0],
+                ],
+                t,
+            ),
+            Palette::Ocean => {
+                let t = 0.5 - 0.5 * (std::f32::consts::TAU * t).cos();
+                ramp(&[[0.0, 0.0, 0.2], [0.0, 0.4, 0.8], [0.6, 1.0, 1.0]], t)
+            }
+            Palette::Matrix => {
+                ramp(&[[0.0, 0.0, 0.0], [0.0, 0.8, 0.1], [0.8, 1.0, 0.8]], t)
+            }
+            Palette::Mono([r, g, b]) => {
+                let c = Vec3::new(r as f32, g as f32, b as f32) / 255.0;
+                c * t.clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Palette {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        match name.as_str() {
+            "rainbow" => Ok(Palette::Rainbow),
+            "fire" => Ok(Palette::Fire),
+            "ocean" => Ok(Palette::Ocean),
+            "matrix" => Ok(Palette::Matrix),
+            _ => {
+                let hex = name
+                    .strip_prefix('#')
+                    .filter(|hex| hex.len() == 6)
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| anyhow!("unknown palette \"{}\"", name))?;
+                let [_, r, g, b] = hex.to_be_bytes();
+                Ok(Palette::Mono([r, g, b]))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+    pub speed: f32,
+    pub palette: Palette,
+    pub density: f32,
+}
+
+// An effect's parameters as the settings have them.  What's left out
+// keeps the effect's default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParamSettings {
+    pub speed: Option<f32>,
+    pub palette: Option<Palette>,
+    pub density: Option<f32>,
+}
+
+impl ParamSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(speed) = self.speed {
+            if !(speed >= 0.0 && speed.is_finite()) {
+                bail!("speed {} isn't 0 or more", speed);
+            }
+        }
+        if let Some(density) = self.density {
+            if !(0.0..=1.0).contains(&density) {
+                bail!("density {} is outside 0 to 1", density);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, defaults: Params) -> Params {
+        Params {
+            speed: self.speed.unwrap_or(defaults.speed),
+            palette: self.palette.unwrap_or(defaults.palette),
+            density: self.density.unwrap_or(defaults.density),
+        }
+    }
+}
+
+// A frame with every LED set by `f`, which gets the LED and returns a
+// color with channels in 0..1.
+fn fill(data: &mut PixelArray, mut f: impl FnMut(&leds::Led) -> Vec3) {
+    for led in leds::table() {
+        put(data, led.index, f(led));
+    }
+}
+
+fn put(data: &mut PixelArray, index: usize, color: Vec3) {
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    data[index] = byte(color.x);
+    data[index + 1] = byte(color.y);
+    data[index + 2] = byte(color.z);
+    data[index + 3] = 255;
+}
+
+// Smooth 3D value noise in 0..1.
+fn noise(p: Vec3) -> f32 {
+    fn hash(x: i32, y: i32, z: i32) -> f32 {
+        let mut h = (x as u32).wrapping_mul(0x8da6b343)
+            ^ (y as u32).wrapping_mul(0xd8163841)
+            ^ (z as u32).wrapping_mul(0xcb1ab31f);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0x5bd1e995);
+        h ^= h >> 15;
+        h as f32 / u32::MAX as f32
+    }
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (u, v, w) = (smooth(p.x - x0), smooth(p.y - y0), smooth(p.z - z0));
+    let (x, y, z) = (x0 as i32, y0 as i32, z0 as i32);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let plane = |z: i32| {
+        lerp(
+            lerp(hash(x, y, z), hash(x + 1, y, z), u),
+            lerp(hash(x, y + 1, z), hash(x + 1, y + 1, z), u),
+            v,
+        )
+    };
+    lerp(plane(z), plane(z + 1), w)
+}
+
+// ----  plasma --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+pub struct Plasma {
+    params: Params,
+    data: Vec<u8>,
+}
+
+impl Plasma {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Rainbow,
+        density: 0.5,
+    };
+
+    pub fn new(params: Params) -> Self {
+        Self {
+            params,
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Plasma {
+    fn name(&self) -> &str {
+        "plasma"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let Params {
+            speed,
+            palette,
+            density,
+        } = self.params;
+        let t = speed * info.time.as_secs_f32();
+        // Denser plasma has finer features.
+        let k = 2.0 + 6.0 * density;
+        fill(&mut self.data, |led| {
+            let p = led.position.to_vec() / HALF_CUBE_MM;
+            let v = (k * p.x + t).sin()
+                + (k * p.y - 1.3 * t).sin()
+                + (0.7 * k * (p.x + p.y + p.z) + 0.7 * t).sin()
+                + (k * p.magnitude() - 1.7 * t).sin();
+            palette.color(v / 8.0 + 0.1 * t)
+        });
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  fire   --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Turbulent flames that rise from whichever side of the cube is down.
+pub struct Fire {
+    params: Params,
+    data: Vec<u8>,
+}
+
+impl Fire {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Fire,
+        density: 0.5,
+    };
+
+    pub fn new(params: Params) -> Self {
+        Self {
+            params,
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Fire {
+    fn name(&self) -> &str {
+        "fire"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let Params {
+            speed,
+            palette,
+            density,
+        } = self.params;
+        let t = speed * info.time.as_secs_f32();
+        let up = -info.down;
+        fill(&mut self.data, |led| {
+            let p = led.position.to_vec() / HALF_CUBE_MM;
+            // 0 at the bottom of the cube, 1 at the top
+            let height = 0.5 + 0.5 * p.dot(up);
+            let rising = 3.0 * p - 2.5 * t * up;
+            let turbulence = noise(rising) * 0.65 + noise(2.0 * rising) * 0.35;
+            let reach = 0.3 + 0.7 * density;
+            let heat = turbulence * (1.0 - height / reach) * 1.6;
+            palette.color(heat)
+        });
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  rain   --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Matrix-style trails running down the side faces.
+pub struct Rain {
+    params: Params,
+    rng: StdRng,
+    downhill: [Option<Direction>; FACE_COUNT],
+    drops: Vec<Drop>,
+    brightness: Vec<f32>,
+    last_time: f32,
+    data: Vec<u8>,
+}
+
+struct Drop {
+    cell: Cell,
+    dir: Direction,
+    progress: f32, // fraction of a step
+    rate: f32,     // steps per second
+}
+
+impl Rain {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Matrix,
+        density: 0.5,
+    };
+
+    pub fn new(params: Params) -> Self {
+        Self {
+            params,
+            rng: StdRng::seed_from_u64(1),
+            downhill: std::array::from_fn(Self::downhill),
+            drops: Vec::new(),
+            brightness: vec![0.0; panel().led_count()],
+            last_time: 0.0,
+            data: black_frame(),
+        }
+    }
+
+    // The direction that runs downhill on `face`, if it is a side face.
+    fn downhill(face: usize) -> Option<Direction> {
+        let leds = leds::table();
+        let panel = panel();
+        let (row, col) = (panel.height / 2, panel.width / 2);
+        let center = leds.get(face, row, col).position;
+        let (dir, drop) = Direction::ALL
+            .iter()
+            .map(|&dir| {
+                let (dr, dc) = dir.delta();
+                let row = row.saturating_add_signed(dr).min(panel.height - 1);
+                let col = col.saturating_add_signed(dc).min(panel.width - 1);
+                (dir, center.y - leds.get(face, row, col).position.y)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        let (pitch_x, pitch_y) = panel.pitch_mm();
+        (drop > 0.5 * pitch_x.min(pitch_y)).then_some(dir)
+    }
+
+    fn spawn(&mut self) {
+        let face = self.rng.gen_range(0..FACE_COUNT);
+        let Some(dir) = self.downhill[face] else {
+            return;
+        };
+        // Start on the face's uphill edge.
+        let panel = panel();
+        let (row, col) = match dir {
+            Direction::Down => (0, self.rng.gen_range(0..panel.width)),
+            Direction::Up => {
+                (panel.height - 1, self.rng.gen_range(0..panel.width))
+            }
+            Direction::Right => (self.rng.gen_range(0..panel.height), 0),
+            Direction::Left => {
+                (self.rng.gen_range(0..panel.height), panel.width - 1)
+            }
+        };
+        self.drops.push(Drop {
+            cell: Cell::new(face, row, col),
+            dir,
+            progress: 0.0,
+            rate: self.rng.gen_range(20.0..60.0),
+        });
+    }
+}
+
+impl PatternSource for Rain {
+    fn name(&self) -> &str {
+        "rain"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let now = info.time.as_secs_f32();
+        let dt = (now - self.last_time).clamp(0.0, 0.1) * self.params.speed;
+        self.last_time = now;
+
+        // A few new drops per frame, so they don't fall in lockstep.
+        let panel = panel();
+        let across = (panel.width + panel.height) as f32 * 2.0;
+        let target = (self.params.density * across) as usize;
+        for _ in 0..4 {
+            if self.drops.len() < target {
+                self.spawn();
+            }
+        }
+
+        for b in &mut self.brightness {
+            *b *= (-3.0 * dt).exp();
+        }
+        let topology = topology::topology();
+        let downhill = self.downhill;
+        self.drops.retain_mut(|drop| {
+            drop.progress += drop.rate * dt;
+            while drop.progress >= 1.0 {
+                drop.progress -= 1.0;
+                let (cell, dir) = topology.neighbor(drop.cell, drop.dir);
+                // Drops that reach the bottom face vanish.
+                if downhill[cell.face].is_none() {
+                    return false;
+                }
+                (drop.cell, drop.dir) = (cell, dir);
+            }
+            self.brightness[drop.cell.id()] = 1.0;
+            true
+        });
+
+        let palette = self.params.palette;
+        let brightness = &self.brightness;
+        fill(&mut self.data, |led| palette.color(brightness[led.id]));
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  starfield  ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Flying through space, seen from the cube's center.  Stars appear far
+// ahead on every side, near the middle of a face, and streak outward
+// over it, and on over the edges, as they rush past.
+pub struct Starfield {
+    params: Params,
+    rng: StdRng,
+    stars: Vec<Star>,
+    brightness: Vec<f32>, // per LED, fading to leave trails
+    last_time: f32,
+    data: Vec<u8>,
+}
+
+// A star at `offset + depth * axis` that comes closer along `axis`.
+// `offset` is across the axis, at most 1 long.
+struct Star {
+    axis: Vec3,
+    offset: Vec3,
+    depth: f32,
+}
+
+impl Star {
+    fn position(&self) -> Vec3 {
+        self.offset + self.depth * self.axis
+    }
+}
+
+// How far ahead stars appear, and how fast they come.
+const STAR_DEPTH: f32 = 4.0;
+const STAR_SPEED: f32 = 1.5;
+
+impl Starfield {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Mono([255, 255, 255]),
+        density: 0.5,
+    };
+
+    pub fn new(params: Params) -> Self {
+        let mut new = Self {
+            params,
+            rng: StdRng::seed_from_u64(2),
+            stars: Vec::new(),
+            brightness: vec![0.0; panel().led_count()],
+            last_time: 0.0,
+            data: black_frame(),
+        };
+        let count = (params.density * 300.0) as usize;
+        for _ in 0..count {
+            let depth = new.rng.gen_range(0.0..STAR_DEPTH);
+            let star = new.random_star(depth);
+            new.stars.push(star);
+        }
+        new
+    }
+
+    fn random_star(&mut self, depth: f32) -> Star {
+        let axis = self.random_direction();
+        let across = loop {
+            let v = self.random_direction().cross(axis);
+            if v.magnitude2() > 0.01 {
+                break v.normalize();
+            }
+        };
+        Star {
+            axis,
+            offset: self.rng.gen_range(0.05..1.0) * across,
+            depth,
+        }
+    }
+
+    fn random_direction(&mut self) -> Vec3 {
+        loop {
+            let v = Vec3::new(
+                self.rng.gen_range(-1.0..1.0),
+                self.rng.gen_range(-1.0..1.0),
+                self.rng.gen_range(-1.0..1.0),
+            );
+            let m2 = v.magnitude2();
+            if m2 > 0.01 && m2 <= 1.0 {
+                return v / m2.sqrt();
+            }
+        }
+    }
+}
+
+impl PatternSource for Starfield {
+    fn name(&self) -> &str {
+        "starfield"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let now = info.time.as_secs_f32();
+        let dt = (now - self.last_time).clamp(0.0, 0.1) * self.params.speed;
+        self.last_time = now;
+
+        for b in &mut self.brightness {
+            *b *= (-8.0 * dt).exp();
+        }
+        let (pitch_x, pitch_y) = panel().pitch_mm();
+        let (leds, topology) = (leds::table(), topology::topology());
+        let led_toward = |v: Vec3| {
+            let cell = topology.cell_toward(v);
+            leds.get(cell.face, cell.row, cell.col)
+        };
+        for i in 0..self.stars.len() {
+            let from = self.stars[i].position();
+            self.stars[i].depth -= STAR_SPEED * dt;
+            if self.stars[i].depth <= 0.0 {
+                self.stars[i] = self.random_star(STAR_DEPTH);
+                continue;
+            }
+            let star = &self.stars[i];
+            let to = star.position();
+            // Closer stars are brighter, and they move faster across
+            // the LEDs, so every LED on the way is lit to draw a line.
+            let glow = 1.0 - star.depth / STAR_DEPTH;
+            let length = led_toward(to).position - led_toward(from).position;
+            let steps = (length.magnitude() / pitch_x.min(pitch_y))
+                .ceil()
+                .clamp(1.0, 64.0) as usize;
+            for step in 1..=steps {
+                let v = from.lerp(to, step as f32 / steps as f32);
+                let id = led_toward(v).id;
+                self.brightness[id] = self.brightness[id].max(glow);
+            }
+        }
+
+        let palette = self.params.palette;
+        let brightness = &self.brightness;
+        fill(&mut self.data, |led| palette.color(brightness[led.id]));
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  shells --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Spherical shells expanding from alternating corners.
+pub struct Shells {
+    params: Params,
+    data: Vec<u8>,
+}
+
+impl Shells {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Rainbow,
+        density: 0.5,
+    };
+
+    pub fn new(params: Params) -> Self {
+        Self {
+            params,
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Shells {
+    fn name(&self) -> &str {
+        "shells"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let Params {
+            speed,
+            palette,
+            density,
+        } = self.params;
+        let t = speed * info.time.as_secs_f32();
+        // Every few seconds, the shells come from a different corner.
+        const CORNERS: [[f32; 3]; 4] = [
+            [1.0, 1.0, 1.0],
+            [-1.0, -1.0, 1.0],
+            [1.0, -1.0, -1.0],
+            [-1.0, 1.0, -1.0],
+        ];
+        let corner = Vec3::from(CORNERS[(t / 4.0) as usize % CORNERS.len()]);
+        let spacing = 0.8 - 0.6 * density;
+        let thickness = 0.15 * spacing + 0.2 * density * spacing;
+        fill(&mut self.data, |led| {
+            let p = led.position.to_vec() / HALF_CUBE_MM;
+            let r = (p - corner).magnitude() - 0.5 * t;
+            let phase = (r / spacing).rem_euclid(1.0) * spacing;
+            let edge = (1.0 - phase / thickness).max(0.0);
+            palette.color(r / (4.0 * spacing)) * edge
+        });
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  planes --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Bands of color that sweep through the cube on a tumbling axis.
+pub struct Planes {
+    params: Params,
+    data: Vec<u8>,
+}
+
+impl Planes {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Rainbow,
+        density: 0.3,
+    };
+
+    pub fn new(params: Params) -> Self {
+        Self {
+            params,
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Planes {
+    fn name(&self) -> &str {
+        "planes"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let Params {
+            speed,
+            palette,
+            density,
+        } = self.params;
+        let t = speed * info.time.as_secs_f32();
+        let axis = Vec3::new(
+            (0.31 * t).sin() * (0.17 * t).cos(),
+            (0.31 * t).cos(),
+            (0.31 * t).sin() * (0.17 * t).sin(),
+        );
+        let bands = 1.0 + 6.0 * density;
+        fill(&mut self.data, |led| {
+            let d = led.position.to_vec().dot(axis) / HALF_CUBE_MM;
+            palette.color(bands * d / 2.0 + 0.2 * t)
+        });
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  life   --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Conway's Game of Life on the cube's surface.  Cells on an edge see
+// the cells across it.  Where three faces meet, a corner cell has
+// seven neighbors instead of eight.
+pub struct Life {
+    params: Params,
+    rng: StdRng,
+    neighbors: Vec<Vec<usize>>,
+    ages: Vec<u32>, // 0 is dead
+    generation: u64,
+    checkpoint: usize, // population when last checked
+    data: Vec<u8>,
+}
+
+impl Life {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Ocean,
+        density: 0.3,
+    };
+    const GENERATIONS_PER_SECOND: f32 = 15.0;
+
+    pub fn new(params: Params) -> Self {
+        let topology = topology::topology();
+        let id = Cell::id;
+        let neighbors = leds::table()
+            .iter()
+            .map(|led| {
+                let cell = Cell::new(led.face, led.row, led.col);
+                let mut ids = Vec::with_capacity(8);
+                for dir in Direction::ALL {
+                    // The orthogonal neighbor, then the diagonal one
+                    // clockwise from it.
+                    let (side, side_dir) = topology.neighbor(cell, dir);
+                    let (diagonal, _) =
+                        topology.neighbor(side, side_dir.turn_right());
+                    ids.push(id(side));
+                    ids.push(id(diagonal));
+                }
+                ids.sort();
+                ids.dedup();
+                ids.retain(|&n| n != id(cell));
+                ids
+            })
+            .collect();
+        let mut new = Self {
+            params,
+            rng: StdRng::seed_from_u64(3),
+            neighbors,
+            ages: vec![0; panel().led_count()],
+            generation: 0,
+            checkpoint: 0,
+            data: black_frame(),
+        };
+        new.seed();
+        new
+    }
+
+    fn seed(&mut self) {
+        for age in &mut self.ages {
+            *age = self.rng.gen_bool(self.params.density as f64) as u32;
+        }
+    }
+
+    fn step(&mut self) {
+        self.ages = (0..self.ages.len())
+            .map(|i| {
+                let live = self.neighbors[i]
+                    .iter()
+                    .filter(|&&n| self.ages[n] > 0)
+                    .count();
+                match (self.ages[i], live) {
+                    (0, 3) => 1,
+                    (0, _) => 0,
+                    (age, 2 | 3) => age + 1,
+                    (_, _) => 0,
+                }
+            })
+            .collect();
+        self.generation += 1;
+
+        // Reseed once the board has settled into still lifes and
+        // blinkers, which barely change the population.
+        const CHECK_INTERVAL: u64 = 64;
+        if self.generation.is_multiple_of(CHECK_INTERVAL) {
+            let population = self.ages.iter().filter(|&&a| a > 0).count();
+            let change = population.abs_diff(self.checkpoint);
+            if change < self.ages.len() / 500 {
+                self.seed();
+            }
+            self.checkpoint = population;
+        }
+    }
+}
+
+impl PatternSource for Life {
+    fn name(&self) -> &str {
+        "life"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let rate = Self::GENERATIONS_PER_SECOND * self.params.speed;
+        let generation = (info.time.as_secs_f32() * rate) as u64;
+        while self.generation < generation {
+            self.step();
+        }
+        let palette = self.params.palette;
+        let ages = &self.ages;
+        fill(&mut self.data, |led| match ages[led.id] {
+            0 => Vec3::zero(),
+            age => palette.color(0.1 + age as f32 / 40.0),
+        });
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  sand   --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+// Grains poured into the cube as if it were a hollow box.  They drop
+// from the ceiling, slide down the walls and spread over the floor like
+// a liquid, so turning the cube pours them to its new low side.
+pub struct Sand {
+    params: Params,
+    rng: StdRng,
+    grains: Vec<(Cell, f32)>, // cell, palette position
+    grid: Vec<Option<usize>>, // the grain in each cell
+    heights: Vec<f32>,        // mm below the cube's center
+    pitch: f32,               // mm between LEDs
+    steps: f32,               // fraction of a step
+    last_time: f32,
+    data: Vec<u8>,
+}
+
+impl Sand {
+    pub const DEFAULTS: Params = Params {
+        speed: 1.0,
+        palette: Palette::Rainbow,
+        density: 0.3,
+    };
+
+    const STEPS_PER_SECOND: f32 = 60.0;
+    // A grain slides only where it drops at least this many pitches
+    // per LED.
+    const REPOSE: f32 = 0.3;
+    // Grains may rise this much while spreading over a floor.
+    const LEVEL: f32 = 0.05;
+
+    pub fn new(params: Params) -> Self {
+        let mut rng = StdRng::seed_from_u64(4);
+        let density = params.density.clamp(0.0, 1.0);
+        let mut leds: Vec<&leds::Led> = leds::table().iter().collect();
+        leds.shuffle(&mut rng);
+        leds.truncate((density * leds.len() as f32 / 2.0) as usize);
+
+        // Grains start where they are, banded by height.
+        let panel = panel();
+        let mut grid = vec![None; panel.led_count()];
+        let mut grains = Vec::new();
+        for led in leds {
+            grid[led.id] = Some(grains.len());
+            let t = 0.4 + 0.4 * led.position.y / HALF_CUBE_MM;
+            grains.push((Cell::new(led.face, led.row, led.col), t));
+        }
+        let (pitch_x, pitch_y) = panel.pitch_mm();
+        Self {
+            params,
+            rng,
+            grains,
+            grid,
+            heights: vec![0.0; panel.led_count()],
+            pitch: pitch_x.min(pitch_y),
+            steps: 0.0,
+            last_time: 0.0,
+            data: black_frame(),
+        }
+    }
+
+    fn is_free(&self, cell: Cell) -> bool {
+        self.grid[cell.id()].is_none()
+    }
+
+    fn height(&self, cell: Cell) -> f32 {
+        self.heights[cell.id()]
+    }
+
+    fn step(&mut self, down: Vec3) {
+        for led in leds::table() {
+            self.heights[led.id] = led.position.to_vec().dot(down);
+        }
+        // Move the lowest grains first, so the ones above can follow.
+        let mut order: Vec<usize> = (0..self.grains.len()).collect();
+        order.sort_by(|&a, &b| {
+            let height = |i: usize| self.height(self.grains[i].0);
+            height(b).total_cmp(&height(a))
+        });
+        for i in order {
+            if let Some(path) = self.path(self.grains[i].0, down) {
+                self.shift(&path);
+            }
+        }
+    }
+
+    // Move each grain on `path` to the next cell.  The last cell must
+    // be free.
+    fn shift(&mut self, path: &[Cell]) {
+        for pair in path.windows(2).rev() {
+            let grain = self.grid[pair[0].id()].take();
+            if let Some(i) = grain {
+                self.grains[i].0 = pair[1];
+            }
+            self.grid[pair[1].id()] = grain;
+        }
+    }
+
+    // The cells that the grain at `cell`, and any grains it pushes
+    // along, move through.  None if it stays put.
+    fn path(&mut self, cell: Cell, down: Vec3) -> Option<Vec<Cell>> {
+        let topology = topology::topology();
+
+        // Grains on the ceiling fall through the middle of the cube.
+        if topology.normal(cell.face).dot(down) < -0.5 {
+            let led = leds::table().get(cell.face, cell.row, cell.col);
+            let p = led.position.to_vec();
+            let distance = (0..3)
+                .filter(|&i| down[i].abs() > 1e-6)
+                .map(|i| (HALF_CUBE_MM.copysign(down[i]) - p[i]) / down[i])
+                .fold(f32::INFINITY, f32::min);
+            let landing = topology.cell_toward(p + distance * down);
+            if self.is_free(landing) {
+                return Some(vec![cell, landing]);
+            }
+        }
+
+        // Otherwise slide to the lowest free neighbor, diagonals
+        // included, if it is steep enough.
+        let here = self.height(cell);
+        let mut around = [(cell, Direction::Up); 8];
+        for (i, dir) in Direction::ALL.into_iter().enumerate() {
+            let (side, side_dir) = topology.neighbor(cell, dir);
+            around[2 * i] = (side, side_dir);
+            around[2 * i + 1] = topology.neighbor(side, side_dir.turn_right());
+        }
+        let lowest = around
+            .iter()
+            .map(|&(c, _)| c)
+            .filter(|&c| self.is_free(c))
+            .max_by(|&a, &b| self.height(a).total_cmp(&self.height(b)));
+        if let Some(lowest) = lowest {
+            if self.height(lowest) - here > Self::REPOSE * self.pitch {
+                return Some(vec![cell, lowest]);
+            }
+        }
+
+        // If the way down is blocked, push the grains in the way along
+        // the level, as a liquid would.
+        let (mut next, mut dir) = (0..4)
+            .map(|i| around[2 * i])
+            .max_by(|&(a, _), &(b, _)| {
+                self.height(a).total_cmp(&self.height(b))
+            })
+            .unwrap();
+        let level = self.height(next) - Self::LEVEL * self.pitch;
+        if level - here > Self::REPOSE * self.pitch {
+            let mut path = vec![cell];
+            for _ in 0..panel().width.max(panel().height) {
+                if self.height(next) < level {
+                    break;
+                }
+                path.push(next);
+                if self.is_free(next) {
+                    return Some(path);
+                }
+                (next, dir) = topology.neighbor(next, dir);
+            }
+        }
+
+        // Or wander along the level.
+        let (wander, _) = around[2 * self.rng.gen_range(0..4)];
+        let is_level = self.height(wander) - here > -Self::LEVEL * self.pitch;
+        (is_level && self.is_free(wander)).then(|| vec![cell, wander])
+    }
+}
+
+impl PatternSource for Sand {
+    fn name(&self) -> &str {
+        "sand"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let now = info.time.as_secs_f32();
+        let dt = (now - self.last_time).clamp(0.0, 0.1);
+        self.last_time = now;
+        self.steps += dt * self.params.speed * Self::STEPS_PER_SECOND;
+        while self.steps >= 1.0 {
+            self.steps -= 1.0;
+            self.step(info.down);
+        }
+
+        self.data = black_frame();
+        let leds = leds::table();
+        for &(cell, t) in &self.grains {
+            let led = leds.get(cell.face, cell.row, cell.col);
+            put(&mut self.data, led.index, self.params.palette.color(t));
+        }
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn info(seconds: f32) -> FrameInfo {
+        FrameInfo {
+            time: Duration::from_secs_f32(seconds),
+            frame_number: 0,
+            down: -Vec3::unit_y(),
+        }
+    }
+
+    fn lit(frame: &PixelArray) -> usize {
+        frame
+            .chunks_exact(4)
+            .filter(|p| p[..3] != [0, 0, 0])
+            .count()
+    }
+
+    #[test]
+    fn speed_scales_time() {
+        let fast = Params {
+            speed: 2.0,
+            ..Plasma::DEFAULTS
+        };
+        let frame = Plasma::new(fast).next_frame(&info(1.5)).to_vec();
+        let mut plasma = Plasma::new(Plasma::DEFAULTS);
+        assert_eq!(plasma.next_frame(&info(3.0)), frame);
+
+        let still = Params {
+            speed: 0.0,
+            ..Fire::DEFAULTS
+        };
+        let mut fire = Fire::new(still);
+        let first = fire.next_frame(&info(0.0)).to_vec();
+        assert_eq!(fire.next_frame(&info(5.0)), first);
+    }
+
+    #[test]
+    fn density_sets_how_much_is_lit() {
+        let leds = panel().led_count();
+        let life = |density| {
+            let params = Params {
+                density,
+                ..Life::DEFAULTS
+            };
+            lit(Life::new(params).next_frame(&info(0.0)))
+        };
+        let (sparse, dense) = (life(0.1), life(0.6));
+        assert!(sparse < leds / 5, "{} of {}", sparse, leds);
+        assert!(dense > leds / 2, "{} of {}", dense, leds);
+
+        let stars = |density| {
+            let params = Params {
+                density,
+                ..Starfield::DEFAULTS
+            };
+            lit(Starfield::new(params).next_frame(&info(0.0)))
+        };
+        assert_eq!(stars(0.0), 0);
+        assert!(stars(0.2) < stars(0.8));
+    }
+
+    #[test]
+    fn palette_colors_the_leds() {
+        let red = Params {
+            palette: Palette::Mono([255, 0, 0]),
+            ..Shells::DEFAULTS
+        };
+        let frame = Shells::new(red).next_frame(&info(1.0)).to_vec();
+        assert!(lit(&frame) > 0);
+        assert!(frame.chunks_exact(4).all(|p| p[1] == 0 && p[2] == 0));
+
+        assert_eq!(Palette::Fire.color(0.0), Vec3::zero());
+        assert_eq!(Palette::Fire.color(2.0), Vec3::new(1.0, 1.0, 1.0));
+        let palette = |name: &str| Palette::try_from(name.to_string());
+        assert_eq!(palette("ocean").unwrap(), Palette::Ocean);
+        assert_eq!(palette("#00ff80").unwrap(), Palette::Mono([0, 255, 128]));
+        assert!(palette("#00ff8").is_err());
+        assert!(palette("neon").is_err());
+    }
+
+    // A star starts near the middle of a face and moves out toward its
+    // edge, leaving a trail.
+    #[test]
+    fn stars_travel_outward() {
+        let params = Params {
+            density: 0.0,
+            ..Starfield::DEFAULTS
+        };
+        let mut starfield = Starfield::new(params);
+        starfield.stars.push(Star {
+            axis: Vec3::unit_z(),
+            offset: 0.5 * Vec3::unit_x(),
+            depth: STAR_DEPTH,
+        });
+        let leds = leds::table();
+        let mut head = Vec::new();
+        // It's too far away to see at first.
+        for frame in 1..50 {
+            let frame = starfield.next_frame(&info(frame as f32 / 20.0));
+            let brightest = leds
+                .iter()
+                .max_by_key(|led| frame[led.index])
+                .unwrap()
+                .position;
+            head.push(brightest.x.abs() + brightest.y.abs());
+        }
+        assert!(head.windows(2).all(|w| w[1] >= w[0]), "{:?}", head);
+        let (pitch_x, _) = panel().pitch_mm();
+        assert!(head[48] - head[0] > 20.0 * pitch_x, "{:?}", head);
+        assert!(lit(&starfield.data) > 3);
+    }
+
+    fn run(sand: &mut Sand, down: Vec3, steps: usize) {
+        for _ in 0..steps {
+            sand.step(down);
+        }
+    }
+
+    fn faces(sand: &Sand) -> [usize; FACE_COUNT] {
+        let mut counts = [0; FACE_COUNT];
+        for (cell, _) in &sand.grains {
+            counts[cell.face] += 1;
+        }
+        counts
+    }
+
+    // Grains are neither lost nor stacked, and they pour to the low
+    // side whichever way that is.
+    #[test]
+    fn sand_pours_down() {
+        let mut sand = Sand::new(Sand::DEFAULTS);
+        let count = sand.grains.len();
+        let topology = topology::topology();
+        for down in [-Vec3::unit_y(), Vec3::unit_x(), -Vec3::unit_z()] {
+            run(&mut sand, down, 4 * panel().width.max(panel().height));
+            let mut cells: Vec<Cell> =
+                sand.grains.iter().map(|g| g.0).collect();
+            cells.sort_by_key(|c| (c.face, c.row, c.col));
+            cells.dedup();
+            assert_eq!(cells.len(), count);
+            let grid = sand.grid.iter().flatten();
+            assert_eq!(grid.count(), count);
+
+            let counts = faces(&sand);
+            let floor = topology.cell_toward(down).face;
+            let ceiling = topology.cell_toward(-down).face;
+            assert_eq!(counts[ceiling], 0, "{:?} {:?}", down, counts);
+            assert!(counts[floor] > count / 2, "{:?} {:?}", down, counts);
+        }
+    }
+}
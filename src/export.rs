@@ -0,0 +1,293 @@
+// Offline video export.
+//
+// `wgpu-cube export [OPTIONS] OUT` renders frames headless at a fixed
+// timestep and writes them to disk, either as numbered PNGs or as a
+// YUV4MPEG2 (Y4M) stream that ffmpeg and most players read.  No window
+// is needed.
+//
+//  - OUT is where to write: a path ending in ".y4m" is a Y4M file,
+//    anything else is a directory of PNGs.
+//  - `--fps N` is the frame rate, default 60.  Animation and trackball
+//    time advance by exactly 1/fps per frame.
+//  - `--frames N` is the number of frames, default 600.
+//  - `--loop` renders exactly one turn of the trackball's spin instead.
+//  - `--size WxH` is the frame size, e.g. "1920x1080", by default the
+//    window size.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::trackball;
+
+const DEFAULT_FPS: u32 = 60;
+const DEFAULT_FRAMES: u32 = 600;
+
+pub const USAGE: &str = "usage: wgpu-cube export [--fps N] \
+                         [--frames N | --loop] [--size WxH] OUT";
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub path: PathBuf,
+    pub fps: u32,
+    pub frames: u32,
+    pub size: Option<(u32, u32)>,
+}
+
+impl Settings {
+    // From the export command's arguments: options, then OUT.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut path = None;
+        let mut fps = DEFAULT_FPS;
+        let mut frames = None;
+        let mut one_loop = false;
+        let mut size = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value =
+                || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+            match arg.as_str() {
+                "--fps" => fps = parse_count(arg, value()?)?,
+                "--frames" => frames = Some(parse_count(arg, value()?)?),
+                "--loop" => one_loop = true,
+                "--size" => size = Some(parse_size(value()?)?),
+                _ if arg.starts_with("--") => bail!("unknown option {}", arg),
+                _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                _ => bail!(USAGE),
+            }
+        }
+        let frames = match (frames, one_loop) {
+            (Some(_), true) => bail!("--frames and --loop don't go together"),
+            (Some(frames), false) => frames,
+            (None, true) => loop_frames(fps),
+            (None, false) => DEFAULT_FRAMES,
+        };
+        Ok(Self {
+            path: path.ok_or_else(|| anyhow!(USAGE))?,
+            fps,
+            frames,
+            size,
+        })
+    }
+}
+
+fn parse_count(option: &str, value: &str) -> Result<u32> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => bail!("bad {} \"{}\"", option, value),
+    }
+}
+
+// The frames in one turn of the trackball's spin.  The turn only closes
+// exactly when fps * SPIN_PERIOD is a whole number, as at 60 or 30 fps.
+pub fn loop_frames(fps: u32) -> u32 {
+    (trackball::SPIN_PERIOD.as_secs_f64() * fps as f64).round() as u32
+}
+
+// "1920x1080"
+pub fn parse_size(spec: &str) -> Result<(u32, u32)> {
+    let size = spec.split_once(['x', 'X']).and_then(|(w, h)| {
+        Some((w.trim().parse().ok()?, h.trim().parse().ok()?))
+    });
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => bail!("bad size \"{}\"", spec),
+    }
+}
+
+enum Writer {
+    Png(PathBuf),
+    Y4m(BufWriter<File>),
+}
+
+pub struct Exporter {
+    settings: Settings,
+    writer: Writer,
+    start: Instant,
+    frame: u32,
+    header_written: bool,
+}
+
+impl Exporter {
+    pub fn new(settings: Settings) -> Result<Self> {
+        let path = &settings.path;
+        let writer = match path.extension().is_some_and(|ext| ext == "y4m") {
+            true => {
+                let file = File::create(path).with_context(|| {
+                    format!("can't create {}", path.display())
+                })?;
+                Writer::Y4m(BufWriter::new(file))
+            }
+            false => {
+                std::fs::create_dir_all(path).with_context(|| {
+                    format!("can't create {}", path.display())
+                })?;
+                Writer::Png(path.clone())
+            }
+        };
+        Ok(Self {
+            settings,
+            writer,
+            start: Instant::now(),
+            frame: 0,
+            header_written: false,
+        })
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.start = now;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frame >= self.settings.frames
+    }
+
+    // When the next frame is, on the export's clock.
+    pub fn next_time(&self) -> Instant {
+        let nanos =
+            self.frame as u64 * 1_000_000_000 / self.settings.fps as u64;
+        self.start + Duration::from_nanos(nanos)
+    }
+
+    pub fn write_frame(&mut self, image: &image::RgbaImage) -> Result<()> {
+        match &mut self.writer {
+            Writer::Png(dir) => {
+                let path = dir.join(format!("frame_{:05}.png", self.frame));
+                image.save(&path).with_context(|| {
+                    format!("can't write {}", path.display())
+                })?;
+            }
+            Writer::Y4m(out) => {
+                if !self.header_written {
+                    writeln!(
+                        out,
+                        "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                        image.width(),
+                        image.height(),
+                        self.settings.fps,
+                    )?;
+                    self.header_written = true;
+                }
+                write_y4m_frame(out, image)?;
+            }
+        }
+        self.frame += 1;
+        if self.is_done() {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Writer::Y4m(out) = &mut self.writer {
+            out.flush()?;
+        }
+        println!(
+            "exported {} frames to {}",
+            self.frame,
+            self.settings.path.display()
+        );
+        Ok(())
+    }
+}
+
+// Full-resolution (4:4:4) planes, BT.601 studio range.
+fn write_y4m_frame(
+    out: &mut impl Write,
+    image: &image::RgbaImage,
+) -> Result<()> {
+    let pixel_count = (image.width() * image.height()) as usize;
+    let mut planes = vec![0u8; 3 * pixel_count];
+    for (i, pixel) in image.pixels().enumerate() {
+        let [r, g, b, _] = pixel.0.map(|c| c as f32);
+        let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+        let u = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+        let v = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+        planes[i] = y.round() as u8;
+        planes[pixel_count + i] = u.round() as u8;
+        planes[2 * pixel_count + i] = v.round() as u8;
+    }
+    out.write_all(b"FRAME\n")?;
+    out.write_all(&planes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(parse_size("1920x1080").unwrap(), (1920, 1080));
+        assert_eq!(parse_size("64X32").unwrap(), (64, 32));
+        for bad in ["0x10", "10x", "wide"] {
+            assert!(parse_size(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(loop_frames(60), 1024);
+
+        let settings =
+            Settings::parse(&args(&["--fps", "30", "--loop", "out.y4m"]))
+                .unwrap();
+        assert_eq!(settings.path, PathBuf::from("out.y4m"));
+        assert_eq!((settings.fps, settings.frames), (30, 512));
+        assert_eq!(settings.size, None);
+        let settings =
+            Settings::parse(&args(&["--size", "8x4", "frames"])).unwrap();
+        assert_eq!((settings.fps, settings.frames), (60, 600));
+        assert_eq!(settings.size, Some((8, 4)));
+        for bad in [
+            &["out.y4m", "more"][..],
+            &["--frames", "2", "--loop", "out"],
+            &["--fps", "0", "out"],
+            &["--speed", "2", "out"],
+            &["--fps"],
+            &[],
+        ] {
+            assert!(Settings::parse(&args(bad)).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn writes_y4m_frames_a_step_apart() {
+        let path = std::env::temp_dir()
+            .join(format!("cube-export-{}.y4m", std::process::id()));
+        let settings = Settings {
+            path: path.clone(),
+            fps: 50,
+            frames: 2,
+            size: None,
+        };
+        let mut exporter = Exporter::new(settings).unwrap();
+        let start = Instant::now();
+        exporter.start(start);
+        // white, red
+        let image = image::RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => image::Rgba([255, 255, 255, 255]),
+            _ => image::Rgba([255, 0, 0, 255]),
+        });
+        for frame in 0..2 {
+            let step = Duration::from_millis(20);
+            assert_eq!(exporter.next_time() - start, frame * step);
+            assert!(!exporter.is_done());
+            exporter.write_frame(&image).unwrap();
+        }
+        assert!(exporter.is_done());
+        drop(exporter);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = b"YUV4MPEG2 W2 H1 F50:1 Ip A1:1 C444\n";
+        assert!(bytes.starts_with(header));
+        // Y, then U, then V, each a whole plane.
+        let frame =
+            [b"FRAME\n".as_slice(), &[235, 81, 128, 90, 128, 240]].concat();
+        assert_eq!(bytes[header.len()..], [frame.clone(), frame].concat());
+    }
+}
@@ -0,0 +1,161 @@
+// Shows the last frames' times as a bar graph in the bottom left corner
+// of the window, with hitches in red.  Drawn over the finished frame.
+// See frame_graph.wgsl.
+
+use crate::stats::{self, Stats};
+
+// The graph's size and distance from the window's edges, in pixels.
+const WIDTH: f32 = 256.0;
+const HEIGHT: f32 = 96.0;
+const MARGIN: f32 = 8.0;
+
+// Frame times at the top of the graph and at the line across it.
+const TOP_MS: f32 = 50.0;
+const BUDGET_MS: f32 = 1000.0 / 60.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GraphUniformRaw {
+    rect: [f32; 4],
+    scale: [f32; 4],
+    ms: [[f32; 4]; stats::HISTORY / 4],
+}
+
+pub struct FrameGraph {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl FrameGraph {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device
+            .create_shader_module(wgpu::include_wgsl!("frame_graph.wgsl"));
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame_graph_uniform_buffer"),
+            size: std::mem::size_of::<GraphUniformRaw>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("frame_graph_bind_group_layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("frame_graph_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("frame_graph_pipeline_layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("frame_graph_pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_frame_graph_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_frame_graph_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            });
+        Self {
+            pipeline,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    // Graph `stats`' recent frames in a window `width` by `height`.
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        stats: &Stats,
+        width: u32,
+        height: u32,
+    ) {
+        let (width, height) = (width as f32, height as f32);
+        let hitch_ms = stats.hitch_ms().unwrap_or(f32::MAX);
+        let mut uniform = GraphUniformRaw {
+            rect: [
+                2.0 * MARGIN / width - 1.0,
+                2.0 * MARGIN / height - 1.0,
+                2.0 * WIDTH / width,
+                2.0 * HEIGHT / height,
+            ],
+            scale: [TOP_MS, BUDGET_MS, hitch_ms, 0.0],
+            ms: [[0.0; 4]; stats::HISTORY / 4],
+        };
+        let mut count = 0;
+        for (i, ms) in stats.recent().enumerate() {
+            uniform.ms[i / 4][i % 4] = ms;
+            count += 1;
+        }
+        uniform.scale[3] = count as f32;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&uniform),
+        );
+    }
+
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) {
+        let mut render_pass =
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("frame_graph_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..4, 0..2 + stats::HISTORY as u32);
+    }
+}
@@ -0,0 +1,61 @@
+// A graph of the last frames' times, a bar each, in the bottom left
+// corner.  Instance 0 is the background, 1 a line at the frame budget
+// and the rest the bars, oldest first.
+
+const FRAMES: u32 = 256u;  // stats::HISTORY
+const LINE_WIDTH: f32 = 0.01;  // of the graph's height
+
+struct Graph {
+    rect: vec4<f32>,   // left, bottom, width, height in clip space
+    scale: vec4<f32>,  // ms at the top, budget ms, hitch ms, frame count
+    ms: array<vec4<f32>, 64>,  // FRAMES frame times, four to a vec4
+};
+
+@group(0) @binding(0) var<uniform> graph: Graph;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_frame_graph_main(
+    @builtin(vertex_index) vertex: u32,
+    @builtin(instance_index) instance: u32,
+) -> VertexOutput {
+    // Each instance is a rectangle from `lo` to `hi`, with the graph
+    // from 0 to 1.
+    var lo = vec2<f32>(0.0, 0.0);
+    var hi = vec2<f32>(1.0, 1.0);
+    var color = vec4<f32>(0.0, 0.0, 0.0, 0.6);
+    let top_ms = graph.scale.x;
+    if instance == 1u {
+        lo.y = graph.scale.y / top_ms;
+        hi.y = lo.y + LINE_WIDTH;
+        color = vec4<f32>(1.0, 1.0, 1.0, 0.5);
+    } else if instance > 1u {
+        let frame = instance - 2u;
+        let ms = graph.ms[frame / 4u][frame % 4u];
+        lo.x = f32(frame) / f32(FRAMES);
+        hi.x = f32(frame + 1u) / f32(FRAMES);
+        hi.y = min(ms / top_ms, 1.0);
+        if frame >= u32(graph.scale.w) {
+            hi.y = 0.0;
+        }
+        if ms > graph.scale.z {
+            color = vec4<f32>(1.0, 0.1, 0.1, 1.0);
+        } else if ms > graph.scale.y {
+            color = vec4<f32>(1.0, 0.8, 0.1, 1.0);
+        } else {
+            color = vec4<f32>(0.2, 0.9, 0.3, 1.0);
+        }
+    }
+    let corner = vec2<f32>(f32(vertex & 1u), f32(vertex >> 1u));
+    let xy = graph.rect.xy + mix(lo, hi, corner) * graph.rect.zw;
+    return VertexOutput(vec4<f32>(xy, 0.0, 1.0), color);
+}
+
+@fragment
+fn fs_frame_graph_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
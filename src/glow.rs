@@ -8,8 +8,10 @@
 //      if light is visible:
 //          calc BRDF
 
+use crate::panel::{panel, Panel};
 use crate::prelude::*;
 use crate::traits::Renderable;
+use crate::wiring::wiring;
 use fast_image_resize as fir;
 use wgpu::util::DeviceExt;
 
@@ -24,13 +26,7 @@ const RESAMPLING_ALGORITHM: ResamplingAlgorithm = ResamplingAlgorithm::Gapped;
 const FACE_COUNT: usize = 6;
 const CHANNEL_COUNT: usize = 4;
 
-const SRC_FACE_WIDTH: usize = 64;
-const SRC_FACE_HEIGHT: usize = 64;
-const SRC_WIDTH: usize = FACE_COUNT * SRC_FACE_WIDTH;
-const SRC_HEIGHT: usize = 1 * SRC_FACE_HEIGHT;
-const SRC_BYTE_COUNT: usize = SRC_HEIGHT * SRC_WIDTH * CHANNEL_COUNT;
-const SRC_FACE_BYTE_COUNT: usize =
-    SRC_FACE_HEIGHT * SRC_FACE_WIDTH * CHANNEL_COUNT;
+// The source is the blinky frame, whose size is set by the panel.
 
 const INT_FACE_WIDTH: usize = 16;
 const INT_FACE_HEIGHT: usize = 16;
@@ -48,10 +44,8 @@ const DST_BYTE_COUNT: usize = DST_HEIGHT * DST_WIDTH * CHANNEL_COUNT;
 const DST_FACE_BYTE_COUNT: usize =
     DST_FACE_HEIGHT * DST_FACE_WIDTH * CHANNEL_COUNT;
 
-type SrcPixelArray = [u8; SRC_BYTE_COUNT];
 type IntPixelArray = [u8; INT_BYTE_COUNT];
 pub type DstPixelArray = [u8; DST_BYTE_COUNT];
-type SrcFacePixelArray = [u8; SRC_FACE_BYTE_COUNT];
 type IntFacePixelArray = [u8; INT_FACE_BYTE_COUNT];
 type DstFacePixelArray = [u8; DST_FACE_BYTE_COUNT];
 
@@ -113,6 +107,7 @@ impl Glow {
             let data = [0u8; DST_BYTE_COUNT];
             Resampler {
                 algorithm,
+                panel: panel(),
                 resizer,
                 data,
             }
@@ -134,7 +129,7 @@ impl Glow {
         wgpu::BindingResource::TextureView(&self.glow_view)
     }
 
-    pub fn update(&mut self, blinky: &SrcPixelArray) {
+    pub fn update(&mut self, blinky: &[u8]) {
         self.resampler.resample(blinky);
     }
 }
@@ -172,12 +167,13 @@ impl Renderable<GlowAttributes, GlowPreparedData> for Glow {
 
 struct Resampler {
     algorithm: ResamplingAlgorithm,
+    panel: Panel,
     resizer: fir::Resizer,
     data: DstPixelArray,
 }
 
 impl Resampler {
-    fn resample(&mut self, blinky: &SrcPixelArray) {
+    fn resample(&mut self, blinky: &[u8]) {
         match self.algorithm {
             ResamplingAlgorithm::Lanczos => self.resample_lanczos(blinky),
             ResamplingAlgorithm::Boxes => self.resample_boxes(blinky),
@@ -185,12 +181,12 @@ impl Resampler {
         };
     }
 
-    fn resample_lanczos(&mut self, blinky: &SrcPixelArray) {
+    fn resample_lanczos(&mut self, blinky: &[u8]) {
         for face in 0..FACE_COUNT {
-            let mut src_face_bytes = blinky.get_face(face);
+            let mut src_face_bytes = self.src_face(blinky, face);
             let src_image = fir::images::Image::from_slice_u8(
-                SRC_FACE_WIDTH as _,
-                SRC_FACE_HEIGHT as _,
+                self.panel.width as _,
+                self.panel.height as _,
                 &mut src_face_bytes,
                 fir::PixelType::U8x4,
             )
@@ -198,99 +194,101 @@ impl Resampler {
             let mut dst_face_image = fir::images::Image::new(
                 DST_FACE_WIDTH as _,
                 DST_FACE_HEIGHT as _,
-                fir::PixelType::U8x4);
+                fir::PixelType::U8x4,
+            );
 
-            let options = fir::ResizeOptions::new()
-                .resize_alg(fir::ResizeAlg::Convolution(fir::FilterType::Lanczos3));
+            let options = fir::ResizeOptions::new().resize_alg(
+                fir::ResizeAlg::Convolution(fir::FilterType::Lanczos3),
+            );
 
-            self.resizer.resize(&src_image, &mut dst_face_image, &options).unwrap();
+            self.resizer
+                .resize(&src_image, &mut dst_face_image, &options)
+                .unwrap();
 
             let face_bytes = dst_face_image.buffer();
             self.data.set_face(face, face_bytes.try_into().unwrap());
         }
     }
 
-    fn resample_boxes(&mut self, blinky: &SrcPixelArray) {
-        const SCALE: usize = SRC_WIDTH / DST_WIDTH;
-        assert!(SCALE * DST_WIDTH == SRC_WIDTH);
-        const SRC_COL_BYTES: usize = CHANNEL_COUNT;
-        const SRC_ROW_BYTES: usize = SRC_WIDTH * SRC_COL_BYTES;
-        const DST_COL_BYTES: usize = CHANNEL_COUNT;
-        const DST_ROW_BYTES: usize = DST_WIDTH * DST_COL_BYTES;
-        for row in 0..DST_HEIGHT {
-            let row_offset = SRC_ROW_BYTES * SCALE * row;
-            for col in 0..DST_WIDTH {
-                let row_col_offset = row_offset + SCALE * CHANNEL_COUNT * col;
-                let mut accum = [0usize; 3];
-                for i in 0..SCALE {
-                    let i_offset = row_col_offset + i * SRC_ROW_BYTES;
-                    for j in 0..SCALE {
-                        accum[0] +=
-                            blinky[i_offset + j * CHANNEL_COUNT + 0] as usize;
-                        accum[1] +=
-                            blinky[i_offset + j * CHANNEL_COUNT + 1] as usize;
-                        accum[2] +=
-                            blinky[i_offset + j * CHANNEL_COUNT + 2] as usize;
-                    }
-                }
-                let s2 = SCALE * SCALE;
-                self.data[row * DST_ROW_BYTES + col * DST_COL_BYTES + 0] =
-                    (accum[0] / s2) as u8;
-                self.data[row * DST_ROW_BYTES + col * DST_COL_BYTES + 1] =
-                    (accum[1] / s2) as u8;
-                self.data[row * DST_ROW_BYTES + col * DST_COL_BYTES + 2] =
-                    (accum[2] / s2) as u8;
-                self.data[row * DST_ROW_BYTES + col * DST_COL_BYTES + 3] = 255;
-            }
-        }
+    fn resample_boxes(&mut self, blinky: &[u8]) {
+        let mut data = [0u8; DST_BYTE_COUNT];
+        self.box_average(blinky, &mut data, DST_FACE_WIDTH, DST_FACE_HEIGHT);
+        self.data = data;
     }
 
-    fn resample_gapped(&mut self, blinky: &SrcPixelArray) {
-        // box-convert down to 16x16 (factor of 4x4).
+    fn resample_gapped(&mut self, blinky: &[u8]) {
+        // box-convert down to 16x16 (factor of 4x4 for 64x64 panels).
         let int_bytes = self.resample_boxes_intermediate(blinky);
 
         // lanczos-convert down to 4x4 (factor of 4x4).
         self.resample_intermediate_lanczos(&int_bytes);
     }
 
-    fn resample_boxes_intermediate(
-        &mut self,
-        blinky: &SrcPixelArray,
-    ) -> IntPixelArray {
-        const SCALE: usize = SRC_WIDTH / INT_WIDTH;
-        assert!(SCALE * INT_WIDTH == SRC_WIDTH);
-        const SRC_COL_BYTES: usize = CHANNEL_COUNT;
-        const SRC_ROW_BYTES: usize = SRC_WIDTH * SRC_COL_BYTES;
-        const INT_COL_BYTES: usize = CHANNEL_COUNT;
-        const INT_ROW_BYTES: usize = INT_WIDTH * INT_COL_BYTES;
+    fn resample_boxes_intermediate(&mut self, blinky: &[u8]) -> IntPixelArray {
         let mut int_bytes = [0u8; INT_BYTE_COUNT];
-        for row in 0..INT_HEIGHT {
-            let row_offset = SRC_ROW_BYTES * SCALE * row;
-            for col in 0..INT_WIDTH {
-                let row_col_offset = row_offset + SCALE * CHANNEL_COUNT * col;
+        self.box_average(
+            blinky,
+            &mut int_bytes,
+            INT_FACE_WIDTH,
+            INT_FACE_HEIGHT,
+        );
+        int_bytes
+    }
+
+    // Average the blinky frame down to `face_width` x `face_height`
+    // per face.  The panel needn't divide evenly, and a panel smaller
+    // than the destination repeats its pixels.  The faces come out
+    // right to left, whatever their slots in the frame.
+    fn box_average(
+        &self,
+        blinky: &[u8],
+        dst: &mut [u8],
+        face_width: usize,
+        face_height: usize,
+    ) {
+        let panel = self.panel;
+        let src_row_bytes = panel.frame_width() * CHANNEL_COUNT;
+        let dst_row_bytes = FACE_COUNT * face_width * CHANNEL_COUNT;
+        let bin = |i: usize, n: usize, len: usize| {
+            let lo = i * len / n;
+            lo..((i + 1) * len / n).max(lo + 1)
+        };
+        for row in 0..face_height {
+            let rows = bin(row, face_height, panel.height);
+            for col in 0..FACE_COUNT * face_width {
+                let (face, face_col) = (col / face_width, col % face_width);
+                let cols = bin(face_col, face_width, panel.width);
                 let mut accum = [0usize; 3];
-                for i in 0..SCALE {
-                    let i_offset = row_col_offset + i * SRC_ROW_BYTES;
-                    for j in 0..SCALE {
-                        accum[0] +=
-                            blinky[i_offset + j * CHANNEL_COUNT + 0] as usize;
-                        accum[1] +=
-                            blinky[i_offset + j * CHANNEL_COUNT + 1] as usize;
-                        accum[2] +=
-                            blinky[i_offset + j * CHANNEL_COUNT + 2] as usize;
+                for i in rows.clone() {
+                    for j in cols.clone() {
+                        let x = frame_slot(face) * panel.width + j;
+                        let k = i * src_row_bytes + x * CHANNEL_COUNT;
+                        for chan in 0..3 {
+                            accum[chan] += blinky[k + chan] as usize;
+                        }
                     }
                 }
-                let s2 = SCALE * SCALE;
-                int_bytes[row * INT_ROW_BYTES + col * INT_COL_BYTES + 0] =
-                    (accum[0] / s2) as u8;
-                int_bytes[row * INT_ROW_BYTES + col * INT_COL_BYTES + 1] =
-                    (accum[1] / s2) as u8;
-                int_bytes[row * INT_ROW_BYTES + col * INT_COL_BYTES + 2] =
-                    (accum[2] / s2) as u8;
-                int_bytes[row * INT_ROW_BYTES + col * INT_COL_BYTES + 3] = 255;
+                let n = rows.len() * cols.len();
+                let k = row * dst_row_bytes + col * CHANNEL_COUNT;
+                for chan in 0..3 {
+                    dst[k + chan] = (accum[chan] / n) as u8;
+                }
+                dst[k + 3] = 255;
             }
         }
-        int_bytes
+    }
+
+    // The face `FACE_COUNT - face - 1` of the blinky frame.
+    fn src_face(&self, blinky: &[u8], face: usize) -> Vec<u8> {
+        let (width, height) = (self.panel.width, self.panel.height);
+        let row_bytes = width * CHANNEL_COUNT;
+        let src_row_bytes = self.panel.frame_width() * CHANNEL_COUNT;
+        let mut data = Vec::with_capacity(height * row_bytes);
+        for row in 0..height {
+            let i = row * src_row_bytes + frame_slot(face) * row_bytes;
+            data.extend_from_slice(&blinky[i..i + row_bytes]);
+        }
+        data
     }
 
     fn resample_intermediate_lanczos(&mut self, int_bytes: &IntPixelArray) {
@@ -309,9 +307,12 @@ impl Resampler {
                 fir::PixelType::U8x4,
             );
 
-            let options = fir::ResizeOptions::new()
-                .resize_alg(fir::ResizeAlg::Convolution(fir::FilterType::Lanczos3));
-            self.resizer.resize(&int_image, &mut dst_face_image, &options).unwrap();
+            let options = fir::ResizeOptions::new().resize_alg(
+                fir::ResizeAlg::Convolution(fir::FilterType::Lanczos3),
+            );
+            self.resizer
+                .resize(&int_image, &mut dst_face_image, &options)
+                .unwrap();
 
             let face_bytes = dst_face_image.buffer();
             self.data.set_face(face, face_bytes.try_into().unwrap());
@@ -319,28 +320,14 @@ impl Resampler {
     }
 }
 
-trait FaceSource<T> {
-    fn get_face(&self, face: usize) -> T;
+// The glow texture has the faces right to left, as the shaders expect.
+// Where its `face`th face from the left is in the blinky frame.
+fn frame_slot(face: usize) -> usize {
+    wiring().slot(FACE_COUNT - face - 1)
 }
 
-impl FaceSource<SrcFacePixelArray> for SrcPixelArray {
-    fn get_face(&self, face: usize) -> SrcFacePixelArray {
-        let mut data: SrcFacePixelArray = [0u8; SRC_FACE_BYTE_COUNT];
-        let face_offset = face * SRC_FACE_WIDTH * CHANNEL_COUNT;
-        for row in 0..SRC_FACE_HEIGHT {
-            let row_offset = row * SRC_WIDTH * CHANNEL_COUNT;
-            let face_row_offset = row * SRC_FACE_WIDTH * CHANNEL_COUNT;
-            for col in 0..SRC_FACE_WIDTH {
-                let col_offset = col * CHANNEL_COUNT;
-                for chan in 0..CHANNEL_COUNT {
-                    let i = face_offset + row_offset + col_offset + chan;
-                    let j = face_row_offset + col_offset + chan;
-                    data[j] = self[i];
-                }
-            }
-        }
-        data
-    }
+trait FaceSource<T> {
+    fn get_face(&self, face: usize) -> T;
 }
 
 impl FaceSource<IntFacePixelArray> for IntPixelArray {
@@ -0,0 +1,227 @@
+// Golden-image tests for the render pipeline.
+//
+// Each scene fixes the cube's orientation, the LED pattern and the
+// frame of it to show; the lights and camera never move.  Scenes are rendered
+// headless and compared to the reference PNGs in `golden/`.  A pixel
+// differs when its color is perceptibly off, and a scene fails when
+// more than a few pixels differ.  Failures leave the rendered image and
+// a diff, with differing pixels in red, in `target/golden/`.
+//
+//     wgpu-cube golden           check every scene
+//     wgpu-cube golden bless     rewrite the references
+//
+// `cargo test` runs the check too, and skips it when there's no
+// adapter.  The scenes are always rendered on the software adapter
+// (llvmpipe on GL, say) without multisampling, so the references don't
+// depend on the GPU.  They assume the default panel, wiring and
+// settings.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{Deg, Quaternion};
+use image::RgbaImage;
+
+use crate::prelude::*;
+use crate::trackball;
+use crate::State;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+// The largest color distance that passes, of about 765.
+const PIXEL_TOLERANCE: f32 = 24.0;
+// The fraction of pixels that may differ.
+const MAX_DIFFERING: f32 = 0.001;
+
+// Scenes' frames are this far apart.
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+struct Scene {
+    name: &'static str,
+    pattern: &'static str,
+    frames: u32, // updates, FRAME_TIME apart; patterns count from 1
+    axis: [f32; 3],
+    angle: f32, // degrees
+}
+
+const SCENES: &[Scene] = &[
+    Scene {
+        name: "faces",
+        pattern: "faces",
+        frames: 1,
+        axis: [1.0, 1.0, 0.0],
+        angle: 30.0,
+    },
+    Scene {
+        name: "stripes",
+        pattern: "stripes",
+        frames: 30,
+        axis: [0.0, 1.0, 0.0],
+        angle: 45.0,
+    },
+    Scene {
+        name: "dark",
+        pattern: "blank",
+        frames: 1,
+        axis: [1.0, 0.0, 0.0],
+        angle: 20.0,
+    },
+];
+
+fn reference_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+fn failure_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+fn render(state: &mut State, scene: &Scene) -> Result<RgbaImage> {
+    let axis = Vec3::from(scene.axis).normalize();
+    let mut trackball = trackball::Trackball::new(&state.size);
+    trackball
+        .set_orientation(Quaternion::from_axis_angle(axis, Deg(scene.angle)));
+    state.cube_controller = Box::new(trackball);
+    state.blinky.select_pattern(scene.pattern)?;
+    let start = Instant::now();
+    state.blinky.restart(start);
+    for frame in 1..=scene.frames {
+        state.update(start + frame * FRAME_TIME);
+    }
+    state.render()?;
+    state.read_frame()
+}
+
+pub struct Comparison {
+    pub differing: usize,
+    pub diff: RgbaImage,
+}
+
+impl Comparison {
+    pub fn passes(&self) -> bool {
+        let pixels = self.diff.width() * self.diff.height();
+        self.differing as f32 <= MAX_DIFFERING * pixels as f32
+    }
+}
+
+// "Redmean" distance: weighted RGB that tracks perceived difference
+// much better than plain RGB, for almost no cost.
+fn color_distance(a: [u8; 4], b: [u8; 4]) -> f32 {
+    let [r1, g1, b1, _] = a.map(|c| c as f32);
+    let [r2, g2, b2, _] = b.map(|c| c as f32);
+    let r_mean = (r1 + r2) / 2.0;
+    let (dr, dg, db) = (r1 - r2, g1 - g2, b1 - b2);
+    ((2.0 + r_mean / 256.0) * dr * dr
+        + 4.0 * dg * dg
+        + (2.0 + (255.0 - r_mean) / 256.0) * db * db)
+        .sqrt()
+}
+
+// Compare images of the same size.  The diff is the reference, dimmed
+// and grey, with differing pixels in red.
+pub fn compare(reference: &RgbaImage, actual: &RgbaImage) -> Comparison {
+    let mut differing = 0;
+    let diff =
+        RgbaImage::from_fn(reference.width(), reference.height(), |x, y| {
+            let (a, b) =
+                (reference.get_pixel(x, y).0, actual.get_pixel(x, y).0);
+            if color_distance(a, b) > PIXEL_TOLERANCE {
+                differing += 1;
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                let grey =
+                    ((a[0] as u32 + a[1] as u32 + a[2] as u32) / 9) as u8;
+                image::Rgba([grey, grey, grey, 255])
+            }
+        });
+    Comparison { differing, diff }
+}
+
+// Render every scene and compare it to its reference, or with `bless`,
+// replace the reference.
+fn run(state: &mut State, bless: bool) -> Result<()> {
+    let mut failures = Vec::new();
+    for scene in SCENES {
+        let actual = render(state, scene)
+            .with_context(|| format!("scene {}", scene.name))?;
+        let path = reference_dir().join(format!("{}.png", scene.name));
+        if bless {
+            std::fs::create_dir_all(reference_dir())?;
+            actual
+                .save(&path)
+                .with_context(|| format!("can't write {}", path.display()))?;
+            continue;
+        }
+        let reference = image::open(&path)
+            .with_context(|| format!("can't read {}", path.display()))?
+            .to_rgba8();
+        let problem = if reference.dimensions() != actual.dimensions() {
+            format!("size is {:?}", reference.dimensions())
+        } else {
+            let comparison = compare(&reference, &actual);
+            if comparison.passes() {
+                continue;
+            }
+            std::fs::create_dir_all(failure_dir())?;
+            let diff_path =
+                failure_dir().join(format!("{}.diff.png", scene.name));
+            comparison.diff.save(&diff_path)?;
+            format!("{} pixels differ", comparison.differing)
+        };
+        std::fs::create_dir_all(failure_dir())?;
+        actual.save(failure_dir().join(format!("{}.png", scene.name)))?;
+        failures.push(format!("{}: {}", scene.name, problem));
+    }
+    if !failures.is_empty() {
+        bail!(
+            "golden images differ ({} has the results):\n  {}",
+            failure_dir().display(),
+            failures.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+pub fn run_tool(args: &[String]) -> Result<()> {
+    let bless = match args {
+        [] => false,
+        [command] if command == "bless" => true,
+        _ => bail!("usage: golden [bless]"),
+    };
+    let headless = State::new_headless(WIDTH, HEIGHT, true);
+    let mut state = pollster::block_on(headless)?;
+    run(&mut state, bless)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tolerates_small_differences() {
+        let reference = RgbaImage::from_pixel(10, 10, image::Rgba([90; 4]));
+        let mut actual = reference.clone();
+        actual.put_pixel(0, 0, image::Rgba([95, 92, 88, 255]));
+        assert_eq!(compare(&reference, &actual).differing, 0);
+        actual.put_pixel(1, 1, image::Rgba([90, 140, 90, 255]));
+        let comparison = compare(&reference, &actual);
+        assert_eq!(comparison.differing, 1);
+        assert_eq!(comparison.diff.get_pixel(1, 1).0, [255, 0, 0, 255]);
+        assert!(!comparison.passes());
+    }
+
+    #[test]
+    fn scenes_match_references() {
+        let headless = State::new_headless(WIDTH, HEIGHT, true);
+        let mut state = match pollster::block_on(headless) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("skipping golden images: {:#}", e);
+                return;
+            }
+        };
+        run(&mut state, false).unwrap();
+    }
+}
@@ -0,0 +1,523 @@
+// LED animations read from image files.
+//
+// An animation is either
+//  - an animated GIF or APNG,
+//  - a still PNG (or any other format the `image` crate reads), or
+//  - a directory of numbered PNGs, played in numeric order.
+//
+// Each frame uses one of three layouts.  The sizes are for 64x64
+// panels; they scale with the panel size.
+//  - strip: 384x64, the six faces side by side exactly as `Blinky`
+//    uploads them (face 5 at the left, face 0 at the right).
+//  - grid: 192x128, faces 0, 1, 2 in the top row and 3, 4, 5 in the
+//    bottom row.
+//  - per-face: a directory holding six 64x64 animations named
+//    face0 .. face5 (e.g., face0.gif or a face0/ directory of PNGs).
+//    Each face loops on its own timing.
+//
+// Face numbers are the ones `CubeModel` uses: left, front, right,
+// bottom, back, top.
+
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::{AnimationDecoder, RgbaImage};
+
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::test_pattern::{PixelArray, FACE_COUNT};
+use crate::wiring::wiring;
+
+// Used for numbered PNGs, which have no timing, and for GIF frames
+// whose delay is zero.
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(33);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Layout {
+    Strip,
+    Grid,
+    Face(usize),
+}
+
+impl Layout {
+    // width, height
+    fn size(self) -> (u32, u32) {
+        let panel = panel();
+        let (width, height) = (panel.width as u32, panel.height as u32);
+        match self {
+            Layout::Strip => (FACE_COUNT as u32 * width, height),
+            Layout::Grid => (3 * width, 2 * height),
+            Layout::Face(_) => (width, height),
+        }
+    }
+}
+
+// One timed image sequence.  `ends[i]` is the time at which frame `i`
+// stops being shown, measured from the start of the loop.
+struct Track {
+    layout: Layout,
+    frames: Vec<RgbaImage>,
+    ends: Vec<Duration>,
+}
+
+impl Track {
+    fn duration(&self) -> Duration {
+        *self.ends.last().unwrap()
+    }
+
+    fn frame_at(&self, time: Duration) -> &RgbaImage {
+        let total = self.duration().as_nanos();
+        let t = match total {
+            0 => 0,
+            _ => time.as_nanos() % total,
+        };
+        let index = self.ends.partition_point(|end| end.as_nanos() <= t);
+        &self.frames[index.min(self.frames.len() - 1)]
+    }
+}
+
+pub struct ImageSequence {
+    name: String,
+    tracks: Rc<Vec<Track>>,
+    data: Vec<u8>,
+}
+
+impl ImageSequence {
+    pub fn load(path: &Path) -> Result<Self> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let tracks = load_tracks(path)
+            .with_context(|| format!("can't load {}", path.display()))?;
+        Ok(Self::from_tracks(name, Rc::new(tracks)))
+    }
+
+    fn from_tracks(name: String, tracks: Rc<Vec<Track>>) -> Self {
+        let mut new = Self {
+            name,
+            tracks,
+            data: vec![0; panel().bytes()],
+        };
+        new.compose(Duration::ZERO);
+        new
+    }
+
+    fn compose(&mut self, time: Duration) {
+        let (width, height) = (panel().width, panel().height);
+        for track in self.tracks.iter() {
+            let image = track.frame_at(time);
+            match track.layout {
+                Layout::Strip => {
+                    for (x, y, pixel) in image.enumerate_pixels() {
+                        let (face, col) =
+                            (x as usize / width, x as usize % width);
+                        // The strip is already laid out as a frame.
+                        let face = wiring().face_at(face);
+                        put(&mut self.data, face, y as usize, col, pixel);
+                    }
+                }
+                Layout::Grid => {
+                    for (x, y, pixel) in image.enumerate_pixels() {
+                        let (x, y) = (x as usize, y as usize);
+                        let face = 3 * (y / height) + x / width;
+                        let (row, col) = (y % height, x % width);
+                        put(&mut self.data, face, row, col, pixel);
+                    }
+                }
+                Layout::Face(face) => {
+                    for (x, y, pixel) in image.enumerate_pixels() {
+                        put(
+                            &mut self.data,
+                            face,
+                            y as usize,
+                            x as usize,
+                            pixel,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl PatternSource for ImageSequence {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        self.compose(info.time);
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// Load each animation and register it under its file stem.  Errors are
+// reported and the offending file is skipped.
+pub fn register_files<I>(registry: &mut PatternRegistry, paths: I)
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    for path in paths {
+        match ImageSequence::load(&path) {
+            Ok(sequence) => {
+                let name = sequence.name.clone();
+                if registry.index_of(&name).is_some() {
+                    eprintln!(
+                        "{}: a pattern named \"{}\" already exists",
+                        path.display(),
+                        name,
+                    );
+                    continue;
+                }
+                let tracks = sequence.tracks.clone();
+                let source_name = name.clone();
+                registry.register(&name, move || {
+                    Box::new(ImageSequence::from_tracks(
+                        source_name.clone(),
+                        tracks.clone(),
+                    ))
+                });
+            }
+            Err(e) => eprintln!("{:#}", e),
+        }
+    }
+}
+
+// Write one pixel in the layout `Blinky` uploads.  Transparent pixels
+// are dark.
+fn put(
+    data: &mut PixelArray,
+    face: usize,
+    row: usize,
+    col: usize,
+    pixel: &image::Rgba<u8>,
+) {
+    let i = panel().index(face, row, col);
+    let [r, g, b, a] = pixel.0;
+    let scale = |c: u8| (c as u16 * a as u16 / 255) as u8;
+    data[i] = scale(r);
+    data[i + 1] = scale(g);
+    data[i + 2] = scale(b);
+    data[i + 3] = 255;
+}
+
+fn load_tracks(path: &Path) -> Result<Vec<Track>> {
+    if path.is_dir() {
+        if let Some(face_paths) = find_face_paths(path)? {
+            return face_paths
+                .iter()
+                .enumerate()
+                .map(|(face, face_path)| {
+                    load_track(face_path, Some(face)).with_context(|| {
+                        format!("can't load {}", face_path.display())
+                    })
+                })
+                .collect();
+        }
+    }
+    Ok(vec![load_track(path, None)?])
+}
+
+// A per-face animation is a directory with entries named face0 ..
+// face5.  Returns `None` if there are no such entries.
+fn find_face_paths(dir: &Path) -> Result<Option<Vec<PathBuf>>> {
+    let mut faces: Vec<Option<PathBuf>> = vec![None; FACE_COUNT];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if let Some(face) = stem
+            .strip_prefix("face")
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n < FACE_COUNT)
+        {
+            if faces[face].is_some() {
+                bail!("more than one animation for face {}", face);
+            }
+            faces[face] = Some(path);
+        }
+    }
+    if faces.iter().all(|f| f.is_none()) {
+        return Ok(None);
+    }
+    faces
+        .into_iter()
+        .enumerate()
+        .map(|(face, f)| f.ok_or_else(|| anyhow!("face{} is missing", face)))
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+fn load_track(path: &Path, face: Option<usize>) -> Result<Track> {
+    let frames = if path.is_dir() {
+        load_numbered_pngs(path)?
+    } else {
+        load_animation_file(path)?
+    };
+    if frames.is_empty() {
+        bail!("no frames");
+    }
+
+    let size = frames[0].0.dimensions();
+    let (strip_size, grid_size) = (Layout::Strip.size(), Layout::Grid.size());
+    let layout = match face {
+        Some(face) => Layout::Face(face),
+        None if size == strip_size => Layout::Strip,
+        None if size == grid_size => Layout::Grid,
+        None => bail!(
+            "frames are {}x{}; expected {}x{} (strip) or {}x{} (3x2 grid)",
+            size.0,
+            size.1,
+            strip_size.0,
+            strip_size.1,
+            grid_size.0,
+            grid_size.1,
+        ),
+    };
+    let expected = layout.size();
+    let mut images = Vec::with_capacity(frames.len());
+    let mut ends = Vec::with_capacity(frames.len());
+    let mut end = Duration::ZERO;
+    for (i, (image, delay)) in frames.into_iter().enumerate() {
+        if image.dimensions() != expected {
+            bail!(
+                "frame {} is {}x{}; expected {}x{}",
+                i,
+                image.width(),
+                image.height(),
+                expected.0,
+                expected.1,
+            );
+        }
+        end += delay;
+        images.push(image);
+        ends.push(end);
+    }
+    Ok(Track {
+        layout,
+        frames: images,
+        ends,
+    })
+}
+
+fn load_animation_file(path: &Path) -> Result<Vec<(RgbaImage, Duration)>> {
+    let format = image::ImageFormat::from_path(path)?;
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let frames = match format {
+        image::ImageFormat::Gif => {
+            let decoder = image::codecs::gif::GifDecoder::new(reader)?;
+            decoder.into_frames().collect_frames()?
+        }
+        image::ImageFormat::Png => {
+            let decoder = image::codecs::png::PngDecoder::new(reader)?;
+            if decoder.is_apng() {
+                decoder.apng().into_frames().collect_frames()?
+            } else {
+                let image = image::DynamicImage::from_decoder(decoder)?;
+                return Ok(vec![(image.into_rgba8(), DEFAULT_FRAME_DELAY)]);
+            }
+        }
+        _ => {
+            let image = image::open(path)?;
+            return Ok(vec![(image.into_rgba8(), DEFAULT_FRAME_DELAY)]);
+        }
+    };
+    Ok(frames
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = match numer {
+                0 => DEFAULT_FRAME_DELAY,
+                _ => Duration::from_micros(1000 * numer as u64 / denom as u64),
+            };
+            (frame.into_buffer(), delay)
+        })
+        .collect())
+}
+
+fn load_numbered_pngs(dir: &Path) -> Result<Vec<(RgbaImage, Duration)>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|p| {
+        p.extension()
+            .map(|ext| ext.eq_ignore_ascii_case("png"))
+            .unwrap_or(false)
+    });
+    // Sort by the number in the name so that "frame10" follows "frame9".
+    let number = |p: &PathBuf| {
+        let stem = p.file_stem().unwrap().to_string_lossy();
+        let digits: String =
+            stem.chars().filter(|c| c.is_ascii_digit()).collect();
+        digits.parse::<u64>().ok()
+    };
+    paths.sort_by(|a, b| number(a).cmp(&number(b)).then(a.cmp(b)));
+
+    paths
+        .iter()
+        .map(|p| {
+            let image = image::open(p)
+                .with_context(|| format!("can't load {}", p.display()))?;
+            Ok((image.into_rgba8(), DEFAULT_FRAME_DELAY))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(face: usize) -> image::Rgba<u8> {
+        image::Rgba([40 * face as u8 + 20, 250 - 40 * face as u8, 0, 255])
+    }
+
+    // A frame in `layout` with each face filled with its color.
+    fn frame(layout: Layout) -> RgbaImage {
+        let (width, height) = (panel().width as u32, panel().height as u32);
+        let (w, h) = layout.size();
+        RgbaImage::from_fn(w, h, |x, y| match layout {
+            // Face 5 is at the left.
+            Layout::Strip => color(FACE_COUNT - 1 - (x / width) as usize),
+            Layout::Grid => color((3 * (y / height) + x / width) as usize),
+            Layout::Face(face) => color(face),
+        })
+    }
+
+    fn track(layout: Layout, frames: Vec<RgbaImage>) -> Track {
+        let ends = (1..=frames.len() as u32)
+            .map(|i| i * DEFAULT_FRAME_DELAY)
+            .collect();
+        Track {
+            layout,
+            frames,
+            ends,
+        }
+    }
+
+    fn shows_faces(sequence: &ImageSequence, faces: &[usize]) {
+        let panel = panel();
+        for &face in faces {
+            for (row, col) in [(0, 0), (panel.height - 1, panel.width - 1)] {
+                let i = panel.index(face, row, col);
+                let [r, g, b, a] = color(face).0;
+                assert_eq!(
+                    sequence.data[i..i + 4],
+                    [r, g, b, a],
+                    "face {} at {}, {}",
+                    face,
+                    row,
+                    col,
+                );
+            }
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cube-image-sequence-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn slices_each_layout() {
+        let all: Vec<usize> = (0..FACE_COUNT).collect();
+        for layout in [Layout::Strip, Layout::Grid] {
+            let tracks = vec![track(layout, vec![frame(layout)])];
+            let sequence =
+                ImageSequence::from_tracks("test".into(), Rc::new(tracks));
+            shows_faces(&sequence, &all);
+        }
+
+        let tracks = all
+            .iter()
+            .map(|&face| {
+                track(Layout::Face(face), vec![frame(Layout::Face(face))])
+            })
+            .collect();
+        let sequence =
+            ImageSequence::from_tracks("test".into(), Rc::new(tracks));
+        shows_faces(&sequence, &all);
+    }
+
+    #[test]
+    fn frames_follow_their_delays() {
+        let black = RgbaImage::new(1, 1);
+        let white = RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        let track = track(Layout::Face(0), vec![black.clone(), white.clone()]);
+        assert_eq!(track.frame_at(Duration::ZERO), &black);
+        assert_eq!(track.frame_at(DEFAULT_FRAME_DELAY), &white);
+        // and loops
+        assert_eq!(track.frame_at(3 * DEFAULT_FRAME_DELAY), &white);
+        assert_eq!(track.frame_at(4 * DEFAULT_FRAME_DELAY), &black);
+    }
+
+    #[test]
+    fn loads_gifs() {
+        let path = temp_dir("gif").join("strip.gif");
+        let (width, height) = Layout::Strip.size();
+        let black =
+            RgbaImage::from_pixel(width, height, image::Rgba([0, 0, 0, 255]));
+        let delay = image::Delay::from_numer_denom_ms(100, 1);
+        let frames = [frame(Layout::Strip), black]
+            .map(|image| image::Frame::from_parts(image, 0, 0, delay));
+        let file = std::fs::File::create(&path).unwrap();
+        image::codecs::gif::GifEncoder::new(file)
+            .encode_frames(frames)
+            .unwrap();
+
+        let mut sequence = ImageSequence::load(&path).unwrap();
+        assert_eq!(sequence.name, "strip");
+        let track = &sequence.tracks[0];
+        assert_eq!(track.layout, Layout::Strip);
+        assert_eq!(track.duration(), Duration::from_millis(200));
+        shows_faces(&sequence, &(0..FACE_COUNT).collect::<Vec<_>>());
+        sequence.compose(Duration::from_millis(150));
+        let i = panel().index(0, 0, 0);
+        assert_eq!(sequence.data[i..i + 4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn loads_numbered_pngs_per_face() {
+        let dir = temp_dir("faces");
+        for face in 0..FACE_COUNT {
+            if face != 2 {
+                let path = dir.join(format!("face{}.png", face));
+                frame(Layout::Face(face)).save(path).unwrap();
+            }
+        }
+        // face2 is a directory of PNGs; "frame10" comes after "frame9".
+        let numbered = dir.join("face2");
+        std::fs::create_dir(&numbered).unwrap();
+        let blank = RgbaImage::new(panel().width as u32, panel().height as u32);
+        frame(Layout::Face(2))
+            .save(numbered.join("frame9.png"))
+            .unwrap();
+        blank.save(numbered.join("frame10.png")).unwrap();
+
+        let mut sequence = ImageSequence::load(&dir).unwrap();
+        shows_faces(&sequence, &(0..FACE_COUNT).collect::<Vec<_>>());
+        assert_eq!(sequence.tracks[2].frames.len(), 2);
+        sequence.compose(DEFAULT_FRAME_DELAY);
+        shows_faces(&sequence, &[0, 1, 3, 4, 5]);
+        let i = panel().index(2, 0, 0);
+        assert_eq!(sequence.data[i..i + 4], [0, 0, 0, 255]);
+
+        std::fs::remove_file(dir.join("face5.png")).unwrap();
+        let error = ImageSequence::load(&dir).err().unwrap();
+        assert!(format!("{:#}", error).contains("face5 is missing"));
+    }
+}
@@ -0,0 +1,415 @@
+// Cube orientation from the physical cube's IMU.
+//
+// Samples are timestamped orientation quaternions that rotate cube
+// space into world space (y up).  They come from a file or a UDP socket.
+//  - CSV:        t,w,x,y,z           (a header line is allowed)
+//  - JSON lines: {"t": 0.5, "w": 1, "x": 0, "y": 0, "z": 0}
+// t is in seconds.  A file plays at its recorded rate, interpolating
+// between samples, and loops.  A stream shows its latest sample,
+// smoothed, and a datagram may hold several lines.
+//
+// Dragging with the mouse takes over from the feed.  The feed takes
+// back over a little while after the button is released.
+
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use cgmath::Quaternion;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+use crate::prelude::*;
+use crate::trackball::{Manipulable, Responder, Trackball};
+
+// How long the mouse keeps control after a drag, and how long the feed
+// then takes to ease back in.
+const MOUSE_HOLD: Duration = Duration::from_secs(2);
+const MOUSE_RELEASE: Duration = Duration::from_secs(1);
+
+pub const DEFAULT_SMOOTHING: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub time: Duration,
+    pub orientation: Quaternion<f32>,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonSample {
+    t: f64,
+    w: f32,
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+// Parse one CSV or JSON line.  Blank lines, comments and the CSV
+// header give None.
+pub fn parse_line(line: &str) -> Result<Option<Sample>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (t, w, x, y, z) = if line.starts_with('{') {
+        let s: JsonSample = serde_json::from_str(line)?;
+        (s.t, s.w, s.x, s.y, s.z)
+    } else {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [t, w, x, y, z] = fields[..] else {
+            anyhow::bail!("expected t,w,x,y,z");
+        };
+        let Ok(t) = t.parse::<f64>() else {
+            return Ok(None); // the header
+        };
+        let number = |s: &str| s.parse::<f32>();
+        (t, number(w)?, number(x)?, number(y)?, number(z)?)
+    };
+    let q = Quaternion::new(w, x, y, z);
+    if !(t >= 0.0 && q.magnitude() > 1e-6) {
+        anyhow::bail!("bad sample");
+    }
+    Ok(Some(Sample {
+        time: Duration::from_secs_f64(t),
+        orientation: q.normalize(),
+    }))
+}
+
+// A recorded feed, sorted by time.
+pub struct Recording {
+    samples: Vec<Sample>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("{}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut samples = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let sample =
+                parse_line(line).with_context(|| format!("line {}", i + 1))?;
+            samples.extend(sample);
+        }
+        samples.sort_by_key(|s| s.time);
+        let Some(first) = samples.first().map(|s| s.time) else {
+            anyhow::bail!("no samples");
+        };
+        for sample in &mut samples {
+            sample.time -= first;
+        }
+        Ok(Self { samples })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.samples.last().unwrap().time
+    }
+
+    // The orientation `elapsed` after the start, looping at the end.
+    pub fn orientation_at(&self, elapsed: Duration) -> Quaternion<f32> {
+        let duration = self.duration();
+        if duration.is_zero() {
+            return self.samples[0].orientation;
+        }
+        let t = Duration::from_nanos(
+            (elapsed.as_nanos() % duration.as_nanos()) as u64,
+        );
+        let next = self.samples.partition_point(|s| s.time <= t);
+        let (a, b) = (&self.samples[next - 1], &self.samples[next]);
+        let span = (b.time - a.time).as_secs_f32();
+        let amount = (t - a.time).as_secs_f32() / span;
+        a.orientation.slerp(b.orientation, amount)
+    }
+}
+
+enum Feed {
+    File {
+        recording: Recording,
+        start: Instant,
+    },
+    Stream {
+        address: SocketAddr,
+        latest: Arc<Mutex<Option<Quaternion<f32>>>>,
+        smoothing: Duration, // time constant
+        smoothed: Option<Quaternion<f32>>,
+        last_time: Instant,
+    },
+}
+
+pub struct ImuFeed {
+    feed: Feed,
+    trackball: Trackball,
+    mouse_pressed: bool,
+    mouse_released: Option<Instant>, // None until the first drag
+}
+
+impl ImuFeed {
+    pub fn play(
+        path: &Path,
+        viewport_size: &PhysicalSize<u32>,
+    ) -> Result<Self> {
+        let feed = Feed::File {
+            recording: Recording::load(path)?,
+            start: Instant::now(),
+        };
+        Ok(Self::new(feed, viewport_size))
+    }
+
+    // Listen for samples on `address`.  `smoothing` is the time
+    // constant of the filter; zero shows each sample as it arrives.
+    pub fn listen(
+        address: &str,
+        smoothing: Duration,
+        viewport_size: &PhysicalSize<u32>,
+    ) -> Result<Self> {
+        let socket = UdpSocket::bind(address)
+            .with_context(|| format!("can't listen on {}", address))?;
+        let address = socket.local_addr()?;
+        let latest = Arc::new(Mutex::new(None));
+        let thread_latest = latest.clone();
+        std::thread::Builder::new()
+            .name("imu".into())
+            .spawn(move || receive(socket, thread_latest))?;
+        let feed = Feed::Stream {
+            address,
+            latest,
+            smoothing,
+            smoothed: None,
+            last_time: Instant::now(),
+        };
+        Ok(Self::new(feed, viewport_size))
+    }
+
+    // Where a stream is received.
+    pub fn address(&self) -> Option<SocketAddr> {
+        match self.feed {
+            Feed::File { .. } => None,
+            Feed::Stream { address, .. } => Some(address),
+        }
+    }
+
+    fn new(feed: Feed, viewport_size: &PhysicalSize<u32>) -> Self {
+        Self {
+            feed,
+            trackball: Trackball::new(viewport_size),
+            mouse_pressed: false,
+            mouse_released: None,
+        }
+    }
+
+    fn feed_orientation(&mut self, t: Instant) -> Quaternion<f32> {
+        match &mut self.feed {
+            Feed::File { recording, start } => {
+                recording.orientation_at(t.saturating_duration_since(*start))
+            }
+            Feed::Stream {
+                latest,
+                smoothing,
+                smoothed,
+                last_time,
+                ..
+            } => {
+                let dt = t.saturating_duration_since(*last_time);
+                *last_time = t;
+                let Some(latest) = *latest.lock().unwrap() else {
+                    return Quaternion::one();
+                };
+                let current = smoothed.get_or_insert(latest);
+                let amount = if smoothing.is_zero() {
+                    1.0
+                } else {
+                    1.0 - (-dt.as_secs_f32() / smoothing.as_secs_f32()).exp()
+                };
+                *current = current.slerp(latest, amount);
+                *current
+            }
+        }
+    }
+}
+
+fn receive(socket: UdpSocket, latest: Arc<Mutex<Option<Quaternion<f32>>>>) {
+    let mut buffer = [0; 65536];
+    loop {
+        let count = match socket.recv(&mut buffer) {
+            Ok(count) => count,
+            Err(e) => {
+                eprintln!("IMU: {}", e);
+                return;
+            }
+        };
+        let text = String::from_utf8_lossy(&buffer[..count]);
+        for line in text.lines() {
+            match parse_line(line) {
+                Ok(Some(sample)) => {
+                    *latest.lock().unwrap() = Some(sample.orientation)
+                }
+                Ok(None) => (),
+                Err(e) => eprintln!("IMU: {:#}", e),
+            }
+        }
+    }
+}
+
+impl ImuFeed {
+    // The drag starts from wherever the feed has the cube.
+    fn take_over(&mut self, t: Instant) {
+        let current = self.quaternion(t);
+        self.trackball.set_orientation(current);
+        self.mouse_pressed = true;
+    }
+
+    fn release(&mut self, t: Instant) {
+        self.mouse_pressed = false;
+        self.mouse_released = Some(t);
+    }
+
+    fn quaternion(&mut self, t: Instant) -> Quaternion<f32> {
+        let feed = self.feed_orientation(t);
+        let mouse_amount = match self.mouse_released {
+            _ if self.mouse_pressed => 1.0,
+            None => return feed,
+            Some(released) => {
+                let since = t.saturating_duration_since(released);
+                let fading = since.saturating_sub(MOUSE_HOLD);
+                1.0 - fading.as_secs_f32() / MOUSE_RELEASE.as_secs_f32()
+            }
+        };
+        if mouse_amount <= 0.0 {
+            return feed;
+        }
+        feed.slerp(self.trackball.quaternion(t), mouse_amount)
+    }
+}
+
+impl Manipulable for ImuFeed {
+    fn set_viewport_size(&mut self, size: &PhysicalSize<u32>) {
+        self.trackball.set_viewport_size(size);
+    }
+
+    fn mouse_down(&mut self, pos: &PhysicalPosition<f64>, t: Instant) {
+        self.take_over(t);
+        self.trackball.mouse_down(pos, t);
+    }
+
+    fn mouse_drag(&mut self, pos: &PhysicalPosition<f64>, t: Instant) {
+        self.trackball.mouse_drag(pos, t);
+    }
+
+    fn mouse_up(&mut self, t: Instant) {
+        self.release(t);
+        self.trackball.mouse_up(t);
+    }
+
+    fn orientation(&mut self, t: Instant) -> Mat4 {
+        self.quaternion(t).into()
+    }
+
+    fn start_export(&mut self, t: Instant) {
+        self.trackball.start_export(t);
+    }
+}
+
+impl Responder for ImuFeed {
+    // The trackball tracks the mouse, but the button is seen here
+    // first so the drag starts from the feed's orientation.
+    fn handle_window_event(&mut self, evt: &WindowEvent) -> bool {
+        if let WindowEvent::MouseInput {
+            button: MouseButton::Left,
+            state,
+            ..
+        } = evt
+        {
+            let now = Instant::now();
+            match state {
+                ElementState::Pressed if !self.mouse_pressed => {
+                    self.take_over(now)
+                }
+                ElementState::Released if self.mouse_pressed => {
+                    self.release(now)
+                }
+                _ => (),
+            }
+        }
+        self.trackball.handle_window_event(evt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Rotation3};
+
+    fn about_y(degrees: f32) -> Quaternion<f32> {
+        Quaternion::from_angle_y(Deg(degrees))
+    }
+
+    fn assert_near(a: Quaternion<f32>, b: Quaternion<f32>) {
+        assert!(a.dot(b).abs() > 0.9999, "{:?} {:?}", a, b);
+    }
+
+    #[test]
+    fn parses_csv_and_json() {
+        let q = about_y(90.0);
+        let csv = format!(
+            "t,w,x,y,z\n1.5, {}, {}, {}, {}\n",
+            q.s, q.v.x, q.v.y, q.v.z
+        );
+        let json = format!(
+            r#"{{"t": 1.5, "w": {}, "x": {}, "y": {}, "z": {}}}"#,
+            q.s, q.v.x, q.v.y, q.v.z
+        );
+        for text in [csv.as_str(), json.as_str()] {
+            let samples: Vec<Sample> = text
+                .lines()
+                .filter_map(|line| parse_line(line).unwrap())
+                .collect();
+            assert_eq!(samples.len(), 1);
+            assert_eq!(samples[0].time, Duration::from_millis(1500));
+            assert_near(samples[0].orientation, q);
+        }
+        assert!(parse_line("1, 2, 3").is_err());
+        assert!(parse_line("1, 0, 0, 0, 0").is_err());
+    }
+
+    #[test]
+    fn recording_interpolates_and_loops() {
+        let recording = Recording::parse(
+            "# a quarter turn and back\n\
+             10, 1, 0, 0, 0\n\
+             11, 0.70710677, 0, 0.70710677, 0\n\
+             12, 1, 0, 0, 0\n",
+        )
+        .unwrap();
+        assert_eq!(recording.duration(), Duration::from_secs(2));
+        let at = |ms| recording.orientation_at(Duration::from_millis(ms));
+        assert_near(at(0), about_y(0.0));
+        assert_near(at(500), about_y(45.0));
+        assert_near(at(1000), about_y(90.0));
+        assert_near(at(1750), about_y(22.5));
+        assert_near(at(2500), about_y(45.0));
+    }
+
+    #[test]
+    fn stream_shows_latest_sample() {
+        let size = PhysicalSize::new(100, 100);
+        let mut feed =
+            ImuFeed::listen("127.0.0.1:0", Duration::ZERO, &size).unwrap();
+        let address = feed.address().unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .send_to(b"0, 1, 0, 0, 0\n1, 0, 0, 1, 0\n", address)
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let expected = about_y(180.0);
+        while feed.quaternion(Instant::now()).dot(expected).abs() < 0.9999 {
+            assert!(Instant::now() < deadline, "no sample arrived");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
@@ -0,0 +1,18 @@
+// A red bar across the top of the window.  Built in, so it works
+// whatever state the hot-reloaded shaders are in.
+
+const BAR_HEIGHT: f32 = 0.02; // in clip space
+
+@vertex
+fn vs_indicator_main(
+    @builtin(vertex_index) index: u32,
+) -> @builtin(position) vec4<f32> {
+    let x = f32(index & 1u) * 2.0 - 1.0;
+    let y = 1.0 - f32(index >> 1u) * BAR_HEIGHT;
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_indicator_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,153 @@
+// Where each LED is.
+//
+// The table holds every LED's center and outward normal in cube space,
+// in mm, derived from `CubeModel`'s face transforms.  Effects that are
+// defined in 3D (planes, spheres, noise fields) can iterate over it and
+// wrap across face edges for free.
+//
+//     for led in leds::table() {
+//         let d = (led.position - center).magnitude();
+//         data[led.index] = ...;  // red
+//     }
+
+use std::sync::OnceLock;
+
+use crate::cube_model::{CubeModel, FACE_LENGTH_MM};
+use crate::panel::{panel, Panel};
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Led {
+    pub face: usize,
+    pub row: usize,
+    pub col: usize,
+    pub id: usize,    // `Panel::led_id`, for per-LED arrays
+    pub index: usize, // of the red channel in a `PixelArray`
+    pub position: Point3,
+    pub normal: Vec3,
+}
+
+pub struct LedTable {
+    panel: Panel,
+    leds: Vec<Led>,
+}
+
+impl LedTable {
+    pub fn new(panel: Panel) -> Self {
+        const HFL: f32 = FACE_LENGTH_MM / 2.0; // half face length
+        let (pitch_x, pitch_y) = panel.pitch_mm();
+        let mut leds = Vec::with_capacity(panel.led_count());
+        for (face, xform) in CubeModel::make_face_xforms().iter().enumerate() {
+            let normal = (xform * Vec3::unit_z().extend(0.0)).truncate();
+            for row in 0..panel.height {
+                for col in 0..panel.width {
+                    // Same as the face decal: row 0 at +y, column 0 at -x.
+                    let x = (col as f32 + 0.5) * pitch_x - HFL;
+                    let y = HFL - (row as f32 + 0.5) * pitch_y;
+                    let p = xform * Point3::new(x, y, 0.0).to_homogeneous();
+                    leds.push(Led {
+                        face,
+                        row,
+                        col,
+                        id: panel.led_id(face, row, col),
+                        index: panel.index(face, row, col),
+                        position: Point3::from_homogeneous(p),
+                        normal,
+                    });
+                }
+            }
+        }
+        Self { panel, leds }
+    }
+
+    pub fn get(&self, face: usize, row: usize, col: usize) -> &Led {
+        &self.leds[self.panel.led_id(face, row, col)]
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Led> {
+        self.leds.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a LedTable {
+    type Item = &'a Led;
+    type IntoIter = std::slice::Iter<'a, Led>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// The table is the same for every pattern, so it is built once.
+pub fn table() -> &'static LedTable {
+    static TABLE: OnceLock<LedTable> = OnceLock::new();
+    TABLE.get_or_init(|| LedTable::new(panel()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube_model::FACE_DISPLACEMENT_MM;
+    use crate::topology::topology;
+
+    const EPSILON: f32 = 1e-3;
+
+    #[test]
+    fn leds_lie_on_their_faces() {
+        const HFL: f32 = FACE_LENGTH_MM / 2.0; // half face length
+        let topology = topology();
+        for led in table() {
+            let normal = led.normal;
+            assert!((normal.magnitude() - 1.0).abs() < EPSILON, "{:?}", led);
+            assert!((normal - topology.normal(led.face)).magnitude() < EPSILON);
+            // Out from the center, on the face's plane, inside its
+            // square.
+            let p = led.position.to_vec();
+            let height = p.dot(normal);
+            assert!(
+                (height - (HFL + FACE_DISPLACEMENT_MM)).abs() < EPSILON,
+                "{:?}",
+                led
+            );
+            let across = p - height * normal;
+            for c in [across.x, across.y, across.z] {
+                assert!(c.abs() < HFL, "{:?}", led);
+            }
+        }
+    }
+
+    #[test]
+    fn indices_match_the_panel() {
+        let panel = panel();
+        let table = table();
+        let mut seen = vec![false; panel.bytes()];
+        for led in table {
+            let (face, row, col) = (led.face, led.row, led.col);
+            assert_eq!(led.index, panel.index(face, row, col));
+            assert_eq!(led.id, panel.led_id(face, row, col));
+            assert_eq!(table.get(face, row, col).id, led.id);
+            assert!(!std::mem::replace(&mut seen[led.index], true));
+        }
+        assert_eq!(table.iter().count(), panel.led_count());
+    }
+
+    #[test]
+    fn neighbors_are_a_pitch_apart() {
+        let panel = panel();
+        let (pitch_x, pitch_y) = panel.pitch_mm();
+        let table = table();
+        for led in table {
+            let (face, row, col) = (led.face, led.row, led.col);
+            if col + 1 < panel.width {
+                let right = table.get(face, row, col + 1).position;
+                let distance = (right - led.position).magnitude();
+                assert!((distance - pitch_x).abs() < EPSILON, "{:?}", led);
+            }
+            if row + 1 < panel.height {
+                let below = table.get(face, row + 1, col).position;
+                let distance = (below - led.position).magnitude();
+                assert!((distance - pitch_y).abs() < EPSILON, "{:?}", led);
+            }
+        }
+    }
+}
@@ -2,6 +2,7 @@ use wgpu::util::*;
 
 use crate::prelude::*;
 use crate::traits::Renderable;
+use crate::tweaks;
 
 // Light types
 //  - ambient
@@ -322,6 +323,52 @@ impl Lights {
             }
     }
 
+    // The lights' intensities and colors, to tweak.  See tweaks.rs.
+    pub fn tweaks(&self) -> Vec<tweaks::Light> {
+        self.lights
+            .iter()
+            .map(|light| {
+                let (Light::Ambient {
+                    intensity, color, ..
+                }
+                | Light::Directional {
+                    intensity, color, ..
+                }) = light;
+                tweaks::Light {
+                    intensity: *intensity,
+                    color: (*color).into(),
+                }
+            })
+            .collect()
+    }
+
+    // Set the first lights' intensities and colors.  The uniform is
+    // written every frame, so they take effect on the next.
+    pub fn set_tweaks(&mut self, tweaks: &[tweaks::Light]) {
+        for (light, tweak) in self.lights.iter_mut().zip(tweaks) {
+            let (Light::Ambient { intensity, color }
+            | Light::Directional {
+                intensity, color, ..
+            }) = light;
+            *intensity = tweak.intensity;
+            *color = tweak.color.into();
+        }
+    }
+
+    // Each shadow casting light's projection from the world to its
+    // shadow map, and its color.
+    pub fn shadow_projections(&self) -> Vec<(Mat4, Vec3)> {
+        self.lights
+            .iter()
+            .filter_map(|light| match light {
+                Light::Ambient { .. } => None,
+                Light::Directional { color, .. } => {
+                    Some((light.create_projection(), *color))
+                }
+            })
+            .collect()
+    }
+
     pub fn light_shadow_view(&self, light_index: usize) -> &wgpu::TextureView {
         &self.shadow_target_views[light_index]
     }
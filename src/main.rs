@@ -1,4 +1,5 @@
-use std::{sync::Arc};
+use anyhow::Context;
+use std::{collections::HashMap, sync::Arc};
 use wgpu;
 use winit::{
     application::ApplicationHandler,
@@ -14,45 +15,59 @@ mod bounds;
 mod camera;
 mod cube;
 mod cube_model;
+mod debug_view;
+mod dmx;
+mod effects;
+mod export;
 mod floor;
+mod frame_graph;
 mod glow;
+mod golden;
+mod image_sequence;
+mod imu;
+mod indicator;
+mod leds;
 mod lights;
+mod offscreen;
+mod opc;
+mod overlays;
+mod panel;
+mod pattern;
+mod pattern_shader;
 mod post;
 mod prefloor;
 mod prelude;
+mod profiler;
+mod recording;
+mod settings;
+mod shaders;
+mod shading;
 // mod splitter;
+mod stats;
 mod test_pattern;
 mod texture;
+mod topology;
 mod trackball;
 mod traits;
+mod tweak_panel;
+mod tweaks;
+mod wiring;
 
 use prelude::*;
-use trackball::{Manipulable, Responder};
+use settings::settings;
 use traits::Renderable;
 
-const BACKFACE_CULL: bool = true;
-const ALPHA_BLENDING: bool = false;
-const SAMPLE_COUNT: u32 = 4; // 4 => MSAA, 1 => no MSAA
-const PRINT_FPS: bool = true;
-const DO_HDR_POSTPROCESSING: bool = true;
 pub const LDR_COLOR_PIXEL_FORMAT: wgpu::TextureFormat =
     wgpu::TextureFormat::Rgba8Unorm;
 pub const BRIGHT_COLOR_PIXEL_FORMAT: wgpu::TextureFormat =
     wgpu::TextureFormat::Rgba16Float;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Hand {
     Left,
     Right,
 }
-const WORLD_HANDEDNESS: Hand = Hand::Right;
-
-const BACKGROUND_COLOR: wgpu::Color = wgpu::Color {
-    r: 0.00250,
-    g: 0.00625,
-    b: 0.01500,
-    a: 1.0,
-};
 
 pub fn print_type_of<T>(_: &T) {
     println!("{}", std::any::type_name::<T>());
@@ -64,25 +79,31 @@ fn create_forward_render_pipeline(
     layout: &wgpu::PipelineLayout,
     color_format: wgpu::TextureFormat,
     depth_format: Option<wgpu::TextureFormat>,
+    sample_count: u32,
     vertex_layouts: &[wgpu::VertexBufferLayout],
     shader: &wgpu::ShaderModule,
+    constants: &[(&str, f64)],
     vertex_entry: &str,
     fragment_entry: &str,
 ) -> wgpu::RenderPipeline {
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants,
+        ..Default::default()
+    };
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some(label),
         layout: Some(layout),
         vertex: wgpu::VertexState {
             module: &shader,
             entry_point: Some(vertex_entry),
-            compilation_options: Default::default(),
+            compilation_options: compilation_options.clone(),
             buffers: vertex_layouts,
         },
         primitive: wgpu::PrimitiveState {
             topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: match BACKFACE_CULL {
+            cull_mode: match settings().backface_cull {
                 true => Some(wgpu::Face::Back),
                 false => None,
             },
@@ -93,7 +114,7 @@ fn create_forward_render_pipeline(
         depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
             format,
             depth_write_enabled: true,
-            depth_compare: match WORLD_HANDEDNESS {
+            depth_compare: match settings().handedness {
                 Hand::Left => wgpu::CompareFunction::Less,
                 Hand::Right => wgpu::CompareFunction::Greater,
             },
@@ -101,18 +122,18 @@ fn create_forward_render_pipeline(
             bias: wgpu::DepthBiasState::default(),
         }),
         multisample: wgpu::MultisampleState {
-            count: SAMPLE_COUNT,
+            count: sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
         fragment: Some(wgpu::FragmentState {
             module: &shader,
             entry_point: Some(fragment_entry),
-            compilation_options: Default::default(),
+            compilation_options,
             targets: &[
                 Some(wgpu::ColorTargetState {
                     format: color_format,
-                    blend: Some(match ALPHA_BLENDING {
+                    blend: Some(match settings().alpha_blending {
                         true => wgpu::BlendState::ALPHA_BLENDING,
                         false => wgpu::BlendState::REPLACE,
                     }),
@@ -183,14 +204,120 @@ fn create_shadow_render_pipeline(
     })
 }
 
+// Every pipeline made from the common shader for one set of shading
+// options.
+struct ScenePipelines {
+    cube_face_forward: wgpu::RenderPipeline,
+    cube_edge_forward: wgpu::RenderPipeline,
+    floor_forward: wgpu::RenderPipeline,
+    cube_face_shadow: wgpu::RenderPipeline,
+    cube_edge_shadow: wgpu::RenderPipeline,
+    floor_shadow: wgpu::RenderPipeline,
+    prefloor: wgpu::RenderPipeline,
+}
+
+// `address` is a port number or host:port.
+fn start_opc_server(address: &str) -> anyhow::Result<opc::OpcServer> {
+    let address = match address.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{}", port),
+        Err(_) if address.is_empty() => {
+            format!("127.0.0.1:{}", opc::DEFAULT_PORT)
+        }
+        Err(_) => address.to_string(),
+    };
+    let map = match &settings().opc_channels {
+        Some(spec) => opc::ChannelMap::parse(spec)?,
+        None => opc::ChannelMap::default(),
+    };
+    opc::OpcServer::start(&address, map)
+}
+
+// The dmx_universe setting sets the first universe and dmx_order the
+// channel order, e.g. "grb".
+fn start_dmx_receiver(spec: &str) -> anyhow::Result<dmx::DmxReceiver> {
+    let mut config = dmx::Configuration::parse(spec)?;
+    if let Some(universe) = settings().dmx_universe {
+        config.first_universe = universe;
+    }
+    if let Some(order) = &settings().dmx_order {
+        config.pixel_order = dmx::PixelOrder::parse(order)?;
+    }
+    dmx::DmxReceiver::start(config)
+}
+
+// The imu_smoothing setting is the time constant, in seconds, for
+// smoothing a UDP feed.
+fn start_imu_feed(
+    spec: &str,
+    size: &winit::dpi::PhysicalSize<u32>,
+) -> anyhow::Result<imu::ImuFeed> {
+    let Some(address) = spec.strip_prefix("udp:") else {
+        return imu::ImuFeed::play(std::path::Path::new(spec), size);
+    };
+    let address = match address.parse::<u16>() {
+        Ok(port) => format!("0.0.0.0:{}", port),
+        Err(_) => address.to_string(),
+    };
+    let smoothing = match settings().imu_smoothing {
+        Some(seconds) => std::time::Duration::from_secs_f32(seconds),
+        None => imu::DEFAULT_SMOOTHING,
+    };
+    imu::ImuFeed::listen(&address, smoothing, size)
+}
+
+// What the forward pass renders into, given the surface's format.
+fn forward_color_format(
+    surface_format: wgpu::TextureFormat,
+) -> wgpu::TextureFormat {
+    match settings().hdr_postprocessing {
+        true => LDR_COLOR_PIXEL_FORMAT,
+        false => surface_format,
+    }
+}
+
+// Check that the adapter can multisample each of the forward pass's
+// targets `sample_count` times.  Returns the features the device needs
+// for that: counts other than 1 and 4 are adapter-specific.
+fn check_sample_count(
+    adapter: &wgpu::Adapter,
+    surface_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> anyhow::Result<wgpu::Features> {
+    let formats = [
+        forward_color_format(surface_format),
+        BRIGHT_COLOR_PIXEL_FORMAT,
+        texture::Texture::DEPTH_FORMAT,
+    ];
+    for format in formats {
+        let flags = adapter.get_texture_format_features(format).flags;
+        if !flags.sample_count_supported(sample_count) {
+            anyhow::bail!(
+                "the adapter can't do {} samples with {:?}, only {:?}",
+                sample_count,
+                format,
+                flags.supported_sample_counts()
+            );
+        }
+    }
+    let features = match sample_count {
+        1 | 4 => wgpu::Features::empty(),
+        _ => wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+    };
+    if !adapter.features().contains(features) {
+        anyhow::bail!("the adapter can only do 1 or 4 samples");
+    }
+    Ok(features)
+}
+
 fn create_multisampled_framebuffer(
     device: &wgpu::Device,
     width: u32,
     height: u32,
     format: wgpu::TextureFormat,
+    sample_count: u32,
     label: &str,
 ) -> Option<wgpu::TextureView> {
-    match SAMPLE_COUNT {
+    match sample_count {
         1 => None,
         _ => {
             let multisampled_texture_extent = wgpu::Extent3d {
@@ -204,7 +331,7 @@ fn create_multisampled_framebuffer(
                         label: Some(label),
                         size: multisampled_texture_extent,
                         mip_level_count: 1,
-                        sample_count: SAMPLE_COUNT,
+                        sample_count,
                         dimension: wgpu::TextureDimension::D2,
                         format: format,
                         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -219,48 +346,62 @@ fn create_multisampled_framebuffer(
 #[rustfmt::skip]
 struct State {
     size: winit::dpi::PhysicalSize<u32>,
-    surface: wgpu::Surface<'static>,
+    surface: Option<wgpu::Surface<'static>>, // None when headless
+    target: Option<offscreen::Offscreen>,    // Some when headless
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     depth_texture: texture::Texture,
     multisampled_framebuffer: Option<wgpu::TextureView>,
     multisampled_bright_color: Option<wgpu::TextureView>,
+    sample_count: u32,
     camera: camera::Camera,             // Buffalo buffalo Buffalo...
     lights: lights::Lights,             // ... buffalo buffalo buffalo...
     blinky: blinky::Blinky,             // ... Buffalo buffalo.
     cube: cube::Cube,                   // Upstate bison upstate...
-    cube_trackball: trackball::Trackball,
+    cube_controller: Box<dyn trackball::Controller>,
     glow: glow::Glow,                   // ... bison baffle baffle...
     prefloor: prefloor::PreFloor,       // ...
     floor: floor::Floor,                // ... upstate bison.
     forward_color_format: wgpu::TextureFormat,
-    cube_face_forward_pipeline: wgpu::RenderPipeline,
-    cube_edge_forward_pipeline: wgpu::RenderPipeline,
-    floor_forward_pipeline: wgpu::RenderPipeline,
-    cube_face_shadow_pipeline: wgpu::RenderPipeline,
-    cube_edge_shadow_pipeline: wgpu::RenderPipeline,
-    floor_shadow_pipeline: wgpu::RenderPipeline,
+    forward_pipeline_layout: wgpu::PipelineLayout,
+    shadow_pipeline_layout: wgpu::PipelineLayout,
+    shading: shading::Shading,
+    compare: Option<shading::Scene>,    // the right half's, if split
+    pipelines: HashMap<shading::Scene, ScenePipelines>,
+    post_pipelines: HashMap<shading::ToneMap, post::PostPipelines>,
+    shaders: shaders::Shaders,
+    indicator: indicator::Indicator,
+    shader_error: bool,                 // the last reload failed
+    frame_graph: frame_graph::FrameGraph,
+    show_frame_graph: bool,
+    debug_view: Option<debug_view::DebugView>, // made when first used
+    overlays: overlays::Overlays,
+    tweaks: tweaks::Tweaks,
+    tweaks_uniform: tweaks::TweaksUniform,
+    tweak_panel: Option<tweak_panel::TweakPanel>, // None when headless
+    frame_times: stats::FrameTimes,     // the last frame's
     static_bind_group: wgpu::BindGroup,
     frame_bind_group: wgpu::BindGroup,
     shadow_pass_bind_group: wgpu::BindGroup,
     forward_pass_bind_group: wgpu::BindGroup,
     post: post::Post,                   // (lost buffalo)
+    profiler: profiler::Profiler,
     frame_count: u32,
 }
 
 impl State {
-    async fn new(window: Arc<Window>) -> Self {
+    async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
         //
         // Device and Surface
 
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(
-            &wgpu::InstanceDescriptor::from_env_or_default()
+            &wgpu::InstanceDescriptor::from_env_or_default(),
         );
 
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance.create_surface(window.clone())?;
 
         let adapter = {
             let ppref = wgpu::PowerPreference::from_env()
@@ -272,27 +413,18 @@ impl State {
             };
             // dbg!(&options);
             let future = instance.request_adapter(&options);
-            let adapter = future.await.unwrap();
+            let adapter = future.await.context("no graphics adapter")?;
             // dbg!(&adapter);
             // dbg!(adapter.get_info());
             // dbg!(adapter.limits());
             adapter
         };
 
-        let (device, queue) = adapter.request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("device"),
-                    required_features: wgpu::Features::empty(),
-                    required_limits: adapter.limits(),
-                    memory_hints: Default::default(),
-                    trace: wgpu::Trace::Off,
-                })
-            .await
-            .unwrap();
-
         let config = {
             let surface_caps = surface.get_capabilities(&adapter);
-            let surface_format = surface_caps.formats.iter()
+            let surface_format = surface_caps
+                .formats
+                .iter()
                 .find(|f| f.is_srgb())
                 .copied()
                 .unwrap_or(surface_caps.formats[0]);
@@ -307,8 +439,139 @@ impl State {
                 desired_maximum_frame_latency: 2,
             }
         };
+
+        let sample_count = settings().sample_count;
+        let features =
+            check_sample_count(&adapter, config.format, sample_count)?
+                | profiler::features(&adapter);
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("device"),
+                required_features: features,
+                required_limits: adapter.limits(),
+                memory_hints: Default::default(),
+                trace: wgpu::Trace::Off,
+            })
+            .await?;
         surface.configure(&device, &config);
 
+        let mut state = Self::with_device(
+            size,
+            Some(surface),
+            device,
+            queue,
+            config,
+            sample_count,
+        );
+        state.tweak_panel = Some(tweak_panel::TweakPanel::new(
+            window,
+            &state.device,
+            state.config.format,
+            &state.tweaks,
+        ));
+        Ok(state)
+    }
+
+    // Render without a window, into a texture of our own.  This works
+    // with no display, on a software adapter.  With `software` it only
+    // uses one, the fallback adapter on GL (llvmpipe, say), and doesn't
+    // multisample, so that images don't depend on the GPU.
+    async fn new_headless(
+        width: u32,
+        height: u32,
+        software: bool,
+    ) -> anyhow::Result<Self> {
+        let size = winit::dpi::PhysicalSize::new(width, height);
+
+        let mut instance_descriptor =
+            wgpu::InstanceDescriptor::from_env_or_default();
+        if software {
+            instance_descriptor.backends = wgpu::Backends::GL;
+        }
+        let instance = wgpu::Instance::new(&instance_descriptor);
+
+        let options = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::from_env()
+                .unwrap_or_default(),
+            force_fallback_adapter: software,
+            compatible_surface: None,
+        };
+        let adapter = instance.request_adapter(&options).await.context(
+            match software {
+                true => "no software graphics adapter",
+                false => "no graphics adapter",
+            },
+        )?;
+
+        // Nothing is presented; the configuration only carries the
+        // target's size and format.
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        // Multisampled targets come out black on the software adapter,
+        // so it renders without.
+        let cpu = adapter.get_info().device_type == wgpu::DeviceType::Cpu;
+        let sample_count = match software || cpu {
+            true => 1,
+            false => settings().sample_count,
+        };
+        if cpu && !software && settings().sample_count != 1 {
+            eprintln!(
+                "warning: {} is a software adapter; rendering with 1 \
+                 sample, not {}",
+                adapter.get_info().name,
+                settings().sample_count,
+            );
+        }
+        let features =
+            check_sample_count(&adapter, config.format, sample_count)?
+                | profiler::features(&adapter);
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("device"),
+                required_features: features,
+                required_limits: adapter.limits(),
+                memory_hints: Default::default(),
+                trace: wgpu::Trace::Off,
+            })
+            .await?;
+
+        Ok(Self::with_device(
+            size,
+            None,
+            device,
+            queue,
+            config,
+            sample_count,
+        ))
+    }
+
+    fn with_device(
+        size: winit::dpi::PhysicalSize<u32>,
+        surface: Option<wgpu::Surface<'static>>,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Self {
+        let target = match surface {
+            Some(_) => None,
+            None => Some(offscreen::Offscreen::new(
+                &device,
+                config.width,
+                config.height,
+                config.format,
+            )),
+        };
+
         // Bindings
 
         let static_bindings = binding::StaticBindings::new(&device);
@@ -316,13 +579,6 @@ impl State {
         let forward_pass_bindings = binding::ForwardPassBindings::new(&device);
         let shadow_pass_bindings = binding::ShadowPassBindings::new(&device);
 
-        // Shader(s)
-
-        let common_shader = {
-            let descriptor = wgpu::include_wgsl!("common_shader.wgsl");
-            device.create_shader_module(descriptor)
-        };
-
         // Camera
 
         let camera = camera::Camera::new(
@@ -331,16 +587,85 @@ impl State {
                 width: size.width,
                 height: size.height,
             },
-            WORLD_HANDEDNESS,
+            settings().handedness,
         );
 
         // Lights
 
         let lights = lights::Lights::new(&device);
 
+        // Tweaks start as built in, then as the preset has them.
+        let mut tweaks = tweaks::Tweaks {
+            lights: lights.tweaks(),
+            ..Default::default()
+        };
+        if let Some(path) = &settings().preset {
+            match tweaks::Tweaks::load(path) {
+                Ok(mut preset) => {
+                    preset.keep_lights(&tweaks.lights);
+                    tweaks = preset;
+                }
+                Err(e) => eprintln!("{:#}", e),
+            }
+        }
+        let tweaks_uniform = tweaks::TweaksUniform::new(&device);
+
         // Blinky
 
-        let blinky = blinky::Blinky::new(&device);
+        let mut patterns = pattern::PatternRegistry::with_builtins();
+        image_sequence::register_files(
+            &mut patterns,
+            settings().animations.iter().cloned(),
+        );
+        // The opc setting is the port or address to serve OPC on.  When
+        // it is set, the cube starts out showing what OPC clients send.
+        // pattern_shaders is a directory of pattern shaders.  The
+        // default, ./patterns, may be missing.
+        let shader_dir = settings().pattern_shaders.as_deref();
+        let default_shader_dir = std::path::Path::new("patterns");
+        if shader_dir.is_some() || default_shader_dir.is_dir() {
+            let dir = shader_dir.unwrap_or(default_shader_dir);
+            if let Err(e) = pattern_shader::register_dir(&mut patterns, dir) {
+                eprintln!("{:#}", e);
+            }
+        }
+        let mut pattern_name = settings().pattern.clone();
+        if let Some(address) = &settings().opc {
+            match start_opc_server(address) {
+                Ok(server) => {
+                    println!("OPC server listening on {}", server.address());
+                    server.register(&mut patterns);
+                    pattern_name.get_or_insert(opc::PATTERN_NAME.to_string());
+                }
+                Err(e) => eprintln!("{:#}", e),
+            }
+        }
+        // The dmx setting is "artnet" or "sacn", optionally followed by
+        // ":address:port".
+        if let Some(spec) = &settings().dmx {
+            match start_dmx_receiver(spec) {
+                Ok(receiver) => {
+                    println!(
+                        "DMX receiver listening on {}",
+                        receiver.address()
+                    );
+                    receiver.register(&mut patterns);
+                    pattern_name.get_or_insert(dmx::PATTERN_NAME.to_string());
+                }
+                Err(e) => eprintln!("{:#}", e),
+            }
+        }
+        // The playback setting is a recording to play back.  See
+        // recording.rs.
+        if let Some(path) = &settings().playback {
+            match recording::register_file(&mut patterns, path) {
+                Ok(()) => {
+                    pattern_name
+                        .get_or_insert(recording::PATTERN_NAME.to_string());
+                }
+                Err(e) => eprintln!("{:#}", e),
+            }
+        }
 
         let prefloor = prefloor::PreFloor::new(
             &device,
@@ -348,7 +673,6 @@ impl State {
                 width: config.width,
                 height: config.height,
             },
-            &common_shader,
             &static_bindings.layout,
             &frame_bindings.layout,
         );
@@ -357,7 +681,38 @@ impl State {
 
         let cube = cube::Cube::new(&device, &queue);
 
-        let cube_trackball = trackball::Trackball::new(&size);
+        let mut blinky = blinky::Blinky::new(
+            &device,
+            patterns,
+            pattern_name.as_deref(),
+            cube.face_xforms(),
+        );
+        // The record setting is a file to record the LEDs to.
+        if let Some(path) = &settings().record {
+            match recording::Recorder::create(path) {
+                Ok(recorder) => blinky.record_to(recorder),
+                Err(e) => eprintln!("{:#}", e),
+            }
+        }
+
+        // The imu setting replays IMU orientations from a file, or
+        // receives them on "udp:[host:]port".
+        let cube_controller: Box<dyn trackball::Controller> =
+            match &settings().imu {
+                Some(spec) => match start_imu_feed(spec, &size) {
+                    Ok(feed) => {
+                        if let Some(address) = feed.address() {
+                            println!("IMU feed listening on {}", address);
+                        }
+                        Box::new(feed)
+                    }
+                    Err(e) => {
+                        eprintln!("{:#}", e);
+                        Box::new(trackball::Trackball::new(&size))
+                    }
+                },
+                None => Box::new(trackball::Trackball::new(&size)),
+            };
 
         // Glow "object"
 
@@ -368,10 +723,7 @@ impl State {
         let floor = floor::Floor::new(&device, &queue);
 
         // Output Color Format
-        let forward_color_format = match DO_HDR_POSTPROCESSING {
-            true => LDR_COLOR_PIXEL_FORMAT,
-            false => config.format,
-        };
+        let forward_color_format = forward_color_format(config.format);
 
         // Depth Texture
 
@@ -379,11 +731,11 @@ impl State {
             "depth_texture",
             &device,
             &config,
-            match WORLD_HANDEDNESS {
+            match settings().handedness {
                 Hand::Left => wgpu::CompareFunction::LessEqual,
                 Hand::Right => wgpu::CompareFunction::GreaterEqual,
             },
-            SAMPLE_COUNT,
+            sample_count,
         );
 
         // Multisampled Framebuffer
@@ -392,10 +744,11 @@ impl State {
             &device,
             config.width,
             config.height,
-            match DO_HDR_POSTPROCESSING {
+            match settings().hdr_postprocessing {
                 true => crate::LDR_COLOR_PIXEL_FORMAT,
                 false => forward_color_format,
             },
+            sample_count,
             "multisampled_frambeuffer",
         );
 
@@ -406,17 +759,21 @@ impl State {
             config.width,
             config.height,
             BRIGHT_COLOR_PIXEL_FORMAT,
+            sample_count,
             "multisampled_bright_color",
         );
 
         let static_bind_group = static_bindings.create_bind_group(
             &device,
-            cube.face_decal_resource(),
-            camera.uniform_resource(),
-            lights.light_uniform_resource(),
-            floor.decal_resource(),
-            floor.decal_sampler_resource(),
-            glow.uniform_resource(),
+            binding::StaticResources {
+                face_decal: cube.face_decal_resource(),
+                camera_uniform: camera.uniform_resource(),
+                lights_uniform: lights.light_uniform_resource(),
+                floor_decal: floor.decal_resource(),
+                floor_decal_sampler: floor.decal_sampler_resource(),
+                glow_uniform: glow.uniform_resource(),
+                tweaks_uniform: tweaks_uniform.resource(),
+            },
         );
         let frame_bind_group = frame_bindings.create_bind_group(
             &device,
@@ -436,152 +793,39 @@ impl State {
 
         // Pipelines
 
-        let cube_face_forward_pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("cube_face_forward_pipeline_layout"),
-                    bind_group_layouts: &[
-                        &static_bindings.layout,
-                        &frame_bindings.layout,
-                        &forward_pass_bindings.layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            create_forward_render_pipeline(
-                "cube_face_forward_pipeline",
-                &device,
-                &layout,
-                forward_color_format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[
-                    cube_model::FaceVertex::desc(),
-                    cube::FaceStaticInstanceRaw::desc(),
+        let forward_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("forward_pipeline_layout"),
+                bind_group_layouts: &[
+                    &static_bindings.layout,
+                    &frame_bindings.layout,
+                    &forward_pass_bindings.layout,
                 ],
-                &common_shader,
-                "vs_cube_face_main",
-                "fs_cube_face_main",
-            )
-        };
-
-        let cube_edge_forward_pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("cube_edge_forward_pipeline_layout"),
-                    bind_group_layouts: &[
-                        &static_bindings.layout,
-                        &frame_bindings.layout,
-                        &forward_pass_bindings.layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            create_forward_render_pipeline(
-                "cube_edge_forward_pipeline",
-                &device,
-                &layout,
-                forward_color_format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[cube_model::EdgeVertex::desc()],
-                &common_shader,
-                "vs_cube_edge_main",
-                "fs_cube_edge_main",
-            )
-        };
-
-        let floor_forward_pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("floor_forward_pipeline_layout"),
-                    bind_group_layouts: &[
-                        &static_bindings.layout,
-                        &frame_bindings.layout,
-                        &forward_pass_bindings.layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            create_forward_render_pipeline(
-                "floor_forward_pipeline",
-                &device,
-                &layout,
-                forward_color_format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[floor::FloorVertexRaw::desc()],
-                &common_shader,
-                "vs_floor_main",
-                "fs_floor_main",
-            )
-        };
-
-        let cube_face_shadow_pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("cube_face_shadow_pipeline"),
-                    bind_group_layouts: &[
-                        &static_bindings.layout,
-                        &frame_bindings.layout,
-                        &shadow_pass_bindings.layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            create_shadow_render_pipeline(
-                "cube_face_shadow_pipeline",
-                &device,
-                &layout,
-                &[
-                    cube_model::FaceVertex::desc(),
-                    cube::FaceStaticInstanceRaw::desc(),
+                push_constant_ranges: &[],
+            });
+        let shadow_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("shadow_pipeline_layout"),
+                bind_group_layouts: &[
+                    &static_bindings.layout,
+                    &frame_bindings.layout,
+                    &shadow_pass_bindings.layout,
                 ],
-                &common_shader,
-                "vs_cube_face_shadow_main",
-            )
-        };
+                push_constant_ranges: &[],
+            });
 
-        let cube_edge_shadow_pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("cube_edge_shadow_pipeline"),
-                    bind_group_layouts: &[
-                        &static_bindings.layout,
-                        &frame_bindings.layout,
-                        &shadow_pass_bindings.layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            create_shadow_render_pipeline(
-                "cube_edge_shadow_pipeline",
-                &device,
-                &layout,
-                &[cube_model::EdgeVertex::desc()],
-                &common_shader,
-                "vs_cube_edge_shadow_main",
-            )
-        };
+        // The shading setting picks the shading options.  See
+        // shading.rs.
+        let shading = settings().shading;
 
-        let floor_shadow_pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("floor_shadow_pipeline"),
-                    bind_group_layouts: &[
-                        &static_bindings.layout,
-                        &frame_bindings.layout,
-                        &shadow_pass_bindings.layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            create_shadow_render_pipeline(
-                "floor_shadow_pipeline",
-                &device,
-                &layout,
-                &[floor::FloorVertexRaw::desc()],
-                &common_shader,
-                "vs_floor_shadow_main",
-            )
-        };
+        let shaders = shaders::Shaders::builtin();
+        let indicator = indicator::Indicator::new(&device, config.format);
+        let frame_graph = frame_graph::FrameGraph::new(&device, config.format);
+        let overlays = overlays::Overlays::new(
+            &device,
+            config.format,
+            &settings().overlays,
+        );
 
         // Postprocessing passes
 
@@ -596,41 +840,64 @@ impl State {
             &frame_bindings.layout,
         );
 
+        let profiler = profiler::Profiler::new(&device, &queue);
+
         let frame_count = 0;
 
         // Results
 
-        Self {
+        let mut state = Self {
             size,
             surface,
+            target,
             device,
             queue,
             config,
             depth_texture,
             multisampled_framebuffer,
             multisampled_bright_color,
+            sample_count,
             camera,
             lights,
             blinky,
             cube,
-            cube_trackball,
+            cube_controller,
             glow,
             prefloor,
             floor,
             forward_color_format,
-            cube_face_forward_pipeline,
-            cube_edge_forward_pipeline,
-            floor_forward_pipeline,
-            cube_face_shadow_pipeline,
-            cube_edge_shadow_pipeline,
-            floor_shadow_pipeline,
+            forward_pipeline_layout,
+            shadow_pipeline_layout,
+            shading: shading::Shading::default(),
+            compare: None,
+            pipelines: HashMap::new(),
+            post_pipelines: HashMap::new(),
+            shaders,
+            indicator,
+            shader_error: false,
+            frame_graph,
+            show_frame_graph: settings().frame_graph,
+            debug_view: None,
+            overlays,
+            tweaks,
+            tweaks_uniform,
+            tweak_panel: None,
+            frame_times: stats::FrameTimes::default(),
             static_bind_group,
             frame_bind_group,
             forward_pass_bind_group,
             shadow_pass_bind_group,
             post,
+            profiler,
             frame_count,
+        };
+        if let Err(e) = state.set_shading(shading, None) {
+            panic!("{:#}", e);
         }
+        // Start with the built-in shaders.  The shader_dir setting loads
+        // them from disk from the first update on.  See shaders.rs.
+        state.shaders = shaders::Shaders::new(settings().shader_dir.clone());
+        state
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -638,7 +905,17 @@ impl State {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if self.target.is_some() {
+                self.target = Some(offscreen::Offscreen::new(
+                    &self.device,
+                    self.config.width,
+                    self.config.height,
+                    self.config.format,
+                ));
+            }
             self.camera.resize(&camera::Configuration {
                 width: self.config.width,
                 height: self.config.height,
@@ -647,17 +924,18 @@ impl State {
                 "depth_texture",
                 &self.device,
                 &self.config,
-                match WORLD_HANDEDNESS {
+                match settings().handedness {
                     Hand::Left => wgpu::CompareFunction::LessEqual,
                     Hand::Right => wgpu::CompareFunction::GreaterEqual,
                 },
-                SAMPLE_COUNT,
+                self.sample_count,
             );
             self.multisampled_framebuffer = create_multisampled_framebuffer(
                 &self.device,
                 self.config.width,
                 self.config.height,
                 self.forward_color_format,
+                self.sample_count,
                 "multisampled_framebuffer (resize)",
             );
             self.multisampled_bright_color = create_multisampled_framebuffer(
@@ -665,49 +943,341 @@ impl State {
                 self.config.width,
                 self.config.height,
                 BRIGHT_COLOR_PIXEL_FORMAT,
+                self.sample_count,
                 "multisampled_bright_color (resize)",
             );
             // self.prefloor.resize(
-            //     &self.device, 
+            //     &self.device,
             //     &prefloor::Configuration {
             //     width: new_size.width,
             //     height: new_size.height,
             // });
             self.post
                 .resize(&self.device, new_size.width, new_size.height);
-            self.cube_trackball.set_viewport_size(&new_size);
+            self.cube_controller.set_viewport_size(&new_size);
         }
     }
 
     pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
-        self.cube_trackball.handle_window_event(event)
+        let panel_used = self
+            .tweak_panel
+            .as_mut()
+            .is_some_and(|panel| panel.handle_window_event(event));
+        panel_used || self.cube_controller.handle_window_event(event)
     }
 
-    pub fn update(&mut self) {
-        let now = std::time::Instant::now();
+    pub fn update(&mut self, now: std::time::Instant) {
         self.frame_count += 1;
-        let cube_to_world = self.cube_trackball.orientation(now);
+        let cube_to_world = self.cube_controller.orientation(now);
         self.cube.update_transform(&cube_to_world);
-        self.blinky.update();
+        // The world's down, as the cube's accelerometer would see it.
+        let world_to_cube = cube_to_world.invert().unwrap();
+        let down = world_to_cube * -Vec3::unit_y().extend(0.0);
+        self.blinky.update(now, down.truncate().normalize());
         self.glow.update(self.blinky.current_frame());
         self.prefloor.update();
+        if self.shaders.changed() {
+            self.reload_shaders();
+        }
+    }
+
+    // Rebuild every pipeline from the shaders on disk.  If they don't
+    // validate, keep the old pipelines and show the error indicator.
+    fn reload_shaders(&mut self) {
+        let pipelines = std::mem::take(&mut self.pipelines);
+        let post_pipelines = std::mem::take(&mut self.post_pipelines);
+        match self.set_shading(self.shading, self.compare) {
+            Ok(()) => {
+                if self.shader_error {
+                    println!("shaders reloaded");
+                }
+                self.shader_error = false;
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                self.pipelines = pipelines;
+                self.post_pipelines = post_pipelines;
+                self.shader_error = true;
+            }
+        }
+    }
+
+    // Make the pipelines for one set of scene shading options.  Each
+    // set gets its own shader module: the GL backend caches programs by
+    // module and entry point, and ignores override constants.
+    fn create_scene_pipelines(
+        &self,
+        scene: &shading::Scene,
+    ) -> anyhow::Result<ScenePipelines> {
+        let shader = &self
+            .shaders
+            .compile(&self.device, shaders::Shader::Common)?;
+        let constants = scene.constants();
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let cube_face_forward = create_forward_render_pipeline(
+            "cube_face_forward_pipeline",
+            &self.device,
+            &self.forward_pipeline_layout,
+            self.forward_color_format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &[
+                cube_model::FaceVertex::desc(),
+                cube::FaceStaticInstanceRaw::desc(),
+            ],
+            shader,
+            &constants,
+            "vs_cube_face_main",
+            "fs_cube_face_main",
+        );
+
+        let cube_edge_forward = create_forward_render_pipeline(
+            "cube_edge_forward_pipeline",
+            &self.device,
+            &self.forward_pipeline_layout,
+            self.forward_color_format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &[cube_model::EdgeVertex::desc()],
+            shader,
+            &constants,
+            "vs_cube_edge_main",
+            "fs_cube_edge_main",
+        );
+
+        let floor_forward = create_forward_render_pipeline(
+            "floor_forward_pipeline",
+            &self.device,
+            &self.forward_pipeline_layout,
+            self.forward_color_format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            self.sample_count,
+            &[floor::FloorVertexRaw::desc()],
+            shader,
+            &constants,
+            "vs_floor_main",
+            "fs_floor_main",
+        );
+
+        let cube_face_shadow = create_shadow_render_pipeline(
+            "cube_face_shadow_pipeline",
+            &self.device,
+            &self.shadow_pipeline_layout,
+            &[
+                cube_model::FaceVertex::desc(),
+                cube::FaceStaticInstanceRaw::desc(),
+            ],
+            shader,
+            "vs_cube_face_shadow_main",
+        );
+
+        let cube_edge_shadow = create_shadow_render_pipeline(
+            "cube_edge_shadow_pipeline",
+            &self.device,
+            &self.shadow_pipeline_layout,
+            &[cube_model::EdgeVertex::desc()],
+            shader,
+            "vs_cube_edge_shadow_main",
+        );
+
+        let floor_shadow = create_shadow_render_pipeline(
+            "floor_shadow_pipeline",
+            &self.device,
+            &self.shadow_pipeline_layout,
+            &[floor::FloorVertexRaw::desc()],
+            shader,
+            "vs_floor_shadow_main",
+        );
+        let prefloor =
+            self.prefloor
+                .create_pipeline(&self.device, shader, &constants);
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            anyhow::bail!("can't create pipelines for {}: {}", scene, error);
+        }
+        Ok(ScenePipelines {
+            cube_face_forward,
+            cube_edge_forward,
+            floor_forward,
+            cube_face_shadow,
+            cube_edge_shadow,
+            floor_shadow,
+            prefloor,
+        })
+    }
+
+    fn create_post_pipelines(
+        &self,
+        tone_map: shading::ToneMap,
+    ) -> anyhow::Result<post::PostPipelines> {
+        let shader =
+            self.shaders.compile(&self.device, shaders::Shader::Post)?;
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines =
+            self.post.create_pipelines(&self.device, &shader, tone_map);
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            anyhow::bail!("can't create post pipelines: {}", error);
+        }
+        Ok(pipelines)
+    }
+
+    fn create_debug_view(&self) -> anyhow::Result<debug_view::DebugView> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let debug_view = debug_view::DebugView::new(
+            &self.device,
+            self.config.format,
+            self.sample_count,
+        );
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            anyhow::bail!("can't create the debug view: {}", error);
+        }
+        Ok(debug_view)
+    }
+
+    // Switch shading options, making any pipelines they need.  With
+    // `compare`, the right half of the view has those scene options
+    // instead.
+    pub fn set_shading(
+        &mut self,
+        shading: shading::Shading,
+        compare: Option<shading::Scene>,
+    ) -> anyhow::Result<()> {
+        for scene in std::iter::once(shading.scene).chain(compare) {
+            if !self.pipelines.contains_key(&scene) {
+                let pipelines = self.create_scene_pipelines(&scene)?;
+                self.pipelines.insert(scene, pipelines);
+            }
+        }
+        let tone_map = shading.tone_map;
+        if !self.post_pipelines.contains_key(&tone_map) {
+            let pipelines = self.create_post_pipelines(tone_map)?;
+            self.post_pipelines.insert(tone_map, pipelines);
+        }
+        self.post
+            .set_pipelines(self.post_pipelines[&tone_map].clone());
+        self.shading = shading;
+        self.compare = compare;
+        Ok(())
+    }
+
+    // The pipelines for the view, or for its left and right halves.
+    fn split_pipelines(&self) -> Vec<&ScenePipelines> {
+        std::iter::once(self.shading.scene)
+            .chain(self.compare)
+            .map(|scene| &self.pipelines[&scene])
+            .collect()
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let z_far = match WORLD_HANDEDNESS {
-            Hand::Left => 1.0,
-            Hand::Right => 0.0,
+        let Some(surface) = &self.surface else {
+            let target = self.target.take().unwrap();
+            let start = std::time::Instant::now();
+            self.render_to(target.view());
+            self.frame_times.acquire = std::time::Duration::ZERO;
+            self.frame_times.encode = start.elapsed();
+            self.target = Some(target);
+            return Ok(());
         };
-        let output = self.surface.get_current_texture()?;
+        let start = std::time::Instant::now();
+        let output = surface.get_current_texture()?;
+        self.frame_times.acquire = start.elapsed();
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        let start = std::time::Instant::now();
+        self.render_to(&view);
+        // The panel isn't part of the frame, so captures leave it out.
+        if let Some(panel) = &mut self.tweak_panel {
+            panel.render(&self.device, &self.queue, &view, &mut self.tweaks);
+        }
+        self.frame_times.encode = start.elapsed();
+        output.present();
+        Ok(())
+    }
+
+    // The last frame rendered headless, as RGBA.
+    fn read_frame(&self) -> anyhow::Result<image::RgbaImage> {
+        let target = self.target.as_ref().context("not headless")?;
+        target.read(&self.device, &self.queue)
+    }
+
+    // Render the current frame at `scale` times the window's size.  The
+    // GPU can't make textures that big, so it's rendered in tiles the
+    // window's size, which reuse the glow and bloom of the whole view.
+    fn capture(&mut self, scale: u32) -> anyhow::Result<image::RgbaImage> {
+        let (width, height) = (self.config.width, self.config.height);
+        let target = offscreen::Offscreen::new(
+            &self.device,
+            width,
+            height,
+            self.config.format,
+        );
+        self.render_to(target.view());
+        if scale == 1 {
+            return target.read(&self.device, &self.queue);
+        }
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: Some("keep_bloom_encoder"),
+            },
+        );
+        self.post.keep_bloom(&mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let mut poster = image::RgbaImage::new(width * scale, height * scale);
+        let mut render_tiles = || -> anyhow::Result<()> {
+            for row in 0..scale {
+                for column in 0..scale {
+                    self.camera.set_tile(Some(camera::Tile {
+                        column,
+                        row,
+                        count: scale,
+                    }));
+                    self.render_to(target.view());
+                    let tile = target.read(&self.device, &self.queue)?;
+                    image::GenericImage::copy_from(
+                        &mut poster,
+                        &tile,
+                        column * width,
+                        row * height,
+                    )?;
+                }
+            }
+            Ok(())
+        };
+        let result = render_tiles();
+        self.camera.set_tile(None);
+        result.map(|_| poster)
+    }
+
+    // Render a frame into `view`, which must match the surface's size
+    // and format.  Tiles of a capture leave the LEDs, the prefloor glow
+    // and the bloom as the whole view left them.
+    fn render_to(&mut self, view: &wgpu::TextureView) {
+        let tile = self.camera.tile();
+        let z_far = match settings().handedness {
+            Hand::Left => 1.0,
+            Hand::Right => 0.0,
+        };
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor {
                 label: Some("the_only_encoder"),
             },
         );
+        // Tiles of a capture don't show the debug view.
+        let debug_mode = match (&self.debug_view, &tile) {
+            (Some(debug_view), None) => debug_view.mode,
+            _ => debug_view::Mode::Off,
+        };
+        let debugging = debug_mode != debug_view::Mode::Off;
+        // Taken so the passes can use it while the pipelines are
+        // borrowed.  Tiles of a capture aren't timed.
+        let mut profiler = std::mem::take(&mut self.profiler);
+        if tile.is_none() {
+            profiler.begin_frame(&self.device);
+        }
 
+        self.lights.set_tweaks(&self.tweaks.lights);
+        self.tweaks_uniform.update(&self.queue, &self.tweaks);
         let camera_prepared_data =
             self.camera.prepare(&camera::CameraAttributes {});
         let lights_prepared_data =
@@ -724,16 +1294,26 @@ impl State {
             self.floor.prepare(&floor::FloorAttributes {});
         let glow_prepared_data = self.glow.prepare(&glow::GlowAttributes {});
 
+        // Shader patterns draw into the blinky texture before anything
+        // reads it.
+        if tile.is_none() {
+            self.blinky.encode(&self.device, &self.queue, &mut encoder);
+        }
+
         // Prefloor (low resolution glow) pass.
         // The `render` method creates its own render pass.
-        self.prefloor.render(
-            &mut encoder,
-            &[
-                &self.static_bind_group,
-                &self.frame_bind_group,
-            ],
-            self.floor.vertex_slice(),
-        );
+        let split_pipelines = self.split_pipelines();
+        if tile.is_none() {
+            let prefloor_pipelines: Vec<_> =
+                split_pipelines.iter().map(|p| &p.prefloor).collect();
+            self.prefloor.render(
+                &mut encoder,
+                &prefloor_pipelines,
+                &[&self.static_bind_group, &self.frame_bind_group],
+                self.floor.vertex_slice(),
+                profiler.pass("prefloor"),
+            );
+        }
 
         // Shadow Passes
 
@@ -752,6 +1332,8 @@ impl State {
                 continue;
             }
             let label = &format!("shadow_{}_render_pass", light_index);
+            let timestamp_writes =
+                profiler.pass(&format!("shadow_{}", light_index));
             let mut shadow_pass =
                 encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some(label),
@@ -767,7 +1349,7 @@ impl State {
                             stencil_ops: None,
                         },
                     ),
-                    timestamp_writes: None,
+                    timestamp_writes,
                     occlusion_query_set: None,
                 });
 
@@ -789,7 +1371,7 @@ impl State {
 
             if true {
                 // record face shadows
-                shadow_pass.set_pipeline(&self.cube_face_shadow_pipeline);
+                shadow_pass.set_pipeline(&split_pipelines[0].cube_face_shadow);
                 self.cube.render(
                     &self.queue,
                     &mut shadow_pass,
@@ -798,7 +1380,7 @@ impl State {
             }
             if true {
                 // record edge shadows
-                shadow_pass.set_pipeline(&self.cube_edge_shadow_pipeline);
+                shadow_pass.set_pipeline(&split_pipelines[0].cube_edge_shadow);
                 self.cube.render(
                     &self.queue,
                     &mut shadow_pass,
@@ -807,7 +1389,7 @@ impl State {
             }
             if false {
                 // record floor shadows
-                shadow_pass.set_pipeline(&self.floor_shadow_pipeline);
+                shadow_pass.set_pipeline(&split_pipelines[0].floor_shadow);
                 self.floor.render(
                     &self.queue,
                     &mut shadow_pass,
@@ -821,9 +1403,9 @@ impl State {
             // Inner scope ensures prepared data created above outlives
             // the render pass.
 
-            let output_view = match DO_HDR_POSTPROCESSING {
+            let output_view = match settings().hdr_postprocessing {
                 true => self.post.input_framebuffer(),
-                false => &view,
+                false => view,
             };
 
             let color_view: &wgpu::TextureView;
@@ -861,7 +1443,9 @@ impl State {
                     view: color_view,
                     resolve_target: color_resolve_target,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(BACKGROUND_COLOR),
+                        load: wgpu::LoadOp::Clear(
+                            settings().background_color(),
+                        ),
                         store: store,
                     },
                 }),
@@ -875,6 +1459,11 @@ impl State {
                 }),
             ];
 
+            // Only the debug view looks at depth afterwards.
+            let depth_store = match debug_mode.shows_depth() {
+                true => wgpu::StoreOp::Store,
+                false => wgpu::StoreOp::Discard,
+            };
             let mut render_pass =
                 encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some("forward_render_pass"),
@@ -884,12 +1473,12 @@ impl State {
                             view: &self.depth_texture.view,
                             depth_ops: Some(wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(z_far),
-                                store: wgpu::StoreOp::Discard,
+                                store: depth_store,
                             }),
                             stencil_ops: None,
                         },
                     ),
-                    timestamp_writes: None,
+                    timestamp_writes: profiler.pass("forward"),
                     occlusion_query_set: None,
                 });
 
@@ -927,59 +1516,135 @@ impl State {
                 &mut render_pass,
                 &lights_prepared_data,
             );
-            if true {
-                // cube faces
-                render_pass.set_pipeline(&self.cube_face_forward_pipeline);
-                self.cube.render(
-                    &self.queue,
-                    &mut render_pass,
-                    &cube_face_prepared_data,
-                );
+            // When comparing shading, draw everything twice, once in
+            // each half.
+            let columns =
+                shading::split_columns(self.config.width, tile.as_ref());
+            for (pipelines, columns) in split_pipelines.iter().zip(columns) {
+                if split_pipelines.len() > 1 {
+                    if columns.is_empty() {
+                        continue;
+                    }
+                    render_pass.set_scissor_rect(
+                        columns.start,
+                        0,
+                        columns.len() as u32,
+                        self.config.height,
+                    );
+                }
                 if true {
-                    // cube edges - must render faces first to set up uniform
-                    render_pass.set_pipeline(&self.cube_edge_forward_pipeline);
+                    // cube faces
+                    render_pass.set_pipeline(&pipelines.cube_face_forward);
                     self.cube.render(
                         &self.queue,
                         &mut render_pass,
-                        &cube_edge_prepared_data,
+                        &cube_face_prepared_data,
                     );
+                    if true {
+                        // cube edges - must render faces first to set up
+                        // uniform
+                        render_pass.set_pipeline(&pipelines.cube_edge_forward);
+                        self.cube.render(
+                            &self.queue,
+                            &mut render_pass,
+                            &cube_edge_prepared_data,
+                        );
+                    }
                 }
-            }
-            if true {
                 if true {
-                    // glow
-                    self.glow.render(
+                    if true {
+                        // glow
+                        self.glow.render(
+                            &self.queue,
+                            &mut render_pass,
+                            &glow_prepared_data,
+                        );
+                    }
+                    // floor
+                    render_pass.set_pipeline(&pipelines.floor_forward);
+                    self.floor.render(
                         &self.queue,
                         &mut render_pass,
-                        &glow_prepared_data,
+                        &floor_prepared_data,
                     );
                 }
-                // floor
-                render_pass.set_pipeline(&self.floor_forward_pipeline);
-                self.floor.render(
-                    &self.queue,
-                    &mut render_pass,
-                    &floor_prepared_data,
-                );
             }
         }
 
+        if let (Some(debug_view), true) = (&mut self.debug_view, debugging) {
+            debug_view.copy_bright(
+                &self.device,
+                &mut encoder,
+                self.post.bright_texture(),
+            );
+        }
+
         // Post Processing
-        if DO_HDR_POSTPROCESSING {
-            self.post.render(
+        if settings().hdr_postprocessing {
+            match &tile {
+                Some(tile) => self.post.render_tile(
+                    &self.queue,
+                    &mut encoder,
+                    view,
+                    &[&self.static_bind_group, &self.frame_bind_group],
+                    tile,
+                ),
+                None => self.post.render(
+                    &mut encoder,
+                    view,
+                    &[&self.static_bind_group, &self.frame_bind_group],
+                    &self.collect_cube_view_bounds(),
+                    &mut profiler,
+                ),
+            }
+        }
+
+        if let (Some(debug_view), true) = (&self.debug_view, debugging) {
+            debug_view.render(
                 &self.device,
                 &self.queue,
                 &mut encoder,
-                &view,
-                &[&self.static_bind_group, &self.frame_bind_group],
-                &self.collect_cube_view_bounds(),
+                view,
+                debug_view::Sources {
+                    shadow_maps: self.lights.shadow_maps_resource(),
+                    shadow_layers: debug_view::shadow_layers(&self.lights),
+                    depth: &self.depth_texture.view,
+                    depth_empty: z_far,
+                    glow: self.prefloor.glow_view_resource(),
+                    ping: self.post.bright_framebuffer(),
+                    pong: self.post.pong_framebuffer(),
+                },
+            );
+        }
+        // The bounds on screen are the whole view's, so tiles of a
+        // capture don't show the overlays.
+        if tile.is_none() {
+            self.overlays.render(
+                &self.queue,
+                &mut encoder,
+                view,
+                &overlays::Scene {
+                    cube_to_world: self.cube.cube_to_world,
+                    world_to_clip: self.camera.view_projection_matrix(),
+                    view_bounds: self.collect_cube_view_bounds(),
+                    shadow_projections: self.lights.shadow_projections(),
+                },
             );
         }
+        if self.shader_error && tile.is_none() {
+            self.indicator.render(&mut encoder, view);
+        }
+        if self.show_frame_graph && tile.is_none() {
+            self.frame_graph.render(&mut encoder, view);
+        }
 
+        profiler.end_frame(&mut encoder);
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
+        profiler.after_submit();
+        self.profiler = profiler;
+        if tile.is_none() {
+            self.blinky.after_submit();
+        }
     }
 
     fn collect_cube_view_bounds(&self) -> bounds::Bounds {
@@ -997,70 +1662,24 @@ impl State {
     }
 }
 
-struct Stats {
-    frame_count: u32,
-    prev_frame_count: u32,
-    prev_time: std::time::Instant,
-    last_frame_time: std::time::Instant,
-}
-
-impl Stats {
-    fn new() -> Self {
-        let now = std::time::Instant::now();
-        Stats {
-            frame_count: 0,
-            prev_frame_count: 0,
-            prev_time: now,
-            last_frame_time: now,
-        }
-    }
-    fn start(&mut self) {
-        let now = std::time::Instant::now();
-        self.frame_count = 0;
-        self.prev_frame_count = 0;
-        self.prev_time = now;
-        self.last_frame_time = now;
-    }
-    fn count_frame(&mut self) {
-        self.frame_count += 1;
-
-        let now = std::time::Instant::now();
-        let dur = now.duration_since(self.prev_time);
-        if dur.as_secs() >= 1 {
-            if self.prev_frame_count != 0 {
-                let n = self.frame_count - self.prev_frame_count;
-                if PRINT_FPS {
-                    println!(
-                        "{0:.2} frames/second",
-                        n as f64 / dur.as_secs_f64(),
-                    );
-                }
-            }
-            self.prev_time = now;
-            self.prev_frame_count = self.frame_count;
-        }
-        self.last_frame_time = now;
-    }
-}
-
 struct App {
     state: Option<State>,
-    stats: Stats,
+    stats: stats::Stats,
 }
 
 impl App {
     pub fn new() -> Self {
         Self {
             state: None,
-            stats: Stats::new(),
+            stats: stats::Stats::new(settings().print_fps),
         }
     }
 }
 
 impl ApplicationHandler<State> for App {
-
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let ph = winit::dpi::PhysicalSize::new(1920, 1080);
+        let (width, height) = settings().window_size;
+        let ph = winit::dpi::PhysicalSize::new(width, height);
         let window = {
             let attributes = Window::default_attributes()
                 .with_title("Hello WGPU")
@@ -1070,7 +1689,15 @@ impl ApplicationHandler<State> for App {
             Arc::new(window)
         };
 
-        self.state = Some(pollster::block_on(State::new(window.clone())));
+        let state = match pollster::block_on(State::new(window.clone())) {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("{:#}", e);
+                event_loop.exit();
+                return;
+            }
+        };
+        self.state = Some(state);
         self.stats.start();
     }
 
@@ -1092,12 +1719,46 @@ impl ApplicationHandler<State> for App {
                 WindowEvent::KeyboardInput {
                     event: KeyEvent {
                         physical_key: PhysicalKey::Code(code),
-                        state,
+                        state: key_state,
                         ..
                     },
                     ..
-                } => match (code, state.is_pressed()) {
+                } => match (code, key_state.is_pressed()) {
                     (KeyCode::Escape, true) => event_loop.exit(),
+                    (KeyCode::KeyP, true) => {
+                        state.blinky.next_pattern();
+                        println!("pattern: {}", state.blinky.pattern_name());
+                    }
+                    (KeyCode::F12, true) => save_screenshot(state, 1),
+                    (KeyCode::F11, true) => {
+                        save_screenshot(state, settings().poster_scale);
+                    }
+                    (KeyCode::F3, true) => {
+                        state.show_frame_graph = !state.show_frame_graph;
+                    }
+                    (KeyCode::F1, true) => {
+                        if let Some(panel) = &mut state.tweak_panel {
+                            panel.shown = !panel.shown;
+                        }
+                    }
+                    (KeyCode::KeyV | KeyCode::KeyX, true) => {
+                        switch_debug_view(state, code);
+                    }
+                    (
+                        KeyCode::KeyW
+                        | KeyCode::KeyN
+                        | KeyCode::KeyO
+                        | KeyCode::KeyL,
+                        true,
+                    ) => toggle_overlay(state, code),
+                    (
+                        KeyCode::KeyB
+                        | KeyCode::KeyG
+                        | KeyCode::KeyS
+                        | KeyCode::KeyT
+                        | KeyCode::KeyC,
+                        true,
+                    ) => switch_shading(state, code),
                     _ => {}
                 }
 
@@ -1118,27 +1779,346 @@ impl ApplicationHandler<State> for App {
     }
 
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
-        if let Some(ref mut state ) = self.state {
-            state.update();
+        if let Some(ref mut state) = self.state {
+            let start = std::time::Instant::now();
+            state.update(start);
+            state.frame_times.update = start.elapsed();
+            if state.show_frame_graph {
+                state.frame_graph.update(
+                    &state.queue,
+                    &self.stats,
+                    state.config.width,
+                    state.config.height,
+                );
+            }
             match state.render() {
                 Ok(_) => {}
-                Err(wgpu::SurfaceError::Lost) => {
-                    state.resize(state.size)
-                }
-                Err(wgpu::SurfaceError::OutOfMemory) => {
-                    event_loop.exit()
-                }
-                Err(e) => eprintln!("{:?}", e)
+                Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
+                Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
+                Err(e) => eprintln!("{:?}", e),
             }
-            self.stats.count_frame();
+            self.stats.count_frame(state.frame_times);
         }
     }
 }
 
-fn main() {
-    env_logger::init();
-    let event_loop: EventLoop<State> = EventLoop::with_user_event().build().unwrap();
+// Save the frame as cube-<time>.png in the current directory.
+fn save_screenshot(state: &mut State, scale: u32) {
+    let time = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+    let path = format!("cube-{}.png", time.to_string().replace(':', "-"));
+    let result = state
+        .capture(scale)
+        .and_then(|image| Ok(image.save(&path)?));
+    match result {
+        Ok(()) => println!("saved {}", path),
+        Err(e) => eprintln!("screenshot: {:#}", e),
+    }
+}
+
+// B, G, S and T step through the lighting model, glow, shadows and tone
+// map.  C splits the view to compare the lighting models: the current
+// one on the left, the other on the right.
+fn switch_shading(state: &mut State, key: KeyCode) {
+    let mut shading = state.shading;
+    let mut comparing = state.compare.is_some();
+    let scene = &mut shading.scene;
+    match key {
+        KeyCode::KeyB => scene.model = scene.model.next(),
+        KeyCode::KeyG => scene.glow = scene.glow.next(),
+        KeyCode::KeyS => scene.shadows = scene.shadows.next(),
+        KeyCode::KeyT => shading.tone_map = shading.tone_map.next(),
+        KeyCode::KeyC => comparing = !comparing,
+        _ => return,
+    }
+    let compare = comparing.then(|| shading::Scene {
+        model: shading.scene.model.next(),
+        ..shading.scene
+    });
+    match state.set_shading(shading, compare) {
+        Ok(()) => match compare {
+            Some(compare) => println!("shading: {} | {}", shading, compare),
+            None => println!("shading: {}", shading),
+        },
+        Err(e) => eprintln!("{:#}", e),
+    }
+}
+
+// V steps through the debug views and X through the channels they
+// show.  See debug_view.rs.
+fn switch_debug_view(state: &mut State, key: KeyCode) {
+    let debug_view = match state.debug_view.take() {
+        Some(debug_view) => debug_view,
+        None => match state.create_debug_view() {
+            Ok(debug_view) => debug_view,
+            Err(e) => {
+                eprintln!("{:#}", e);
+                return;
+            }
+        },
+    };
+    let debug_view = state.debug_view.insert(debug_view);
+    match key {
+        KeyCode::KeyV => {
+            let shadow_layers = debug_view::shadow_layers(&state.lights);
+            debug_view.mode = debug_view.mode.next(&shadow_layers);
+        }
+        KeyCode::KeyX => debug_view.channel = debug_view.channel.next(),
+        _ => return,
+    }
+    println!("debug view: {}, {}", debug_view.mode, debug_view.channel);
+}
+
+// W, N, O and L toggle the wireframe, normals, bounds and light frusta
+// overlays.  See overlays.rs.
+fn toggle_overlay(state: &mut State, key: KeyCode) {
+    let Some(overlay) = overlays::Overlay::for_key(key) else {
+        return;
+    };
+    state.overlays.toggle(overlay);
+    let shown: Vec<String> =
+        state.overlays.shown.iter().map(|o| o.to_string()).collect();
+    match shown.is_empty() {
+        true => println!("overlays: none"),
+        false => println!("overlays: {}", shown.join(", ")),
+    }
+}
+
+// The panel setting is the LED panels' resolution.  It has to be set
+// before anything looks at it.
+fn set_panel() -> anyhow::Result<()> {
+    panel::set(settings().panel)
+}
+
+// The wiring setting is a file describing how the panels are wired.
+// See wiring.rs.
+fn set_wiring() -> anyhow::Result<()> {
+    match &settings().wiring {
+        Some(path) => wiring::set(wiring::Wiring::load(path, panel::panel())?),
+        None => Ok(()),
+    }
+}
+
+// `wgpu-cube render OUT.png [WxH]`: render one frame headless.
+fn render_image(args: &[String]) -> anyhow::Result<()> {
+    let (path, size) = match args {
+        [path] => (path, settings().window_size),
+        [path, size] => (path, export::parse_size(size)?),
+        _ => anyhow::bail!("usage: wgpu-cube render OUT.png [WxH]"),
+    };
+    let mut state =
+        pollster::block_on(State::new_headless(size.0, size.1, false))?;
+    state.update(std::time::Instant::now());
+    state.render()?;
+    let image = state.read_frame()?;
+    image
+        .save(path)
+        .with_context(|| format!("can't write {}", path))?;
+    state.profiler.finish(&state.device)
+}
+
+// `wgpu-cube bench [FRAMES]`: time rendering headless, at the window
+// size.  Each frame waits for the GPU to finish.
+fn bench(args: &[String]) -> anyhow::Result<()> {
+    const WARMUP_FRAMES: u32 = 10;
+    let frames: u32 = match args {
+        [] => 600,
+        [frames] => match frames.parse() {
+            Ok(frames) if frames > 0 => frames,
+            _ => anyhow::bail!("bad frame count \"{}\"", frames),
+        },
+        _ => anyhow::bail!("usage: wgpu-cube bench [FRAMES]"),
+    };
+    let (width, height) = settings().window_size;
+    let mut state =
+        pollster::block_on(State::new_headless(width, height, false))?;
+    let mut stats = stats::Stats::new(false);
+    for frame in 0..WARMUP_FRAMES + frames {
+        if frame == WARMUP_FRAMES {
+            stats.start();
+        }
+        let start = std::time::Instant::now();
+        state.update(start);
+        state.frame_times.update = start.elapsed();
+        state.render()?;
+        state.device.poll(wgpu::PollType::Wait)?;
+        stats.count_frame(state.frame_times);
+    }
+    println!(
+        "{} frames at {}x{}, {} samples: {:.2} frames/second",
+        frames,
+        width,
+        height,
+        state.sample_count,
+        stats.frames_per_second(),
+    );
+    let ms = stats.frame_ms();
+    println!(
+        "ms per frame: mean {:.2}, min {:.2}, p50 {:.2}, p95 {:.2}, \
+         p99 {:.2}, max {:.2}; {} hitches",
+        ms.mean,
+        ms.min,
+        ms.p50,
+        ms.p95,
+        ms.p99,
+        ms.max,
+        stats.hitches(),
+    );
+    write_stats(&stats, &state)?;
+    state.profiler.finish(&state.device)?;
+    if settings().profile {
+        state.profiler.print_report();
+    }
+    Ok(())
+}
+
+// `wgpu-cube export [OPTIONS] OUT`: render video headless at a fixed
+// timestep.  See export.rs.
+fn export_video(args: &[String]) -> anyhow::Result<()> {
+    let export_settings = export::Settings::parse(args)?;
+    let (width, height) =
+        export_settings.size.unwrap_or(settings().window_size);
+    let mut state =
+        pollster::block_on(State::new_headless(width, height, false))?;
+    let mut exporter = export::Exporter::new(export_settings)?;
+    let now = std::time::Instant::now();
+    exporter.start(now);
+    state.blinky.restart(now);
+    state.cube_controller.start_export(now);
+    while !exporter.is_done() {
+        state.update(exporter.next_time());
+        state.render()?;
+        exporter.write_frame(&state.read_frame()?)?;
+    }
+    state.profiler.finish(&state.device)
+}
+
+// `wgpu-cube [view]`: show the cube in a window.
+fn view(args: &[String]) -> anyhow::Result<()> {
+    if !args.is_empty() {
+        anyhow::bail!("usage: wgpu-cube view");
+    }
+    let event_loop: EventLoop<State> = EventLoop::with_user_event().build()?;
     event_loop.set_control_flow(ControlFlow::Poll);
     let mut app = App::new();
-    event_loop.run_app(&mut app).unwrap();
+    event_loop.run_app(&mut app)?;
+    match &mut app.state {
+        Some(state) => {
+            write_stats(&app.stats, state)?;
+            state.profiler.finish(&state.device)
+        }
+        None => Ok(()),
+    }
+}
+
+// Write the frame statistics to `stats_out`, if it's set.
+fn write_stats(stats: &stats::Stats, state: &State) -> anyhow::Result<()> {
+    let Some(path) = &settings().stats_out else {
+        return Ok(());
+    };
+    let setup = stats::Setup {
+        width: state.config.width,
+        height: state.config.height,
+        sample_count: state.sample_count,
+    };
+    stats.write(path, &setup)
+}
+
+const USAGE: &str = "\
+usage: wgpu-cube [OPTIONS] [COMMAND]
+
+commands:
+    view                    show the cube in a window (the default)
+    render OUT.png [WxH]    render one frame headless
+    bench [FRAMES]          time FRAMES frames headless, default 600
+    export [OPTIONS] OUT    render video headless, a directory of
+                            PNGs or a .y4m: --fps N, --frames N or
+                            --loop, --size WxH
+    golden [bless]          check the golden images, or rewrite them
+    recording ...           look into an LED recording
+
+options, which override the settings file (see settings.rs):
+    --config PATH           the settings file, default ./cube.toml
+    --sample-count N        MSAA samples, 1 for none
+    --window-size WxH
+    --background R,G,B      linear, 0 to 1
+    --handedness left|right
+    --[no-]backface-cull
+    --[no-]alpha-blending
+    --[no-]print-fps
+    --[no-]hdr-postprocessing
+    --[no-]profile          print the time each render pass takes
+    --profile-out FILE      write a summary of them, .csv or .json
+    --[no-]frame-graph      graph frame times in the window; F3 too
+    --stats-out FILE.json   write frame statistics on exit
+    --overlays LIST         lines to debug the geometry: wireframe,
+                            normals, bounds, frusta; W N O L too
+    --preset FILE.toml      tweaks to start with; F1 edits them
+    --effects LIST          effect parameters, e.g. fire.speed=0.5,
+                            life.palette=#ff8000
+    --panel N|WxH           LEDs per face, default 64
+    --wiring FILE.toml      how the panels are wired
+    --pattern NAME          the pattern to start with
+    --animations LIST       GIFs, APNGs and PNG directories to add
+                            as patterns
+    --pattern-shaders DIR   pattern shaders, default ./patterns
+    --opc [HOST:]PORT       serve OPC, \"\" for the default port
+    --opc-channels MAP      the panels each OPC channel drives,
+                            e.g. 1=0+1+2,2=3+4+5
+    --dmx PROTOCOL[:ADDR]   receive artnet or sacn
+    --dmx-universe N        the first DMX universe
+    --dmx-order ORDER       DMX channel order, e.g. grb
+    --imu FILE|udp:PORT     turn the cube as an IMU says
+    --imu-smoothing SECS    smooth a UDP IMU feed this much
+    --record FILE           record the LEDs
+    --playback FILE         play a recording
+    --shading LIST          e.g. classic,shadow4; see shading.rs
+    --shader-dir DIR        read the shaders from here, reloading
+    --poster-scale N        F11 captures N times the window, default 4
+
+The options from --panel on fall back to environment variables,
+CUBE_ and the option in capitals: CUBE_PATTERN=fire.
+";
+
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args
+        .first()
+        .is_some_and(|arg| ["help", "--help", "-h"].contains(&arg.as_str()))
+    {
+        print!("{}", USAGE);
+        return;
+    }
+    let command = match settings::init(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{:#}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = set_panel() {
+        eprintln!("panel: {:#}", e);
+    }
+    if let Err(e) = set_wiring() {
+        eprintln!("wiring: {:#}", e);
+    }
+    let result = match command.split_first() {
+        None => view(&[]),
+        Some((name, args)) => match name.as_str() {
+            "view" => view(args),
+            "render" => render_image(args),
+            "bench" => bench(args),
+            "export" => export_video(args),
+            "golden" => golden::run_tool(args),
+            "recording" => recording::run_tool(args),
+            _ => {
+                eprintln!("unknown command \"{}\"\n\n{}", name, USAGE);
+                std::process::exit(2);
+            }
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
 }
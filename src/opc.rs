@@ -0,0 +1,398 @@
+// Open Pixel Control server.
+//
+// OPC (http://openpixelcontrol.org/) is a simple TCP protocol.  Each
+// message is a four byte header -- channel, command, and a big-endian
+// data length -- followed by the data.  Only command 0, "set pixel
+// colors", is used; its data is a sequence of RGB triples.
+//
+// Each OPC channel carries one or more panels, each panel's pixels in
+// its own scan order.  Panels are numbered by their place in the chain
+// (see wiring.rs); with the default wiring panel n is face n, scanned
+// row by row.  The default map sends channel 0 to all six panels in
+// order and channels 1 through 6 to panels 0 through 5.  The
+// opc_channels setting changes it, e.g. "1=0+1+2,2=3+4+5".
+//
+// A whole cube doesn't fit in one message (they carry at most 65,535
+// bytes), so each client's messages are gathered into a frame of its
+// own.  The frame ends, and is copied into a shared back buffer, when
+//
+//   - a message fills the last panel in the chain,
+//   - a message comes on a channel the frame already has,
+//   - a system exclusive message (command 255) comes, or
+//   - the client disconnects.
+//
+// So a client can send channels 1 through 6, say, or send a short
+// frame and then a system exclusive message.  `OpcSource` copies the
+// back buffer into its own frame once per animation frame, so frames
+// are never seen half written.
+
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::test_pattern::{black_frame, PixelArray, FACE_COUNT};
+use crate::wiring::wiring;
+
+pub const DEFAULT_PORT: u16 = 7890;
+pub const PATTERN_NAME: &str = "opc";
+
+const HEADER_SIZE: usize = 4;
+const SET_PIXEL_COLORS: u8 = 0;
+const SYSTEM_EXCLUSIVE: u8 = 255;
+
+// Which panels each OPC channel carries.
+#[derive(Clone, Debug)]
+pub struct ChannelMap {
+    channels: Vec<(u8, Vec<usize>)>,
+}
+
+impl Default for ChannelMap {
+    fn default() -> Self {
+        let mut channels = vec![(0, (0..FACE_COUNT).collect())];
+        for face in 0..FACE_COUNT {
+            channels.push((face as u8 + 1, vec![face]));
+        }
+        Self { channels }
+    }
+}
+
+impl ChannelMap {
+    // Parse "channel=panel+panel+...,channel=...".
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut channels: Vec<(u8, Vec<usize>)> = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (channel, panels) = entry.split_once('=').ok_or_else(|| {
+                anyhow!("\"{}\": expected channel=panels", entry)
+            })?;
+            let channel: u8 = channel
+                .trim()
+                .parse()
+                .with_context(|| format!("\"{}\": bad channel", entry))?;
+            let panels = panels
+                .split('+')
+                .map(|p| match p.trim().parse::<usize>() {
+                    Ok(panel) if panel < FACE_COUNT => Ok(panel),
+                    _ => Err(anyhow!("\"{}\": bad panel \"{}\"", entry, p)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if channels.iter().any(|(c, _)| *c == channel) {
+                bail!("channel {} is mapped twice", channel);
+            }
+            channels.push((channel, panels));
+        }
+        Ok(Self { channels })
+    }
+
+    fn panels(&self, channel: u8) -> Option<&[usize]> {
+        self.channels
+            .iter()
+            .find(|(c, _)| *c == channel)
+            .map(|(_, panels)| panels.as_slice())
+    }
+}
+
+struct BackBuffer {
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+pub struct OpcServer {
+    address: SocketAddr,
+    back: Arc<Mutex<BackBuffer>>,
+}
+
+impl OpcServer {
+    // Listen on `address` and serve each client on its own thread.  The
+    // threads run until the program exits.
+    pub fn start(address: &str, map: ChannelMap) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("can't listen on {}", address))?;
+        let address = listener.local_addr()?;
+        let back = Arc::new(Mutex::new(BackBuffer {
+            data: black_frame(),
+            dirty: false,
+        }));
+        let map = Arc::new(map);
+        let thread_back = back.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let back = thread_back.clone();
+                let map = map.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(stream, &map, &back) {
+                        eprintln!("opc: {:#}", e);
+                    }
+                });
+            }
+        });
+        Ok(Self { address, back })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Register an "opc" pattern that shows what clients send.
+    pub fn register(&self, registry: &mut PatternRegistry) {
+        let back = self.back.clone();
+        registry.register(PATTERN_NAME, move || {
+            Box::new(OpcSource {
+                back: back.clone(),
+                data: black_frame(),
+            })
+        });
+    }
+}
+
+fn serve(
+    mut stream: TcpStream,
+    map: &ChannelMap,
+    back: &Mutex<BackBuffer>,
+) -> Result<()> {
+    let mut header = [0u8; HEADER_SIZE];
+    let mut message = Vec::new();
+    let mut frame = ClientFrame::new();
+    loop {
+        match stream.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                frame.show(back);
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let [channel, command, hi, lo] = header;
+        message.resize(u16::from_be_bytes([hi, lo]) as usize, 0);
+        stream.read_exact(&mut message)?;
+        frame.receive(map, channel, command, &message, back);
+    }
+}
+
+// The frame a client is sending.
+struct ClientFrame {
+    data: Vec<u8>,
+    channels: Vec<u8>, // received since the frame began
+}
+
+impl ClientFrame {
+    fn new() -> Self {
+        Self {
+            data: black_frame(),
+            channels: Vec::new(),
+        }
+    }
+
+    fn receive(
+        &mut self,
+        map: &ChannelMap,
+        channel: u8,
+        command: u8,
+        message: &[u8],
+        back: &Mutex<BackBuffer>,
+    ) {
+        match command {
+            SET_PIXEL_COLORS => {}
+            SYSTEM_EXCLUSIVE => return self.show(back),
+            _ => return,
+        }
+        let Some(panels) = map.panels(channel) else {
+            return;
+        };
+        if self.channels.contains(&channel) {
+            self.show(back);
+        }
+        self.channels.push(channel);
+        if write_pixels(&mut self.data, panels, message) {
+            self.show(back);
+        }
+    }
+
+    // End the frame, if it has begun, and copy it to the back buffer.
+    fn show(&mut self, back: &Mutex<BackBuffer>) {
+        if self.channels.is_empty() {
+            return;
+        }
+        let mut back = back.lock().unwrap();
+        back.data.copy_from_slice(&self.data);
+        back.dirty = true;
+        self.channels.clear();
+    }
+}
+
+// Copy RGB triples onto consecutive panels.  Extra data is ignored and
+// short messages leave the remaining pixels unchanged.  Returns true if
+// the last panel in the chain was filled.
+fn write_pixels(data: &mut PixelArray, panels: &[usize], rgb: &[u8]) -> bool {
+    let panel = panel();
+    let panel_pixels = panel.width * panel.height;
+    let mut filled_last = false;
+    for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+        let (n, pos) = (i / panel_pixels, i % panel_pixels);
+        let Some(&n) = panels.get(n) else { break };
+        let (face, row, col) = wiring().locate_on(n, pos);
+        let j = panel.index(face, row, col);
+        data[j..j + 3].copy_from_slice(pixel);
+        data[j + 3] = 255;
+        filled_last |= n == FACE_COUNT - 1 && pos == panel_pixels - 1;
+    }
+    filled_last
+}
+
+pub struct OpcSource {
+    back: Arc<Mutex<BackBuffer>>,
+    data: Vec<u8>,
+}
+
+impl PatternSource for OpcSource {
+    fn name(&self) -> &str {
+        PATTERN_NAME
+    }
+
+    fn next_frame(&mut self, _: &FrameInfo) -> &PixelArray {
+        let mut back = self.back.lock().unwrap();
+        if back.dirty {
+            self.data.copy_from_slice(&back.data[..]);
+            back.dirty = false;
+        }
+        drop(back);
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    // A loopback client: send a message and wait for it to show up.
+    fn send_and_wait(
+        server: &OpcServer,
+        source: &mut Box<dyn PatternSource>,
+        channel: u8,
+        rgb: &[u8],
+    ) -> Vec<u8> {
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        let len = (rgb.len() as u16).to_be_bytes();
+        stream
+            .write_all(&[channel, SET_PIXEL_COLORS, len[0], len[1]])
+            .unwrap();
+        stream.write_all(rgb).unwrap();
+        drop(stream);
+
+        let info = FrameInfo {
+            time: Duration::ZERO,
+            frame_number: 0,
+            down: -Vec3::unit_y(),
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server.back.lock().unwrap().dirty {
+            assert!(Instant::now() < deadline, "no frame received");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        source.next_frame(&info).to_vec()
+    }
+
+    fn start(map: ChannelMap) -> (OpcServer, Box<dyn PatternSource>) {
+        let server = OpcServer::start("127.0.0.1:0", map).unwrap();
+        let mut registry = PatternRegistry::new();
+        server.register(&mut registry);
+        let source = registry.create(registry.index_of("opc").unwrap());
+        (server, source)
+    }
+
+    fn face_pixels() -> usize {
+        panel().width * panel().height
+    }
+
+    fn index(face: usize, row: usize, col: usize) -> usize {
+        panel().index(face, row, col)
+    }
+
+    #[test]
+    fn channel_zero_fills_all_faces() {
+        let (server, mut source) = start(ChannelMap::default());
+        let mut rgb = Vec::new();
+        for face in 0..FACE_COUNT {
+            for _ in 0..face_pixels() {
+                rgb.extend_from_slice(&[face as u8, 10, 20]);
+            }
+        }
+        // 6 faces of RGB don't fit in one OPC message, so send 3 faces.
+        rgb.truncate(3 * 3 * face_pixels());
+        let frame = send_and_wait(&server, &mut source, 0, &rgb);
+        for face in 0..3 {
+            let i = index(face, panel().height - 1, panel().width - 1);
+            assert_eq!(frame[i..i + 4], [face as u8, 10, 20, 255]);
+        }
+        let i = index(3, 0, 0);
+        assert_eq!(frame[i..i + 4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn mapped_channel_writes_its_faces() {
+        let map = ChannelMap::parse("7=5+2").unwrap();
+        let (server, mut source) = start(map);
+        let mut rgb = vec![0u8; 3 * 2 * face_pixels()];
+        rgb[..3].copy_from_slice(&[1, 2, 3]);
+        rgb[3 * face_pixels()..][..3].copy_from_slice(&[4, 5, 6]);
+        let frame = send_and_wait(&server, &mut source, 7, &rgb);
+        let i = index(5, 0, 0);
+        assert_eq!(frame[i..i + 4], [1, 2, 3, 255]);
+        let i = index(2, 0, 0);
+        assert_eq!(frame[i..i + 4], [4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn frames_are_shown_whole() {
+        let map = ChannelMap::default();
+        let back = Mutex::new(BackBuffer {
+            data: black_frame(),
+            dirty: false,
+        });
+        let shown = |back: &Mutex<BackBuffer>| {
+            std::mem::replace(&mut back.lock().unwrap().dirty, false)
+        };
+        let face = vec![7u8; 3 * face_pixels()];
+        let mut frame = ClientFrame::new();
+
+        // Channels 1 through 6 make a frame, shown after the last one.
+        for channel in 1..=FACE_COUNT as u8 {
+            assert!(!shown(&back));
+            frame.receive(&map, channel, SET_PIXEL_COLORS, &face, &back);
+        }
+        assert!(shown(&back));
+        let i = index(5, panel().height - 1, panel().width - 1);
+        assert_eq!(back.lock().unwrap().data[i..i + 4], [7, 7, 7, 255]);
+
+        // A channel that comes again starts another frame.
+        frame.receive(&map, 1, SET_PIXEL_COLORS, &face, &back);
+        frame.receive(&map, 2, SET_PIXEL_COLORS, &face, &back);
+        assert!(!shown(&back));
+        frame.receive(&map, 1, SET_PIXEL_COLORS, &face, &back);
+        assert!(shown(&back));
+
+        // So does a system exclusive message.
+        frame.receive(&map, 0, SYSTEM_EXCLUSIVE, &[0, 1], &back);
+        assert!(shown(&back));
+        frame.receive(&map, 0, SYSTEM_EXCLUSIVE, &[0, 1], &back);
+        assert!(!shown(&back));
+    }
+
+    #[test]
+    fn parse_rejects_bad_maps() {
+        assert!(ChannelMap::parse("1=6").is_err());
+        assert!(ChannelMap::parse("1=0,1=2").is_err());
+        assert!(ChannelMap::parse("1").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+// Lines drawn over the finished frame to debug the geometry: the
+// cube's edge mesh as a wireframe, its face and vertex normals, the
+// bounding boxes that shadows and the bloom are fitted to, and each
+// light's shadow frustum.  There's no depth test, so hidden lines show
+// too.  See overlays.wgsl.
+//
+// W, N, O and L toggle the wireframe, the normals, the bounds and the
+// light frusta.  The `overlays` setting picks those shown at the start.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::bounds::Bounds;
+use crate::cube::CUBE_BOUNDS_WORLD;
+use crate::cube_model::CubeModel;
+use crate::floor::FLOOR_BOUNDS_WORLD;
+use crate::lights;
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overlay {
+    Wireframe,
+    Normals,
+    Bounds,
+    Frusta,
+}
+
+// Normals' lengths, in mm.
+const VERTEX_NORMAL_MM: f32 = 6.0;
+const FACE_NORMAL_MM: f32 = 6.0;
+const PANEL_NORMAL_MM: f32 = 40.0;
+
+const WIREFRAME_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const FACE_NORMAL_COLOR: [f32; 4] = [0.0, 0.9, 0.9, 1.0];
+const VERTEX_NORMAL_COLOR: [f32; 4] = [0.9, 0.0, 0.9, 1.0];
+const CUBE_BOUNDS_COLOR: [f32; 4] = [0.1, 0.9, 0.2, 1.0];
+const FLOOR_BOUNDS_COLOR: [f32; 4] = [0.9, 0.9, 0.1, 1.0];
+const VIEW_BOUNDS_COLOR: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+
+// Lines in a box, between corners whose indices differ in one bit.
+const BOX_LINES: usize = 12;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertexRaw {
+    position: [f32; 4], // in clip space
+    color: [f32; 4],
+}
+
+impl LineVertexRaw {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+    ];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        let stride = std::mem::size_of::<Self>();
+        assert!(stride.is_multiple_of(wgpu::VERTEX_STRIDE_ALIGNMENT as usize));
+        wgpu::VertexBufferLayout {
+            array_stride: stride as _,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Where things are this frame.
+pub struct Scene {
+    pub cube_to_world: Mat4,
+    pub world_to_clip: Mat4,
+    pub view_bounds: Bounds, // the cube's, in clip space
+    pub shadow_projections: Vec<(Mat4, Vec3)>, // see Lights
+}
+
+// The cube's lines, in its own coordinates.
+struct Geometry {
+    wireframe: Vec<[Point3; 2]>,
+    face_normals: Vec<[Point3; 2]>, // the edge mesh's and the panels'
+    vertex_normals: Vec<[Point3; 2]>,
+}
+
+pub struct Overlays {
+    pub shown: Vec<Overlay>,
+    geometry: Geometry,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl Overlay {
+    // The overlay a key toggles.
+    pub fn for_key(key: winit::keyboard::KeyCode) -> Option<Self> {
+        use winit::keyboard::KeyCode;
+        match key {
+            KeyCode::KeyW => Some(Overlay::Wireframe),
+            KeyCode::KeyN => Some(Overlay::Normals),
+            KeyCode::KeyO => Some(Overlay::Bounds),
+            KeyCode::KeyL => Some(Overlay::Frusta),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Overlay::Wireframe => "wireframe",
+            Overlay::Normals => "normals",
+            Overlay::Bounds => "bounds",
+            Overlay::Frusta => "frusta",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Geometry {
+    fn new(model: &CubeModel) -> Self {
+        let position =
+            |i: u32| Point3::from(model.edge_vertices[i as usize].position);
+        let normal =
+            |i: u32| Vec3::from(model.edge_vertices[i as usize].normal);
+
+        // Triangles share edges; draw each once.
+        let mut edges = BTreeSet::new();
+        let mut face_normals = Vec::new();
+        for triangle in model.edge_indices.chunks(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+            for (i, j) in [(a, b), (b, c), (c, a)] {
+                edges.insert((i.min(j), i.max(j)));
+            }
+            // Which way the winding faces, not what the mesh says.
+            let [pa, pb, pc] = [position(a), position(b), position(c)];
+            let center = Point3::centroid(&[pa, pb, pc]);
+            let n = (pb - pa).cross(pc - pa).normalize();
+            face_normals.push([center, center + FACE_NORMAL_MM * n]);
+        }
+        for xform in CubeModel::make_face_xforms() {
+            let center = Point3::from_homogeneous(
+                xform * Point3::origin().to_homogeneous(),
+            );
+            let n = xform.transform_vector(Vec3::unit_z());
+            face_normals.push([center, center + PANEL_NORMAL_MM * n]);
+        }
+        let wireframe = edges
+            .into_iter()
+            .map(|(i, j)| [position(i), position(j)])
+            .collect();
+        let vertex_normals = (0..model.edge_vertices.len() as u32)
+            .map(|i| {
+                let p = position(i);
+                [p, p + VERTEX_NORMAL_MM * normal(i)]
+            })
+            .collect();
+        Self {
+            wireframe,
+            face_normals,
+            vertex_normals,
+        }
+    }
+
+    // The most vertices `lines` can return.
+    fn max_vertices(&self) -> usize {
+        let cube = self.wireframe.len()
+            + self.face_normals.len()
+            + self.vertex_normals.len();
+        let boxes = (3 + lights::MAX_LIGHTS) * BOX_LINES;
+        2 * (cube + boxes)
+    }
+
+    // The `shown` overlays' lines, as pairs of vertices.
+    fn lines(&self, shown: &[Overlay], scene: &Scene) -> Vec<LineVertexRaw> {
+        let mut vertices = Vec::new();
+        let mut add = |ends: [cgmath::Vector4<f32>; 2], color: [f32; 4]| {
+            for end in ends {
+                vertices.push(LineVertexRaw {
+                    position: end.into(),
+                    color,
+                });
+            }
+        };
+        let cube_to_clip = scene.world_to_clip * scene.cube_to_world;
+        let mut add_cube_lines = |lines: &[[Point3; 2]], color| {
+            for line in lines {
+                let ends = line.map(|p| cube_to_clip * p.to_homogeneous());
+                add(ends, color);
+            }
+        };
+        if shown.contains(&Overlay::Wireframe) {
+            add_cube_lines(&self.wireframe, WIREFRAME_COLOR);
+        }
+        if shown.contains(&Overlay::Normals) {
+            add_cube_lines(&self.face_normals, FACE_NORMAL_COLOR);
+            add_cube_lines(&self.vertex_normals, VERTEX_NORMAL_COLOR);
+        }
+        if shown.contains(&Overlay::Bounds) {
+            for (bounds, color) in [
+                (&CUBE_BOUNDS_WORLD, CUBE_BOUNDS_COLOR),
+                (&FLOOR_BOUNDS_WORLD, FLOOR_BOUNDS_COLOR),
+            ] {
+                let corners = ortho_corners(bounds)
+                    .map(|p| scene.world_to_clip * p.to_homogeneous());
+                box_lines(&corners, |ends| add(ends, color));
+            }
+            // The view bounds are already in clip space.  Without a
+            // depth test, any depth inside the view does.
+            let b = &scene.view_bounds;
+            let corners = [0, 1, 2, 3].map(|i| {
+                let x = [b.xmin, b.xmax][i & 1];
+                let y = [b.ymin, b.ymax][i >> 1];
+                cgmath::Vector4::new(x, y, 0.5, 1.0)
+            });
+            for (i, j) in [(0, 1), (1, 3), (3, 2), (2, 0)] {
+                add([corners[i], corners[j]], VIEW_BOUNDS_COLOR);
+            }
+        }
+        if shown.contains(&Overlay::Frusta) {
+            for (projection, color) in &scene.shadow_projections {
+                let Some(clip_to_world) = projection.invert() else {
+                    continue;
+                };
+                let corners = frustum_corners()
+                    .map(|p| scene.world_to_clip * clip_to_world * p);
+                box_lines(&corners, |ends| add(ends, color.extend(1.0).into()));
+            }
+        }
+        vertices
+    }
+}
+
+// An Ortho's corners, with x, y and z from the low bits of the index.
+fn ortho_corners(ortho: &cgmath::Ortho<f32>) -> [Point3; 8] {
+    std::array::from_fn(|i| {
+        Point3::new(
+            [ortho.left, ortho.right][i & 1],
+            [ortho.bottom, ortho.top][(i >> 1) & 1],
+            [ortho.far, ortho.near][i >> 2],
+        )
+    })
+}
+
+// The corners of wgpu's clip volume, in the same order.
+fn frustum_corners() -> [cgmath::Vector4<f32>; 8] {
+    std::array::from_fn(|i| {
+        cgmath::Vector4::new(
+            [-1.0, 1.0][i & 1],
+            [-1.0, 1.0][(i >> 1) & 1],
+            [0.0, 1.0][i >> 2],
+            1.0,
+        )
+    })
+}
+
+// Call `line` with the ends of each of a box's edges.
+fn box_lines<T: Copy>(corners: &[T; 8], mut line: impl FnMut([T; 2])) {
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                line([corners[i], corners[i | bit]]);
+            }
+        }
+    }
+}
+
+impl Overlays {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        shown: &[Overlay],
+    ) -> Self {
+        let geometry = Geometry::new(&CubeModel::new());
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("overlays_vertex_buffer"),
+            size: (geometry.max_vertices()
+                * std::mem::size_of::<LineVertexRaw>())
+                as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shader =
+            device.create_shader_module(wgpu::include_wgsl!("overlays.wgsl"));
+        let layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("overlays_pipeline_layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+        let pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("overlays_pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_overlays_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[LineVertexRaw::desc()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_overlays_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            });
+        Self {
+            shown: shown.to_vec(),
+            geometry,
+            pipeline,
+            vertex_buffer,
+        }
+    }
+
+    // Show `overlay` if it's hidden, or hide it.
+    pub fn toggle(&mut self, overlay: Overlay) {
+        match self.shown.iter().position(|&o| o == overlay) {
+            Some(i) => {
+                self.shown.remove(i);
+            }
+            None => self.shown.push(overlay),
+        }
+    }
+
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        scene: &Scene,
+    ) {
+        if self.shown.is_empty() {
+            return;
+        }
+        let vertices = self.geometry.lines(&self.shown, scene);
+        queue.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&vertices),
+        );
+        let mut render_pass =
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("overlays_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Scene {
+        Scene {
+            cube_to_world: Mat4::identity(),
+            world_to_clip: Mat4::identity(),
+            view_bounds: [
+                Point3::new(-0.5, -0.5, 0.5),
+                Point3::new(0.5, 0.5, 0.5),
+            ]
+            .into_iter()
+            .collect(),
+            shadow_projections: vec![(
+                Mat4::from_scale(0.5),
+                Vec3::new(1.0, 1.0, 1.0),
+            )],
+        }
+    }
+
+    #[test]
+    fn draws_each_edge_once() {
+        let model = CubeModel::new();
+        let geometry = Geometry::new(&model);
+        // Neighboring triangles share a side, which is drawn once.
+        let sides = model.edge_indices.len();
+        assert!(geometry.wireframe.len() < sides);
+        assert!(geometry.wireframe.len() * 2 >= sides);
+        let all = [
+            Overlay::Wireframe,
+            Overlay::Normals,
+            Overlay::Bounds,
+            Overlay::Frusta,
+        ];
+        let vertices = geometry.lines(&all, &scene());
+        assert!(vertices.len().is_multiple_of(2));
+        assert!(vertices.len() <= geometry.max_vertices());
+    }
+
+    #[test]
+    fn frustum_is_the_projections_clip_volume() {
+        let geometry = Geometry {
+            wireframe: Vec::new(),
+            face_normals: Vec::new(),
+            vertex_normals: Vec::new(),
+        };
+        let vertices = geometry.lines(&[Overlay::Frusta], &scene());
+        assert_eq!(vertices.len(), 2 * BOX_LINES);
+        // Scaled by a half, so the clip volume's corners are twice as
+        // far out.
+        for vertex in vertices {
+            let [x, y, z, w] = vertex.position;
+            assert_eq!([x.abs(), y.abs(), w], [2.0, 2.0, 1.0]);
+            assert!(z == 0.0 || z == 2.0);
+        }
+    }
+}
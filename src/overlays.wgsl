@@ -0,0 +1,22 @@
+// Lines for debugging the geometry, already in clip space, each in a
+// flat color.  See overlays.rs.
+
+struct LineVertexInput {
+    @location(0) position: vec4<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_overlays_main(in: LineVertexInput) -> VertexOutput {
+    return VertexOutput(in.position, in.color);
+}
+
+@fragment
+fn fs_overlays_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,130 @@
+// The LED panels' resolution.
+//
+// Every face carries the same panel, `width` LEDs across and `height`
+// LEDs down.  The resolution is chosen once at startup, before any
+// pattern, texture or LED table is made, and doesn't change after that.
+//
+// In a frame, and in the blinky texture, the faces' rows are laid side
+// by side, each face in its slot (see wiring.rs), so the frame is
+// `FACE_COUNT * width` pixels wide and `height` pixels high.
+
+use std::sync::OnceLock;
+
+use anyhow::Result;
+
+use crate::cube_model::FACE_LENGTH_MM;
+use crate::test_pattern::{CHANNEL_COUNT, FACE_COUNT};
+use crate::wiring::wiring;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Panel {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Panel {
+    pub const DEFAULT: Panel = Panel {
+        width: 64,
+        height: 64,
+    };
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height }
+    }
+
+    // "32" for a square panel or "64x32" for width x height.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (width, height) = match spec.split_once(['x', 'X']) {
+            Some((width, height)) => (width, height),
+            None => (spec, spec),
+        };
+        let size = |s: &str| match s.trim().parse::<usize>() {
+            Ok(n) if (1..=1024).contains(&n) => Ok(n),
+            _ => Err(anyhow::anyhow!("bad panel size \"{}\"", spec)),
+        };
+        Ok(Self::new(size(width)?, size(height)?))
+    }
+
+    // LEDs on the whole cube
+    pub fn led_count(self) -> usize {
+        FACE_COUNT * self.width * self.height
+    }
+
+    // bytes in a frame
+    pub fn bytes(self) -> usize {
+        self.led_count() * CHANNEL_COUNT
+    }
+
+    // The frame's size in pixels.
+    pub fn frame_width(self) -> usize {
+        FACE_COUNT * self.width
+    }
+
+    pub fn frame_height(self) -> usize {
+        self.height
+    }
+
+    // An LED's number, face by face, then row by row.
+    pub fn led_id(self, face: usize, row: usize, col: usize) -> usize {
+        (face * self.height + row) * self.width + col
+    }
+
+    // Where an LED's red channel is in a frame.
+    pub fn index(self, face: usize, row: usize, col: usize) -> usize {
+        let x = self.width * wiring().slot(face) + col;
+        CHANNEL_COUNT * (self.frame_width() * row + x)
+    }
+
+    // Distance between LED centers, mm, across and down.
+    pub fn pitch_mm(self) -> (f32, f32) {
+        (
+            FACE_LENGTH_MM / self.width as f32,
+            FACE_LENGTH_MM / self.height as f32,
+        )
+    }
+}
+
+static PANEL: OnceLock<Panel> = OnceLock::new();
+
+// Choose the resolution.  This fails once anything has used it.
+pub fn set(panel: Panel) -> Result<()> {
+    PANEL
+        .set(panel)
+        .map_err(|_| anyhow::anyhow!("the panel size is already in use"))
+}
+
+pub fn panel() -> Panel {
+    *PANEL.get_or_init(|| Panel::DEFAULT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(Panel::parse("32").unwrap(), Panel::new(32, 32));
+        assert_eq!(Panel::parse("64x32").unwrap(), Panel::new(64, 32));
+        assert!(Panel::parse("0").is_err());
+        assert!(Panel::parse("64x").is_err());
+    }
+
+    #[test]
+    fn indices_cover_the_frame() {
+        let panel = Panel::new(5, 3);
+        let mut seen = vec![false; panel.led_count()];
+        for face in 0..FACE_COUNT {
+            for row in 0..panel.height {
+                for col in 0..panel.width {
+                    let index = panel.index(face, row, col);
+                    assert_eq!(index % CHANNEL_COUNT, 0);
+                    assert!(!seen[index / CHANNEL_COUNT]);
+                    seen[index / CHANNEL_COUNT] = true;
+                }
+            }
+        }
+        // Upright, face 0 is at the right end of the first row.
+        let last = panel.frame_width() - 1;
+        assert_eq!(panel.index(0, 0, panel.width - 1), CHANNEL_COUNT * last);
+    }
+}
@@ -0,0 +1,82 @@
+// LED animation sources.
+//
+// A pattern source generates one frame of LED data at a time.  Sources
+// are registered by name so they can be chosen at startup and cycled at
+// runtime.  To add an animation, implement `PatternSource` in its own
+// module and register it in `PatternRegistry::with_builtins`.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::effects;
+use crate::prelude::*;
+use crate::test_pattern;
+use crate::test_pattern::PixelArray;
+
+// Everything a pattern source may know about the frame it is generating.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameInfo {
+    pub time: Duration,      // since the animation started
+    pub frame_number: usize, // frames since the animation started
+    pub down: Vec3,          // gravity in cube space, unit length
+}
+
+pub trait PatternSource {
+    fn name(&self) -> &str;
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray;
+    fn current_frame(&self) -> &PixelArray;
+
+    // A source that runs on the GPU names its pattern shader here.
+    fn shader(&self) -> Option<&Path> {
+        None
+    }
+}
+
+pub type PatternConstructor = Box<dyn Fn() -> Box<dyn PatternSource>>;
+
+pub struct PatternRegistry {
+    entries: Vec<(String, PatternConstructor)>,
+}
+
+impl PatternRegistry {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        test_pattern::register(&mut registry);
+        effects::register(&mut registry);
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &str, constructor: F)
+    where
+        F: Fn() -> Box<dyn PatternSource> + 'static,
+    {
+        assert!(
+            self.index_of(name).is_none(),
+            "pattern \"{}\" registered twice",
+            name
+        );
+        self.entries.push((name.to_string(), Box::new(constructor)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|(n, _)| *n == name)
+    }
+
+    pub fn create(&self, index: usize) -> Box<dyn PatternSource> {
+        (self.entries[index].1)()
+    }
+}
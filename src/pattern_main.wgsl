@@ -0,0 +1,29 @@
+// Appended to every pattern shader.  One invocation per LED.  The
+// blinky texture is six panels wide and one panel high, with each face
+// in its slot (see wiring.rs).
+
+@compute @workgroup_size(8, 8)
+fn pattern_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(t_pattern);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let width = size.x / 6u;
+    let slot = id.x / width;
+    let face = pattern_uniform.faces[slot / 4u][slot % 4u];
+    let col_row = vec2<u32>(id.x % width, id.y);
+    let uv = (vec2<f32>(col_row) + 0.5) / vec2<f32>(f32(width), f32(size.y));
+    let local = vec4<f32>(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y, 0.0, 1.0);
+    let position = (pattern_uniform.led_xforms[face] * local).xyz;
+
+    let color = pattern(PatternInput(
+        pattern_uniform.time,
+        pattern_uniform.frame,
+        face,
+        uv,
+        position,
+        pattern_uniform.down,
+    ));
+    let rgb = vec3<u32>(round(255.0 * clamp(color, vec3(0.0), vec3(1.0))));
+    textureStore(t_pattern, vec2<i32>(id.xy), vec4<u32>(rgb, 255u));
+}
@@ -0,0 +1,32 @@
+// Prepended to every pattern shader.
+//
+// A pattern shader defines
+//
+//     fn pattern(in: PatternInput) -> vec3<f32>
+//
+// which returns the color of one LED, each channel in 0..1.
+
+struct PatternInput {
+    time: f32,           // seconds since the pattern started
+    frame: u32,          // frames since the pattern started
+    face: u32,           // 0..5: left, front, right, bottom, back, top
+    uv: vec2<f32>,       // LED center on its face, (0, 0) at top left
+    position: vec3<f32>, // LED center on the cube, faces at +/- 1
+    down: vec3<f32>,     // gravity in cube space, unit length
+}
+
+struct PatternUniform {
+    led_xforms: array<mat4x4<f32>, 6>,
+    down: vec3<f32>,
+    time: f32,
+    frame: u32,
+    faces: array<vec4<u32>, 2>, // the face at each slot
+}
+
+@group(0) @binding(0)
+var<uniform> pattern_uniform: PatternUniform;
+@group(0) @binding(1)
+var t_pattern: texture_storage_2d<rgba8uint, write>;
+
+const PI: f32 = 3.14159265358979;
+const TAU: f32 = 6.28318530717959;
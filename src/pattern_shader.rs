@@ -0,0 +1,347 @@
+// LED patterns written as WGSL compute shaders.
+//
+// Each `<name>.wgsl` file in the pattern shader directory is registered
+// as pattern `<name>`.  The file defines a `pattern` function (see
+// pattern_prelude.wgsl) which is run once per LED, straight into the
+// blinky texture, before the forward pass.  The file is read and
+// compiled when the pattern is selected, so edits show up the next time
+// it comes around.
+//
+// The glow is still resampled on the CPU, so each frame is copied back
+// from the blinky texture.  The glow lags the LEDs by a frame or two.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::binding;
+use crate::cube_model::{FACE_DISPLACEMENT_MM, FACE_LENGTH_MM};
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::prelude::*;
+use crate::test_pattern::{PixelArray, CHANNEL_COUNT, FACE_COUNT};
+use crate::wiring::wiring;
+
+const PRELUDE: &str = include_str!("pattern_prelude.wgsl");
+const MAIN: &str = include_str!("pattern_main.wgsl");
+const WORKGROUP_SIDE: u32 = 8;
+
+// Register every pattern shader in `dir`.
+pub fn register_dir(registry: &mut PatternRegistry, dir: &Path) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("can't read {}", dir.display()))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.extension().is_some_and(|ext| ext == "wgsl"));
+    paths.sort();
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        if registry.index_of(&name).is_some() {
+            eprintln!(
+                "{}: a pattern named \"{}\" already exists",
+                path.display(),
+                name,
+            );
+            continue;
+        }
+        let source_name = name.clone();
+        registry.register(&name, move || {
+            Box::new(ShaderSource {
+                name: source_name.clone(),
+                path: path.clone(),
+                data: vec![0; panel().bytes()],
+            })
+        });
+    }
+    Ok(())
+}
+
+// The CPU side of a shader pattern.  It has no pixels of its own;
+// `Blinky` runs the shader and reads the frame back.
+pub struct ShaderSource {
+    name: String,
+    path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl PatternSource for ShaderSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn next_frame(&mut self, _: &FrameInfo) -> &PixelArray {
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+
+    fn shader(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PatternUniformRaw {
+    led_xforms: [[[f32; 4]; 4]; FACE_COUNT],
+    down: [f32; 3],
+    time: f32,
+    frame: u32,
+    _padding: [u32; 3],
+    faces: [[u32; 4]; 2], // the face at each slot
+}
+
+pub struct PatternShaders {
+    led_xforms: [[[f32; 4]; 4]; FACE_COUNT],
+    faces: [[u32; 4]; 2],
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    // The shader file and its pipeline, or None if it didn't compile.
+    pipeline: Option<(PathBuf, Option<wgpu::ComputePipeline>)>,
+    texture_size: wgpu::Extent3d,
+    // Copies to a buffer need rows padded to a multiple of 256 bytes.
+    row_bytes: usize,
+    padded_row_bytes: usize,
+    readback_buffer: wgpu::Buffer,
+    readback: Readback,
+    readback_mapped: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Readback {
+    Idle,
+    Copied,  // the copy is encoded
+    Mapping, // the copy is submitted and the buffer is being mapped
+}
+
+impl PatternShaders {
+    pub fn new(
+        device: &wgpu::Device,
+        blinky_view: &wgpu::TextureView,
+        face_xforms: &[Mat4],
+    ) -> Self {
+        // Map the unit square onto each face, then scale the cube so
+        // its faces are at +/- 1.
+        const HFL: f32 = FACE_LENGTH_MM / 2.0;
+        let face_scale = Mat4::from_nonuniform_scale(HFL, HFL, 1.0);
+        let cube_scale = Mat4::from_scale(1.0 / (HFL + FACE_DISPLACEMENT_MM));
+        let led_xforms: Vec<[[f32; 4]; 4]> = face_xforms
+            .iter()
+            .map(|xf| (cube_scale * xf * face_scale).into())
+            .collect();
+        let faces = std::array::from_fn(|i| {
+            std::array::from_fn(|j| match 4 * i + j {
+                slot if slot < FACE_COUNT => wiring().face_at(slot) as u32,
+                _ => 0,
+            })
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pattern_uniform_buffer"),
+            size: std::mem::size_of::<PatternUniformRaw>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pattern_pass_bindings = binding::PatternPassBindings::new(device);
+        let bind_group = pattern_pass_bindings.create_bind_group(
+            device,
+            uniform_buffer.as_entire_binding(),
+            wgpu::BindingResource::TextureView(blinky_view),
+        );
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pattern_pipeline_layout"),
+                bind_group_layouts: &[&pattern_pass_bindings.layout],
+                push_constant_ranges: &[],
+            });
+
+        let panel = panel();
+        let texture_size = wgpu::Extent3d {
+            width: panel.frame_width() as u32,
+            height: panel.frame_height() as u32,
+            depth_or_array_layers: 1,
+        };
+        let row_bytes = panel.frame_width() * CHANNEL_COUNT;
+        let padded_row_bytes = row_bytes
+            .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pattern_readback_buffer"),
+            size: (padded_row_bytes * panel.frame_height()) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            led_xforms: led_xforms.try_into().unwrap(),
+            faces,
+            uniform_buffer,
+            bind_group,
+            pipeline_layout,
+            pipeline: None,
+            texture_size,
+            row_bytes,
+            padded_row_bytes,
+            readback_buffer,
+            readback: Readback::Idle,
+            readback_mapped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Compile the shader again the next time it's run, so edits to it,
+    // or fixes to one that didn't compile, show up.
+    pub fn forget_pipeline(&mut self) {
+        self.pipeline = None;
+    }
+
+    // Run the shader at `path` into `blinky_texture` and, unless the
+    // last copy is still being read, copy the result for readback.
+    // Returns false if the shader can't be used.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        blinky_texture: &wgpu::Texture,
+        path: &Path,
+        info: &FrameInfo,
+    ) -> bool {
+        if self.pipeline.as_ref().is_none_or(|(p, _)| p != path) {
+            let pipeline = match self.compile(device, path) {
+                Ok(pipeline) => Some(pipeline),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    None
+                }
+            };
+            self.pipeline = Some((path.to_path_buf(), pipeline));
+        }
+        let Some((_, Some(pipeline))) = &self.pipeline else {
+            return false;
+        };
+
+        let uniform = PatternUniformRaw {
+            led_xforms: self.led_xforms,
+            down: info.down.into(),
+            time: info.time.as_secs_f32(),
+            frame: info.frame_number as u32,
+            _padding: [0; 3],
+            faces: self.faces,
+        };
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
+
+        {
+            let mut pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("pattern_pass"),
+                    timestamp_writes: None,
+                });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(
+                binding::PatternPassBindings::GROUP_INDEX,
+                &self.bind_group,
+                &[],
+            );
+            pass.dispatch_workgroups(
+                self.texture_size.width.div_ceil(WORKGROUP_SIDE),
+                self.texture_size.height.div_ceil(WORKGROUP_SIDE),
+                1,
+            );
+        }
+
+        if self.readback == Readback::Idle {
+            encoder.copy_texture_to_buffer(
+                blinky_texture.as_image_copy(),
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &self.readback_buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(self.padded_row_bytes as u32),
+                        rows_per_image: None,
+                    },
+                },
+                self.texture_size,
+            );
+            self.readback = Readback::Copied;
+        }
+        true
+    }
+
+    // Call after the encoder has been submitted.
+    pub fn start_readback(&mut self) {
+        if self.readback == Readback::Copied {
+            let mapped = self.readback_mapped.clone();
+            self.readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    mapped.store(result.is_ok(), Ordering::Release)
+                });
+            self.readback = Readback::Mapping;
+        }
+    }
+
+    // Copy the last frame that has been read back into `frame`.
+    // Returns false if none has arrived since the last call.
+    pub fn finish_readback(
+        &mut self,
+        device: &wgpu::Device,
+        frame: &mut PixelArray,
+    ) -> bool {
+        if self.readback != Readback::Mapping {
+            return false;
+        }
+        let _ = device.poll(wgpu::PollType::Poll);
+        if !self.readback_mapped.swap(false, Ordering::Acquire) {
+            return false;
+        }
+        let data = self.readback_buffer.slice(..).get_mapped_range();
+        let rows = data.chunks_exact(self.padded_row_bytes);
+        for (dst, src) in frame.chunks_exact_mut(self.row_bytes).zip(rows) {
+            dst.copy_from_slice(&src[..self.row_bytes]);
+        }
+        drop(data);
+        self.readback_buffer.unmap();
+        self.readback = Readback::Idle;
+        true
+    }
+
+    fn compile(
+        &self,
+        device: &wgpu::Device,
+        path: &Path,
+    ) -> Result<wgpu::ComputePipeline> {
+        let body = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        let source = format!("{}\n{}\n{}", PRELUDE, body, MAIN);
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&path.display().to_string()),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pattern_pipeline"),
+                layout: Some(&self.pipeline_layout),
+                module: &module,
+                entry_point: Some("pattern_main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            anyhow::bail!("{}: {}", path.display(), error);
+        }
+        Ok(pipeline)
+    }
+}
@@ -1,10 +1,14 @@
 use crate::binding;
 use crate::bounds;
+use crate::camera;
+use crate::profiler;
+use crate::shading;
 use wgpu::util::DeviceExt;
 
 const BLUR_STEPS: usize = 3;
 const SCALING_STEPS: usize = 3;
 const PASS_COUNT: usize = 2 * BLUR_STEPS + 1;
+const TILE_PASS: usize = PASS_COUNT; // composite one tile of a poster
 const BLUR_RADIUS: u32 = (4 * BLUR_STEPS << SCALING_STEPS) as _;
 
 const BLACK: wgpu::Color = wgpu::Color {
@@ -19,6 +23,19 @@ const BLACK: wgpu::Color = wgpu::Color {
 struct PostUniformRaw {
     image_size: [f32; 2],
     output_size: [f32; 2],
+    image_offset: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl PostUniformRaw {
+    fn new(image_size: [f32; 2], output_size: [f32; 2]) -> Self {
+        Self {
+            image_size,
+            output_size,
+            image_offset: [0.0, 0.0],
+            _padding: [0.0, 0.0],
+        }
+    }
 }
 
 #[repr(C)]
@@ -74,15 +91,17 @@ pub struct Post {
     uniform_aligned_size: usize,
     ldr_color: wgpu::TextureView,
     ping: wgpu::TextureView,
+    ping_texture: wgpu::Texture,
     pong: wgpu::TextureView,
+    kept_bloom_texture: wgpu::Texture,
     blur_pass_bindings: binding::BlurPassBindings,
     composite_pass_bindings: binding::CompositePassBindings,
-    hblur_pipeline: wgpu::RenderPipeline,
-    vblur_pipeline: wgpu::RenderPipeline,
-    composite_pipeline: wgpu::RenderPipeline,
+    binding_layouts: [wgpu::BindGroupLayout; 2], // static and frame
+    pipelines: PostPipelines,
     hblur_pass: PostPass,
     vblur_pass: PostPass,
     composite_pass: PostPass,
+    tile_pass: PostPass,
 }
 
 fn round_up(n: usize, align: u32) -> usize {
@@ -94,7 +113,7 @@ fn create_uniform_buffer(device: &wgpu::Device) -> (wgpu::Buffer, usize) {
     let raw_size = std::mem::size_of::<PostUniformRaw>();
     let min_align = device.limits().min_uniform_buffer_offset_alignment;
     let aligned_size = round_up(raw_size, min_align);
-    let buffer_size = PASS_COUNT * aligned_size;
+    let buffer_size = (PASS_COUNT + 1) * aligned_size;
     let mut data = vec![0u8; buffer_size];
 
     let mut insert = |pos: usize, post: PostUniformRaw| {
@@ -110,35 +129,17 @@ fn create_uniform_buffer(device: &wgpu::Device) -> (wgpu::Buffer, usize) {
         if i < SCALING_STEPS {
             output_size[0] *= 0.5;
         }
-        insert(
-            2 * i,
-            PostUniformRaw {
-                image_size,
-                output_size,
-            },
-        );
+        insert(2 * i, PostUniformRaw::new(image_size, output_size));
         if i < SCALING_STEPS {
             image_size[0] *= 0.5;
             output_size[1] *= 0.5;
         }
-        insert(
-            2 * i + 1,
-            PostUniformRaw {
-                image_size,
-                output_size,
-            },
-        );
+        insert(2 * i + 1, PostUniformRaw::new(image_size, output_size));
         if i < SCALING_STEPS {
             image_size[1] *= 0.5;
         }
     }
-    insert(
-        2 * BLUR_STEPS,
-        PostUniformRaw {
-            image_size,
-            output_size: [1.0, 1.0],
-        },
-    );
+    insert(2 * BLUR_STEPS, PostUniformRaw::new(image_size, [1.0, 1.0]));
     let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("post_uniform_buffer"),
         contents: bytemuck::cast_slice(&data),
@@ -181,9 +182,16 @@ fn create_framebuffer(
     device: &wgpu::Device,
     config: &Configuration,
 ) -> wgpu::TextureView {
+    framebuffer_view(label, &create_framebuffer_texture(label, device, config))
+}
+
+fn create_framebuffer_texture(
+    label: &str,
+    device: &wgpu::Device,
+    config: &Configuration,
+) -> wgpu::Texture {
     let texture_label = String::from(label) + "_texture";
-    let view_label = String::from(label) + "_view";
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
+    device.create_texture(&wgpu::TextureDescriptor {
         label: Some(&texture_label),
         size: wgpu::Extent3d {
             width: config.width,
@@ -195,10 +203,15 @@ fn create_framebuffer(
         dimension: wgpu::TextureDimension::D2,
         format: config.format,
         usage: wgpu::TextureUsages::TEXTURE_BINDING
-            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
-    });
+    })
+}
 
+fn framebuffer_view(label: &str, texture: &wgpu::Texture) -> wgpu::TextureView {
+    let view_label = String::from(label) + "_view";
     texture.create_view(&wgpu::TextureViewDescriptor {
         label: Some(&view_label),
         ..Default::default()
@@ -223,6 +236,7 @@ fn create_pipeline(
     device: &wgpu::Device,
     binding_layouts: &[&wgpu::BindGroupLayout],
     shader_module: &wgpu::ShaderModule,
+    constants: &[(&str, f64)],
     fragment_entry: &str,
     color_format: wgpu::TextureFormat,
 ) -> wgpu::RenderPipeline {
@@ -240,7 +254,10 @@ fn create_pipeline(
         vertex: wgpu::VertexState {
             module: &shader_module,
             entry_point: Some("vs_main"),
-            compilation_options: Default::default(),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
             buffers: &[PostVertexRaw::desc()],
         },
         primitive: wgpu::PrimitiveState {
@@ -261,20 +278,87 @@ fn create_pipeline(
         fragment: Some(wgpu::FragmentState {
             module: &shader_module,
             entry_point: Some(fragment_entry),
-            compilation_options: Default::default(),
-            targets: &[
-                Some(wgpu::ColorTargetState {
-                    format: color_format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::ALL,
-                }),
-            ],
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            targets: &[Some(wgpu::ColorTargetState {
+                format: color_format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
         }),
         multiview: None,
         cache: None,
     })
 }
 
+#[derive(Clone)]
+pub struct PostPipelines {
+    hblur: wgpu::RenderPipeline,
+    vblur: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+}
+
+impl PostPipelines {
+    fn new(
+        device: &wgpu::Device,
+        binding_layouts: &[wgpu::BindGroupLayout; 2],
+        blur_pass_layout: &wgpu::BindGroupLayout,
+        composite_pass_layout: &wgpu::BindGroupLayout,
+        shader_module: &wgpu::ShaderModule,
+        tone_map: shading::ToneMap,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let [static_binding_layout, frame_binding_layout] = binding_layouts;
+        let constants = tone_map.constants();
+        let hblur = create_pipeline(
+            "horizontal_blur",
+            device,
+            &[
+                static_binding_layout,
+                frame_binding_layout,
+                blur_pass_layout,
+            ],
+            shader_module,
+            &constants,
+            "fs_horizontal_blur_main",
+            crate::BRIGHT_COLOR_PIXEL_FORMAT,
+        );
+        let vblur = create_pipeline(
+            "vertical_blur",
+            device,
+            &[
+                static_binding_layout,
+                frame_binding_layout,
+                blur_pass_layout,
+            ],
+            shader_module,
+            &constants,
+            "fs_vertical_blur_main",
+            crate::BRIGHT_COLOR_PIXEL_FORMAT,
+        );
+        let composite = create_pipeline(
+            "composite",
+            device,
+            &[
+                static_binding_layout,
+                frame_binding_layout,
+                composite_pass_layout,
+            ],
+            shader_module,
+            &constants,
+            "fs_composite_main",
+            format,
+        );
+        Self {
+            hblur,
+            vblur,
+            composite,
+        }
+    }
+}
+
 impl Post {
     pub fn new(
         device: &wgpu::Device,
@@ -296,16 +380,23 @@ impl Post {
             device,
             &config.with_format(crate::LDR_COLOR_PIXEL_FORMAT),
         );
-        let ping = create_framebuffer(
+        let ping_texture = create_framebuffer_texture(
             "ping",
             device,
             &config.with_format(crate::BRIGHT_COLOR_PIXEL_FORMAT),
         );
+        let ping = framebuffer_view("ping", &ping_texture);
         let pong = create_framebuffer(
             "pong",
             device,
             &config.with_format(crate::BRIGHT_COLOR_PIXEL_FORMAT),
         );
+        let kept_bloom_texture = create_framebuffer_texture(
+            "kept_bloom",
+            device,
+            &config.with_format(crate::BRIGHT_COLOR_PIXEL_FORMAT),
+        );
+        let kept_bloom = framebuffer_view("kept_bloom", &kept_bloom_texture);
 
         // Framebuffer samplers
         let ldr_color_sampler = create_sampler("ldr_color_sampler", device);
@@ -346,6 +437,14 @@ impl Post {
             wgpu::BindingResource::TextureView(&ping),
             wgpu::BindingResource::Sampler(&ping_sampler),
         );
+        let tile_bind_group = composite_pass_bindings.create_bind_group(
+            device,
+            uniform_resource.clone(),
+            wgpu::BindingResource::TextureView(&ldr_color),
+            wgpu::BindingResource::Sampler(&ldr_color_sampler),
+            wgpu::BindingResource::TextureView(&kept_bloom),
+            wgpu::BindingResource::Sampler(&ping_sampler),
+        );
 
         // Shader
         let shader = wgpu::include_wgsl!("post_shaders.wgsl");
@@ -353,40 +452,15 @@ impl Post {
 
         // Pipelines
 
-        let hblur_pipeline = create_pipeline(
-            "horizontal_blur",
-            device,
-            &[
-                static_binding_layout,
-                frame_binding_layout,
-                &blur_pass_bindings.layout,
-            ],
-            &shader_module,
-            "fs_horizontal_blur_main",
-            crate::BRIGHT_COLOR_PIXEL_FORMAT,
-        );
-        let vblur_pipeline = create_pipeline(
-            "vertical_blur",
-            device,
-            &[
-                static_binding_layout,
-                frame_binding_layout,
-                &blur_pass_bindings.layout,
-            ],
-            &shader_module,
-            "fs_vertical_blur_main",
-            crate::BRIGHT_COLOR_PIXEL_FORMAT,
-        );
-        let composite_pipeline = create_pipeline(
-            "composite",
+        let binding_layouts =
+            [static_binding_layout.clone(), frame_binding_layout.clone()];
+        let pipelines = PostPipelines::new(
             device,
-            &[
-                static_binding_layout,
-                frame_binding_layout,
-                &composite_pass_bindings.layout,
-            ],
+            &binding_layouts,
+            &blur_pass_bindings.layout,
+            &composite_pass_bindings.layout,
             &shader_module,
-            "fs_composite_main",
+            shading::ToneMap::default(),
             config.format,
         );
 
@@ -408,6 +482,12 @@ impl Post {
             bind_group: composite_bind_group,
         };
 
+        let tile_pass = PostPass {
+            render_pass_label: String::from("tile_composite_render_pass"),
+            bind_group_index: binding::CompositePassBindings::GROUP_INDEX,
+            bind_group: tile_bind_group,
+        };
+
         Self {
             config,
             vertex_buffer,
@@ -416,18 +496,42 @@ impl Post {
             uniform_aligned_size,
             ldr_color,
             ping,
+            ping_texture,
             pong,
+            kept_bloom_texture,
             blur_pass_bindings,
             composite_pass_bindings,
-            hblur_pipeline,
-            vblur_pipeline,
-            composite_pipeline,
+            binding_layouts,
+            pipelines,
             hblur_pass,
             vblur_pass,
             composite_pass,
+            tile_pass,
         }
     }
 
+    // Pipelines for a tone map, or from a new version of the shader.
+    pub fn create_pipelines(
+        &self,
+        device: &wgpu::Device,
+        shader_module: &wgpu::ShaderModule,
+        tone_map: shading::ToneMap,
+    ) -> PostPipelines {
+        PostPipelines::new(
+            device,
+            &self.binding_layouts,
+            &self.blur_pass_bindings.layout,
+            &self.composite_pass_bindings.layout,
+            shader_module,
+            tone_map,
+            self.config.format,
+        )
+    }
+
+    pub fn set_pipelines(&mut self, pipelines: PostPipelines) {
+        self.pipelines = pipelines;
+    }
+
     pub fn input_framebuffer(&self) -> &wgpu::TextureView {
         &self.ldr_color
     }
@@ -436,6 +540,16 @@ impl Post {
         &self.ping
     }
 
+    // The bright framebuffer is also where the blur ping-pongs to, and
+    // ends up with the bloom.
+    pub fn bright_texture(&self) -> &wgpu::Texture {
+        &self.ping_texture
+    }
+
+    pub fn pong_framebuffer(&self) -> &wgpu::TextureView {
+        &self.pong
+    }
+
     pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
         self.config.width = width;
         self.config.height = height;
@@ -444,16 +558,24 @@ impl Post {
             device,
             &self.config.with_format(crate::LDR_COLOR_PIXEL_FORMAT),
         );
-        self.ping = create_framebuffer(
+        self.ping_texture = create_framebuffer_texture(
             "ping",
             device,
             &self.config.with_format(crate::BRIGHT_COLOR_PIXEL_FORMAT),
         );
+        self.ping = framebuffer_view("ping", &self.ping_texture);
         self.pong = create_framebuffer(
             "pong",
             device,
             &self.config.with_format(crate::BRIGHT_COLOR_PIXEL_FORMAT),
         );
+        self.kept_bloom_texture = create_framebuffer_texture(
+            "kept_bloom",
+            device,
+            &self.config.with_format(crate::BRIGHT_COLOR_PIXEL_FORMAT),
+        );
+        let kept_bloom =
+            framebuffer_view("kept_bloom", &self.kept_bloom_texture);
         let ldr_color_sampler = create_sampler("ldr_color_sampler", device);
         let ping_sampler = create_sampler("ping_sampler", device);
         let pong_sampler = create_sampler("pong_sampler", device);
@@ -486,45 +608,107 @@ impl Post {
                 wgpu::BindingResource::TextureView(&self.ping),
                 wgpu::BindingResource::Sampler(&ping_sampler),
             );
+        self.tile_pass.bind_group =
+            self.composite_pass_bindings.create_bind_group(
+                device,
+                uniform_resource.clone(),
+                wgpu::BindingResource::TextureView(&self.ldr_color),
+                wgpu::BindingResource::Sampler(&ldr_color_sampler),
+                wgpu::BindingResource::TextureView(&kept_bloom),
+                wgpu::BindingResource::Sampler(&ping_sampler),
+            );
+    }
+
+    // Keep the bloom of the frame just rendered for `render_tile`.
+    pub fn keep_bloom(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_texture(
+            self.ping_texture.as_image_copy(),
+            self.kept_bloom_texture.as_image_copy(),
+            self.ping_texture.size(),
+        );
+    }
+
+    // Composite one tile of a poster.  Tiles are too small to blur on
+    // their own, so the bloom is the kept bloom of the whole view,
+    // magnified; that way the tiles match the window and each other.
+    pub fn render_tile(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        image_out: &wgpu::TextureView,
+        other_bind_groups: &[&wgpu::BindGroup],
+        tile: &camera::Tile,
+    ) {
+        // The blurred image fills this much of the bloom texture.
+        let shrink = 0.5f32.powi(SCALING_STEPS.min(BLUR_STEPS) as i32);
+        let n = tile.count as f32;
+        let size = shrink / n;
+        let uniform = PostUniformRaw {
+            image_size: [size, size],
+            output_size: [1.0, 1.0],
+            // Rows count from the top, image coordinates from the bottom.
+            image_offset: [
+                tile.column as f32 * size,
+                (tile.count - 1 - tile.row) as f32 * size,
+            ],
+            _padding: [0.0, 0.0],
+        };
+        queue.write_buffer(
+            &self.uniform_buffer,
+            (TILE_PASS * self.uniform_aligned_size) as wgpu::BufferAddress,
+            bytemuck::bytes_of(&uniform),
+        );
+        self.render_post_pass(
+            encoder,
+            &self.pipelines.composite,
+            &self.tile_pass,
+            image_out,
+            TILE_PASS,
+            other_bind_groups,
+            None,
+            None,
+        );
     }
 
     pub fn render(
         &self,
-        _device: &wgpu::Device,
-        _queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
         image_out: &wgpu::TextureView,
         other_bind_groups: &[&wgpu::BindGroup],
         bloom_bounds: &bounds::Bounds,
+        profiler: &mut profiler::Profiler,
     ) {
         for i in 0..BLUR_STEPS {
             self.render_post_pass(
                 encoder,
-                &self.hblur_pipeline,
+                &self.pipelines.hblur,
                 &self.hblur_pass,
                 &self.pong,
                 2 * i,
                 other_bind_groups,
                 Some(bloom_bounds),
+                profiler.pass(&format!("hblur_{}", i)),
             );
             self.render_post_pass(
                 encoder,
-                &self.vblur_pipeline,
+                &self.pipelines.vblur,
                 &self.vblur_pass,
                 &self.ping,
                 2 * i + 1,
                 other_bind_groups,
                 Some(bloom_bounds),
+                profiler.pass(&format!("vblur_{}", i)),
             );
         }
         self.render_post_pass(
             encoder,
-            &self.composite_pipeline,
+            &self.pipelines.composite,
             &self.composite_pass,
             image_out,
             2 * BLUR_STEPS,
             other_bind_groups,
             None,
+            profiler.pass("composite"),
         );
     }
 
@@ -537,6 +721,7 @@ impl Post {
         pass_number: usize,
         other_bind_groups: &[&wgpu::BindGroup],
         bloom_bounds: Option<&bounds::Bounds>,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
     ) {
         // const NEXT_LAST: usize = PASS_COUNT - 2;
         // let load_op = match pass_number {
@@ -559,23 +744,21 @@ impl Post {
         let mut render_pass =
             encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some(&pass.render_pass_label),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: image_out,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: load_op,
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: image_out,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: load_op,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes,
                 occlusion_query_set: None,
             });
         let mut owf = 1.0 / (1 << (pass_number + 2) / 2) as f32;
         let mut ohf = 1.0 / (1 << (pass_number + 1) / 2) as f32;
-        if pass_number == 2 * BLUR_STEPS as usize {
+        if pass_number >= 2 * BLUR_STEPS as usize {
             owf = 1.0;
             ohf = 1.0;
         }
@@ -1,6 +1,7 @@
 struct PostUniform {
     image_size: vec2<f32>,
     output_size: vec2<f32>,
+    image_offset: vec2<f32>,
 }
 @group(2) @binding(0)
 var<uniform> post: PostUniform;
@@ -22,9 +23,10 @@ fn vs_main(
     let pos = vec4<f32>(xy, position.z, position.a);
 
     // uv: domain is (-1, -1)..(+1, +1)
-    //     range is (0, 1)..(image_size.x, 1 - image_size.y).
+    //     range is (0, 1)..(image_size.x, 1 - image_size.y),
+    //     shifted by image_offset.
     //     Y coordinate is reversed.
-    let uv = (position.xy + 1.0) * 0.5 * post.image_size;
+    let uv = (position.xy + 1.0) * 0.5 * post.image_size + post.image_offset;
     let coord = vec2<f32>(uv.x, 1.0 - uv.y);
     
     return VertexOutput(pos, coord);
@@ -149,6 +151,25 @@ fn fs_vertical_blur_main(
 const EXPOSURE: f32 = 1.0;
 const GAMMA: f32 = 2.2;
 
+// Which tone map, set when the pipeline is made.  See shading.rs.
+//   0: exposure
+//   1: Reinhard
+//   2: extended Reinhard
+//   3: Reinhard on luminance
+override TONE_MAP: u32 = 3u;
+
+// The start of common_shader.wgsl's TweaksUniform, which is all the
+// composite needs.  See tweaks.rs.
+struct TweaksUniform {
+    edge_color: vec4<f32>,
+    led_brightness: f32,
+    bloom: f32,
+    bloom_threshold: f32,
+    white_point: f32,
+}
+@group(0) @binding(6)
+var<uniform> tweaks: TweaksUniform;
+
 @group(2) @binding(3)
 var t_bright: texture_2d<f32>;
 
@@ -192,17 +213,33 @@ fn reinhard_luminance_tone_map(C: vec3<f32>, Lwhite: f32) -> vec3<f32> {
 fn fs_composite_main(
     in: VertexOutput,
 ) -> @location(0) vec4<f32> {
-    let ldr_index = vec2<i32>(in.position.xy + 0.5);
+    // Fragment positions are pixel centers, at .5, so this is the
+    // fragment's own pixel.  Rounding them read the next pixel over,
+    // which is past the edge of a tile's image.
+    let ldr_index = vec2<i32>(in.position.xy);
     let bright_coord = in.coord;
     let ldr_color = textureLoad(t_image, ldr_index, 0).rgb;
     let bright_color = textureSample(t_bright, s_bright, bright_coord).rgb;
-    let hdr_color = ldr_color + bright_color;
+    let hdr_color = ldr_color + tweaks.bloom * bright_color;
 
     // tone mapping
-    // let mapped_color = exposure_tone_map(hdr_color, EXPOSURE);
-    // let mapped_color = reinhard_simple_tone_map(hdr_color);
-    // let mapped_color = reinhard_extended_tone_map(hdr_color, 2.0);
-    let mapped_color = reinhard_luminance_tone_map(hdr_color, 2.0);
+    var mapped_color: vec3<f32>;
+    switch TONE_MAP {
+        case 0u: {
+            mapped_color = exposure_tone_map(hdr_color, EXPOSURE);
+        }
+        case 1u: {
+            mapped_color = reinhard_simple_tone_map(hdr_color);
+        }
+        case 2u: {
+            mapped_color =
+                reinhard_extended_tone_map(hdr_color, tweaks.white_point);
+        }
+        default: {
+            mapped_color =
+                reinhard_luminance_tone_map(hdr_color, tweaks.white_point);
+        }
+    }
 
     // // Uncomment to show HDR - LDR
     // let unbright = reinhard_luminance_tone_map(ldr_color, 2.0);
@@ -18,69 +18,80 @@ pub struct Configuration {
 pub struct PreFloor {
     glow_view: wgpu::TextureView,
     glow_sampler: wgpu::Sampler,
-    pipeline: wgpu::RenderPipeline,
+    glow_size: (u32, u32),
+    pipeline_layout: wgpu::PipelineLayout,
 }
 
 impl PreFloor {
     pub fn new(
         device: &wgpu::Device,
         config: &Configuration,
-        shader: &wgpu::ShaderModule,
         static_binding_layout: &wgpu::BindGroupLayout,
         frame_binding_layout: &wgpu::BindGroupLayout,
     ) -> Self {
         let (glow_view, glow_sampler) = Self::create_glow(device, config);
-        let pipeline = {
-            let layout = device.create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: Some("prefloor_pipeline_layout"),
-                    bind_group_layouts: &[
-                        static_binding_layout,
-                        frame_binding_layout,
-                    ],
-                    push_constant_ranges: &[],
-                },
-            );
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("prefloor_pipeline"),
-                layout: Some(&layout),
-                vertex: wgpu::VertexState {
-                    module: shader,
-                    entry_point: Some("vs_floor_main"),
-                    compilation_options: Default::default(),
-                    buffers: &[crate::floor::FloorVertexRaw::desc()],
-                },
-                primitive: Default::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                // fragment: None,
-                fragment: Some(wgpu::FragmentState {
-                    module: shader,
-                    entry_point: Some("fs_prefloor_main"),
-                    compilation_options: Default::default(),
-                    targets: &[
-                        Some(wgpu::ColorTargetState {
-                            format: wgpu::TextureFormat::Rgba16Float,
-                            blend: None,
-                            write_mask: wgpu::ColorWrites::ALL,
-                        }),
-                    ],
-                }),
-                multiview: None,
-                cache: None,
-            })
-        };
+        let glow_size = Self::glow_size(config);
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("prefloor_pipeline_layout"),
+                bind_group_layouts: &[
+                    static_binding_layout,
+                    frame_binding_layout,
+                ],
+                push_constant_ranges: &[],
+            });
         Self {
             glow_view,
             glow_sampler,
-            pipeline,
+            glow_size,
+            pipeline_layout,
         }
     }
 
+    // The pipeline for one set of shading options.  `State` keeps it
+    // with the forward pipelines.
+    pub fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        constants: &[(&str, f64)],
+    ) -> wgpu::RenderPipeline {
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants,
+            ..Default::default()
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("prefloor_pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_floor_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[crate::floor::FloorVertexRaw::desc()],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            // fragment: None,
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_prefloor_main"),
+                compilation_options,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba16Float,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        })
+    }
+
     // XXX need to recreate forward pass bind group.
     // It works well enough with the default aspect ratio...
     // pub fn resize(&mut self, device: &wgpu::Device, config: &Configuration) {
@@ -99,52 +110,70 @@ impl PreFloor {
         wgpu::BindingResource::Sampler(&self.glow_sampler)
     }
 
+    // With two pipelines, as when comparing shading, the first draws the
+    // left half and the second the right.
     pub fn render(
         &self,
         encoder: &mut wgpu::CommandEncoder,
+        pipelines: &[&wgpu::RenderPipeline],
         other_bind_groups: &[&wgpu::BindGroup],
         vertex_slice: wgpu::BufferSlice,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
     ) {
         let mut render_pass =
             encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("prefloor_render_pass"),
-                color_attachments: &[
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &self.glow_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.0,
-                                g: 0.0,
-                                b: 0.0,
-                                a: 0.0,
-                            }),
-                            store: wgpu::StoreOp::Store,
-                        },
-                    })
-                ],
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.glow_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.0,
+                            g: 0.0,
+                            b: 0.0,
+                            a: 0.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes,
                 occlusion_query_set: None,
-
             });
-        render_pass.set_pipeline(&self.pipeline);
         for (i, bg) in other_bind_groups.iter().enumerate() {
             render_pass.set_bind_group(i as u32, *bg, &[]);
         }
         render_pass.set_vertex_buffer(0, vertex_slice);
-        render_pass.draw(0..6, 0..1);
+        let (width, height) = self.glow_size;
+        let columns = crate::shading::split_columns(width, None);
+        for (pipeline, columns) in pipelines.iter().zip(columns) {
+            if pipelines.len() > 1 {
+                render_pass.set_scissor_rect(
+                    columns.start,
+                    0,
+                    columns.len() as u32,
+                    height,
+                );
+            }
+            render_pass.set_pipeline(pipeline);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+
+    fn glow_size(config: &Configuration) -> (u32, u32) {
+        (GLOW_HEIGHT * config.width / config.height, GLOW_HEIGHT)
     }
 
     fn create_glow(
         device: &wgpu::Device,
         config: &Configuration,
     ) -> (wgpu::TextureView, wgpu::Sampler) {
+        let (width, height) = Self::glow_size(config);
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("prefloor_glow_texture"),
             size: wgpu::Extent3d {
-                width: GLOW_HEIGHT * config.width / config.height,
-                height: GLOW_HEIGHT,
+                width,
+                height,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
@@ -155,7 +184,6 @@ impl PreFloor {
                 | wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::RENDER_ATTACHMENT,
             view_formats: &[],
-
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("prefloor_glow_view"),
@@ -0,0 +1,488 @@
+// Timing for each render pass.
+//
+// With `profile` set (see settings.rs), every pass of a frame is timed
+// and the rolling average of each, over the last ROLLING_FRAMES frames
+// it ran in, is printed once a second.  With `profile_out` set, a
+// summary of the whole run is written there when the program is done,
+// as CSV or JSON by the file's extension.
+//
+// When the adapter has TIMESTAMP_QUERY, each pass writes GPU timestamps
+// as it starts and ends.  They're resolved into a buffer that's read
+// back a few frames later, so nothing waits on the GPU; a frame that
+// finds every readback buffer still in use isn't timed.  Otherwise the
+// passes are timed on the CPU as they're encoded, each until the next
+// starts, which shows what recording them costs but not what the GPU
+// makes of them.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::settings::settings;
+
+// Timestamps for this many passes per frame; any more go untimed.
+const MAX_PASSES: usize = 32;
+
+// Frames that can be waiting to be read back at once.
+const READBACK_COUNT: usize = 4;
+
+const ROLLING_FRAMES: usize = 60;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+// Whether profiling is on.
+fn enabled() -> bool {
+    settings().profile || settings().profile_out.is_some()
+}
+
+// The features a device needs for GPU timing, if the adapter has them
+// and profiling is on.
+pub fn features(adapter: &wgpu::Adapter) -> wgpu::Features {
+    let features = wgpu::Features::TIMESTAMP_QUERY;
+    match enabled() && adapter.features().contains(features) {
+        true => features,
+        false => wgpu::Features::empty(),
+    }
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    timer: Option<Timer>, // none when profiling is off
+    recording: bool,      // between `begin_frame` and `end_frame`
+    stats: Vec<PassStats>,
+    last_report: Option<Instant>,
+}
+
+enum Timer {
+    Gpu(GpuTimer),
+    Cpu(CpuTimer),
+}
+
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readbacks: Vec<Readback>,
+    current: Option<usize>, // the readback for the frame being recorded
+    period: f32,            // nanoseconds per timestamp tick
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    passes: Vec<String>,
+    // Hears from `map_async` while the buffer is being mapped.
+    mapping: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+#[derive(Default)]
+struct CpuTimer {
+    pass: Option<(String, Instant)>,
+}
+
+struct PassStats {
+    name: String,
+    recent: VecDeque<f64>,
+    count: u64,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+// One line of the summary that `write` exports.
+#[derive(Serialize)]
+struct Summary<'a> {
+    pass: &'a str,
+    timer: &'static str,
+    frames: u64,
+    mean_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+    recent_ms: f64,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        if !enabled() {
+            return Self::default();
+        }
+        let timer =
+            match device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+                true => Timer::Gpu(GpuTimer::new(device, queue)),
+                false => Timer::Cpu(CpuTimer::default()),
+            };
+        Self {
+            timer: Some(timer),
+            ..Self::default()
+        }
+    }
+
+    // Start timing a frame, picking up the times of frames before it
+    // that the GPU has finished.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        let Some(timer) = &mut self.timer else {
+            return;
+        };
+        if let Timer::Gpu(gpu) = timer {
+            if let Err(e) = device.poll(wgpu::PollType::Poll) {
+                eprintln!("profiler: {}", e);
+            }
+            gpu.collect(&mut self.stats);
+            gpu.current = gpu.readbacks.iter().position(Readback::is_free);
+        }
+        self.recording = true;
+        if settings().profile {
+            let now = Instant::now();
+            match self.last_report {
+                Some(last) if now - last < REPORT_INTERVAL => {}
+                Some(_) => {
+                    self.print_report();
+                    self.last_report = Some(now);
+                }
+                None => self.last_report = Some(now),
+            }
+        }
+    }
+
+    // Time a pass called `name`, which must be unique in the frame.
+    // Returns the timestamp writes for its descriptor.
+    pub fn pass(
+        &mut self,
+        name: &str,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        if !self.recording {
+            return None;
+        }
+        match self.timer.as_mut()? {
+            Timer::Gpu(gpu) => {
+                let readback = &mut gpu.readbacks[gpu.current?];
+                let index = readback.passes.len();
+                if index == MAX_PASSES {
+                    return None;
+                }
+                readback.passes.push(name.to_string());
+                Some(wgpu::RenderPassTimestampWrites {
+                    query_set: &gpu.query_set,
+                    beginning_of_pass_write_index: Some(2 * index as u32),
+                    end_of_pass_write_index: Some(2 * index as u32 + 1),
+                })
+            }
+            Timer::Cpu(cpu) => {
+                cpu.end_pass(&mut self.stats);
+                cpu.pass = Some((name.to_string(), Instant::now()));
+                None
+            }
+        }
+    }
+
+    // Finish timing a frame: resolve its timestamps, to be read back
+    // once it's been submitted.
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !std::mem::take(&mut self.recording) {
+            return;
+        }
+        match &mut self.timer {
+            Some(Timer::Gpu(gpu)) => gpu.resolve(encoder),
+            Some(Timer::Cpu(cpu)) => cpu.end_pass(&mut self.stats),
+            None => {}
+        }
+    }
+
+    // Call after submitting the encoder given to `end_frame`.
+    pub fn after_submit(&mut self) {
+        if let Some(Timer::Gpu(gpu)) = &mut self.timer {
+            gpu.read_back();
+        }
+    }
+
+    // Wait for the frames still on the GPU and write the summary to
+    // `profile_out`, if it's set.
+    pub fn finish(&mut self, device: &wgpu::Device) -> Result<()> {
+        if let Some(Timer::Gpu(gpu)) = &mut self.timer {
+            device.poll(wgpu::PollType::Wait)?;
+            gpu.collect(&mut self.stats);
+        }
+        match &settings().profile_out {
+            Some(path) => self.write(path),
+            None => Ok(()),
+        }
+    }
+
+    fn timer_name(&self) -> &'static str {
+        match self.timer {
+            Some(Timer::Gpu(_)) => "gpu",
+            _ => "cpu",
+        }
+    }
+
+    pub fn print_report(&self) {
+        if self.stats.is_empty() {
+            return;
+        }
+        let passes: Vec<_> = self
+            .stats
+            .iter()
+            .map(|stats| format!("{} {:.3}", stats.name, stats.recent_mean()))
+            .collect();
+        let total: f64 = self.stats.iter().map(PassStats::recent_mean).sum();
+        println!(
+            "{} ms: {}, total {:.3}",
+            self.timer_name(),
+            passes.join(", "),
+            total
+        );
+    }
+
+    fn summary(&self) -> Vec<Summary<'_>> {
+        self.stats
+            .iter()
+            .map(|stats| Summary {
+                pass: &stats.name,
+                timer: self.timer_name(),
+                frames: stats.count,
+                mean_ms: stats.total / stats.count as f64,
+                min_ms: stats.min,
+                max_ms: stats.max,
+                recent_ms: stats.recent_mean(),
+            })
+            .collect()
+    }
+
+    // Write the summary as CSV or JSON, by `path`'s extension.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let summary = self.summary();
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::to_string_pretty(&summary)? + "\n",
+            Some("csv") => to_csv(&summary),
+            _ => bail!("{}: profiles are .csv or .json", path.display()),
+        };
+        std::fs::write(path, text)
+            .with_context(|| format!("can't write {}", path.display()))
+    }
+}
+
+fn to_csv(summary: &[Summary]) -> String {
+    let mut csv =
+        String::from("pass,timer,frames,mean_ms,min_ms,max_ms,recent_ms\n");
+    for line in summary {
+        csv += &format!(
+            "{},{},{},{:.4},{:.4},{:.4},{:.4}\n",
+            line.pass,
+            line.timer,
+            line.frames,
+            line.mean_ms,
+            line.min_ms,
+            line.max_ms,
+            line.recent_ms,
+        );
+    }
+    csv
+}
+
+// Add a pass's time to its stats.
+fn record(stats: &mut Vec<PassStats>, name: &str, ms: f64) {
+    let index = match stats.iter().position(|stats| stats.name == name) {
+        Some(index) => index,
+        None => {
+            stats.push(PassStats::new(name));
+            stats.len() - 1
+        }
+    };
+    stats[index].add(ms);
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = 2 * MAX_PASSES as u64 * TIMESTAMP_SIZE;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("profiler_query_set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2 * MAX_PASSES as u32,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("profiler_resolve_buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACK_COUNT)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("profiler_readback_buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ
+                        | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                passes: Vec::new(),
+                mapping: None,
+            })
+            .collect();
+        Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            current: None,
+            period: queue.get_timestamp_period(),
+        }
+    }
+
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(current) = self.current else {
+            return;
+        };
+        let readback = &self.readbacks[current];
+        let count = 2 * readback.passes.len() as u32;
+        if count == 0 {
+            return;
+        }
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..count,
+            &self.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            count as u64 * TIMESTAMP_SIZE,
+        );
+    }
+
+    fn read_back(&mut self) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        let readback = &mut self.readbacks[current];
+        if readback.passes.is_empty() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        readback.buffer.slice(..).map_async(
+            wgpu::MapMode::Read,
+            move |result| {
+                let _ = sender.send(result);
+            },
+        );
+        readback.mapping = Some(receiver);
+    }
+
+    // Record the times of the frames that have been read back.
+    fn collect(&mut self, stats: &mut Vec<PassStats>) {
+        for readback in &mut self.readbacks {
+            let Some(mapping) = &readback.mapping else {
+                continue;
+            };
+            match mapping.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => continue,
+                Ok(Ok(())) => {
+                    let data = readback.buffer.slice(..).get_mapped_range();
+                    let ticks: &[u64] = bytemuck::cast_slice(&data);
+                    for (name, ticks) in
+                        readback.passes.iter().zip(ticks.chunks_exact(2))
+                    {
+                        let elapsed = ticks[1].saturating_sub(ticks[0]);
+                        let ms = elapsed as f64 * self.period as f64 / 1e6;
+                        record(stats, name, ms);
+                    }
+                    drop(data);
+                    readback.buffer.unmap();
+                }
+                Ok(Err(e)) => eprintln!("profiler: {}", e),
+                Err(mpsc::TryRecvError::Disconnected) => {}
+            }
+            readback.passes.clear();
+            readback.mapping = None;
+        }
+    }
+}
+
+impl Readback {
+    fn is_free(&self) -> bool {
+        self.mapping.is_none() && self.passes.is_empty()
+    }
+}
+
+impl CpuTimer {
+    fn end_pass(&mut self, stats: &mut Vec<PassStats>) {
+        if let Some((name, start)) = self.pass.take() {
+            record(stats, &name, start.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+impl PassStats {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            recent: VecDeque::with_capacity(ROLLING_FRAMES),
+            count: 0,
+            total: 0.0,
+            min: f64::INFINITY,
+            max: 0.0,
+        }
+    }
+
+    fn add(&mut self, ms: f64) {
+        if self.recent.len() == ROLLING_FRAMES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ms);
+        self.count += 1;
+        self.total += ms;
+        self.min = self.min.min(ms);
+        self.max = self.max.max(ms);
+    }
+
+    fn recent_mean(&self) -> f64 {
+        self.recent.iter().sum::<f64>() / self.recent.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_roll() {
+        let mut stats = Vec::new();
+        for frame in 0..ROLLING_FRAMES + 10 {
+            record(&mut stats, "forward", frame as f64);
+            record(&mut stats, "composite", 1.0);
+        }
+        let names: Vec<_> = stats.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["forward", "composite"]);
+        let forward = &stats[0];
+        assert_eq!(forward.count, ROLLING_FRAMES as u64 + 10);
+        assert_eq!(forward.min, 0.0);
+        assert_eq!(forward.max, (ROLLING_FRAMES + 9) as f64);
+        // The last ROLLING_FRAMES frames are 10 to ROLLING_FRAMES + 9.
+        assert_eq!(forward.recent_mean(), (ROLLING_FRAMES + 19) as f64 / 2.0);
+        assert_eq!(stats[1].recent_mean(), 1.0);
+    }
+
+    #[test]
+    fn writes_csv() {
+        let summary = [Summary {
+            pass: "shadow_0",
+            timer: "gpu",
+            frames: 3,
+            mean_ms: 0.5,
+            min_ms: 0.25,
+            max_ms: 1.0,
+            recent_ms: 0.5,
+        }];
+        assert_eq!(
+            to_csv(&summary),
+            "pass,timer,frames,mean_ms,min_ms,max_ms,recent_ms\n\
+             shadow_0,gpu,3,0.5000,0.2500,1.0000,0.5000\n"
+        );
+    }
+}
@@ -0,0 +1,623 @@
+// Recordings of what the LEDs showed.
+//
+// A recording is a header followed by timestamped frames.  All numbers
+// are little-endian; "varint" is LEB128.
+//
+//     header  "CUBEREC" 1                  magic and version
+//             width: u16, height: u16      panel size in LEDs
+//             faces: u8, channels: u8      6 and 4 (RGBA)
+//     frame   delta: varint                microseconds since the last
+//                                          frame (since recording
+//                                          started, for the first)
+//             kind: u8
+//             length: varint               payload bytes
+//             payload
+//
+// Frames are laid out the way `Blinky` uploads them.  There are three
+// kinds:
+//  - key (0): the whole frame.  The first frame is a key, and so is
+//    every `KEY_INTERVAL`th, so playback can seek.
+//  - repeat (1): no payload; same as the previous frame.
+//  - xor (2): the frame XORed with the previous one, as runs of
+//    "skip: varint, count: varint, count XORed bytes".
+//
+// The record setting is a file to record to.  The playback setting is
+// a recording to play back as the "playback" pattern.
+//
+// `wgpu-cube recording stats FILE` prints a recording's statistics and
+// `wgpu-cube recording extract FILE FRAME PNG` saves one frame.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::panel::{panel, Panel};
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
+use crate::test_pattern::{PixelArray, CHANNEL_COUNT, FACE_COUNT};
+
+pub const PATTERN_NAME: &str = "playback";
+
+const MAGIC: &[u8; 7] = b"CUBEREC";
+const VERSION: u8 = 1;
+const KEY_INTERVAL: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub width: usize,
+    pub height: usize,
+    pub faces: usize,
+    pub channels: usize,
+}
+
+impl Header {
+    fn for_panel(panel: Panel) -> Self {
+        Self {
+            width: panel.width,
+            height: panel.height,
+            faces: FACE_COUNT,
+            channels: CHANNEL_COUNT,
+        }
+    }
+
+    pub fn frame_bytes(&self) -> usize {
+        self.faces * self.width * self.height * self.channels
+    }
+
+    fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&(self.width as u16).to_le_bytes())?;
+        out.write_all(&(self.height as u16).to_le_bytes())?;
+        out.write_all(&[self.faces as u8, self.channels as u8])
+    }
+
+    fn read(bytes: &[u8]) -> Result<(Self, usize)> {
+        const SIZE: usize = 14;
+        if bytes.len() < SIZE || &bytes[..7] != MAGIC {
+            bail!("not a cube recording");
+        }
+        if bytes[7] != VERSION {
+            bail!("unknown recording version {}", bytes[7]);
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let header = Self {
+            width: u16_at(8) as usize,
+            height: u16_at(10) as usize,
+            faces: bytes[12] as usize,
+            channels: bytes[13] as usize,
+        };
+        Ok((header, SIZE))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Key,
+    Repeat,
+    Xor,
+}
+
+impl Kind {
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Kind::Key),
+            1 => Ok(Kind::Repeat),
+            2 => Ok(Kind::Xor),
+            _ => bail!("unknown frame kind {}", byte),
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            Kind::Key => 0,
+            Kind::Repeat => 1,
+            Kind::Xor => 2,
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos).ok_or_else(|| anyhow!("truncated"))?;
+        *pos += 1;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    bail!("bad varint")
+}
+
+// Runs of bytes that differ between `previous` and `frame`.  A run
+// continues across short stretches of equal bytes, which are cheaper
+// to XOR than to skip.
+fn encode_xor(previous: &[u8], frame: &[u8], out: &mut Vec<u8>) {
+    const MIN_SKIP: usize = 3;
+    let same = |i: usize| previous[i] == frame[i];
+    let mut i = 0;
+    while i < frame.len() {
+        let start = i;
+        while i < frame.len() && same(i) {
+            i += 1;
+        }
+        if i == frame.len() {
+            break;
+        }
+        let skip = i - start;
+        let run_start = i;
+        while i < frame.len() {
+            let equal = (i..(i + MIN_SKIP).min(frame.len())).all(same);
+            if equal {
+                break;
+            }
+            i += 1;
+        }
+        write_varint(out, skip as u64);
+        write_varint(out, (i - run_start) as u64);
+        out.extend((run_start..i).map(|j| previous[j] ^ frame[j]));
+    }
+}
+
+fn decode_xor(payload: &[u8], frame: &mut [u8]) -> Result<()> {
+    let (mut pos, mut i) = (0, 0);
+    while pos < payload.len() {
+        i += read_varint(payload, &mut pos)? as usize;
+        let count = read_varint(payload, &mut pos)? as usize;
+        let (Some(dst), Some(src)) =
+            (frame.get_mut(i..i + count), payload.get(pos..pos + count))
+        else {
+            bail!("xor run out of bounds");
+        };
+        dst.iter_mut().zip(src).for_each(|(d, s)| *d ^= s);
+        (i, pos) = (i + count, pos + count);
+    }
+    Ok(())
+}
+
+// Writes frames to a file as they are shown.
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: Option<Instant>,
+    last_time: Duration,
+    previous: Vec<u8>,
+    frame_count: usize,
+    payload: Vec<u8>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("can't create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        Header::for_panel(panel()).write(&mut out)?;
+        Ok(Self {
+            out,
+            start: None,
+            last_time: Duration::ZERO,
+            previous: Vec::new(),
+            frame_count: 0,
+            payload: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, now: Instant, frame: &PixelArray) -> Result<()> {
+        let time = now.duration_since(*self.start.get_or_insert(now));
+        self.write(time, frame)
+    }
+
+    fn write(&mut self, time: Duration, frame: &PixelArray) -> Result<()> {
+        self.payload.clear();
+        let kind = if self.frame_count.is_multiple_of(KEY_INTERVAL) {
+            Kind::Key
+        } else if frame == self.previous.as_slice() {
+            Kind::Repeat
+        } else {
+            encode_xor(&self.previous, frame, &mut self.payload);
+            match self.payload.len() < frame.len() {
+                true => Kind::Xor,
+                false => Kind::Key,
+            }
+        };
+        if kind == Kind::Key {
+            self.payload.clear();
+            self.payload.extend_from_slice(frame);
+        }
+
+        let mut prefix = Vec::new();
+        let delta = time.saturating_sub(self.last_time);
+        write_varint(&mut prefix, delta.as_micros() as u64);
+        prefix.push(kind.byte());
+        write_varint(&mut prefix, self.payload.len() as u64);
+        self.out.write_all(&prefix)?;
+        self.out.write_all(&self.payload)?;
+        if kind == Kind::Key {
+            self.out.flush()?;
+        }
+
+        self.last_time = time;
+        self.previous.clear();
+        self.previous.extend_from_slice(frame);
+        self.frame_count += 1;
+        Ok(())
+    }
+}
+
+struct Entry {
+    time: Duration, // since the recording started
+    kind: Kind,
+    payload: Range<usize>,
+}
+
+pub struct Recording {
+    pub header: Header,
+    bytes: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        Self::parse(bytes).with_context(|| format!("{}", path.display()))
+    }
+
+    // A recording cut short, e.g. by a crash, ends at its last
+    // complete frame.
+    pub fn parse(bytes: Vec<u8>) -> Result<Self> {
+        let (header, mut pos) = Header::read(&bytes)?;
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut time = Duration::ZERO;
+        while pos < bytes.len() {
+            let Ok(entry) = Self::read_entry(&bytes, &mut pos, time) else {
+                break;
+            };
+            if entries.is_empty() && entry.kind != Kind::Key {
+                bail!("the first frame isn't a key frame");
+            }
+            if entry.kind == Kind::Key
+                && entry.payload.len() != header.frame_bytes()
+            {
+                bail!("frame {} is the wrong size", entries.len());
+            }
+            time = entry.time;
+            entries.push(entry);
+        }
+        if entries.is_empty() {
+            bail!("no frames");
+        }
+        Ok(Self {
+            header,
+            bytes,
+            entries,
+        })
+    }
+
+    fn read_entry(
+        bytes: &[u8],
+        pos: &mut usize,
+        time: Duration,
+    ) -> Result<Entry> {
+        let delta = Duration::from_micros(read_varint(bytes, pos)?);
+        let kind = Kind::from_byte(
+            *bytes.get(*pos).ok_or_else(|| anyhow!("truncated"))?,
+        )?;
+        *pos += 1;
+        let length = read_varint(bytes, pos)? as usize;
+        let payload = *pos..*pos + length;
+        if payload.end > bytes.len() {
+            bail!("truncated");
+        }
+        *pos = payload.end;
+        Ok(Entry {
+            time: time + delta,
+            kind,
+            payload,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn time(&self, index: usize) -> Duration {
+        self.entries[index].time
+    }
+
+    // How long one pass takes when looping: the last frame is shown
+    // for an average frame's time.
+    pub fn loop_duration(&self) -> Duration {
+        let last = self.entries.last().unwrap().time;
+        match self.len() {
+            1 => last,
+            n => last + last / (n as u32 - 1),
+        }
+    }
+
+    // The frame shown at `time` after the recording started.
+    fn index_at(&self, time: Duration) -> usize {
+        let index = self.entries.partition_point(|e| e.time <= time);
+        index.saturating_sub(1)
+    }
+
+    // Turn `frame`, which holds frame `index - 1`, into frame `index`.
+    fn apply(&self, index: usize, frame: &mut [u8]) -> Result<()> {
+        let entry = &self.entries[index];
+        let payload = &self.bytes[entry.payload.clone()];
+        match entry.kind {
+            Kind::Key => frame.copy_from_slice(payload),
+            Kind::Repeat => (),
+            Kind::Xor => decode_xor(payload, frame)?,
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> String {
+        let mut counts = [0usize; 3];
+        let mut sizes = [0usize; 3];
+        for entry in &self.entries {
+            let k = entry.kind.byte() as usize;
+            counts[k] += 1;
+            sizes[k] += entry.payload.len();
+        }
+        let longest_gap = self
+            .entries
+            .windows(2)
+            .map(|pair| pair[1].time - pair[0].time)
+            .max()
+            .unwrap_or_default();
+        let duration = self.entries.last().unwrap().time;
+        let raw = self.len() * self.header.frame_bytes();
+        let mut text = format!(
+            "panel {}x{}, {} faces\n\
+             {} frames in {:.3} s ({:.1} fps), longest gap {:.1} ms\n",
+            self.header.width,
+            self.header.height,
+            self.header.faces,
+            self.len(),
+            duration.as_secs_f64(),
+            (self.len() - 1) as f64 / duration.as_secs_f64().max(1e-9),
+            longest_gap.as_secs_f64() * 1e3,
+        );
+        for (k, name) in ["key", "repeat", "xor"].iter().enumerate() {
+            text += &format!(
+                "{:>6} frames: {:>6}, {:>10} bytes\n",
+                name, counts[k], sizes[k]
+            );
+        }
+        text += &format!(
+            "{} bytes, {:.1}x smaller than raw frames",
+            self.bytes.len(),
+            raw as f64 / self.bytes.len() as f64,
+        );
+        text
+    }
+}
+
+// Decodes frames in order, going back to a key frame to seek backward.
+struct Decoder {
+    recording: Rc<Recording>,
+    index: Option<usize>,
+    frame: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(recording: Rc<Recording>) -> Self {
+        let frame = vec![0; recording.header.frame_bytes()];
+        Self {
+            recording,
+            index: None,
+            frame,
+        }
+    }
+
+    fn seek(&mut self, target: usize) -> Result<&[u8]> {
+        let recording = self.recording.clone();
+        let entries = &recording.entries;
+        let next = match self.index {
+            Some(index) if index <= target => index + 1,
+            _ => (0..=target)
+                .rev()
+                .find(|&i| entries[i].kind == Kind::Key)
+                .unwrap(),
+        };
+        for index in next..=target {
+            self.index = None;
+            recording.apply(index, &mut self.frame)?;
+            self.index = Some(index);
+        }
+        Ok(&self.frame)
+    }
+}
+
+// Plays a recording back with its original timing, looping.
+pub struct Playback {
+    decoder: Decoder,
+}
+
+impl Playback {
+    pub fn new(recording: Rc<Recording>) -> Self {
+        let mut decoder = Decoder::new(recording);
+        if let Err(e) = decoder.seek(0) {
+            eprintln!("playback: {:#}", e);
+        }
+        Self { decoder }
+    }
+}
+
+impl PatternSource for Playback {
+    fn name(&self) -> &str {
+        PATTERN_NAME
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        let recording = &self.decoder.recording;
+        let period = recording.loop_duration().as_nanos();
+        let time = match period {
+            0 => 0,
+            _ => info.time.as_nanos() % period,
+        };
+        let index = recording.index_at(Duration::from_nanos(time as u64));
+        if let Err(e) = self.decoder.seek(index) {
+            eprintln!("playback: frame {}: {:#}", index, e);
+        }
+        &self.decoder.frame
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.decoder.frame
+    }
+}
+
+// Load a recording and register it as the "playback" pattern.
+pub fn register_file(
+    registry: &mut PatternRegistry,
+    path: &Path,
+) -> Result<()> {
+    let recording = Recording::load(path)?;
+    if recording.header != Header::for_panel(panel()) {
+        bail!(
+            "{} was recorded with {}x{} panels; set panel to match",
+            path.display(),
+            recording.header.width,
+            recording.header.height,
+        );
+    }
+    let recording = Rc::new(recording);
+    registry.register(PATTERN_NAME, move || {
+        Box::new(Playback::new(recording.clone()))
+    });
+    Ok(())
+}
+
+// `recording stats FILE` or `recording extract FILE FRAME PNG`
+pub fn run_tool(args: &[String]) -> Result<()> {
+    const USAGE: &str =
+        "usage: recording stats FILE | recording extract FILE FRAME PNG";
+    match args {
+        [command, path] if command == "stats" => {
+            let recording = Recording::load(Path::new(path))?;
+            println!("{}", recording.stats());
+        }
+        [command, path, frame, png] if command == "extract" => {
+            let recording = Rc::new(Recording::load(Path::new(path))?);
+            let index: usize = frame.parse().context(USAGE)?;
+            if index >= recording.len() {
+                bail!("{} has {} frames", path, recording.len());
+            }
+            let time = recording.time(index);
+            let header = recording.header;
+            if header.channels != 4 {
+                bail!("can't save {} channel frames", header.channels);
+            }
+            let mut decoder = Decoder::new(recording);
+            let data = decoder.seek(index)?.to_vec();
+            let width = (header.faces * header.width) as u32;
+            let image =
+                image::RgbaImage::from_raw(width, header.height as u32, data)
+                    .unwrap();
+            image
+                .save(png)
+                .with_context(|| format!("can't write {}", png))?;
+            println!("frame {} at {:.3} s", index, time.as_secs_f64());
+        }
+        _ => bail!(USAGE),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests run in parallel, so each names its own file.
+    fn record(name: &str, frames: &[(u64, Vec<u8>)]) -> Recording {
+        let path = std::env::temp_dir().join(format!(
+            "cube-recording-{}-{}.rec",
+            name,
+            std::process::id()
+        ));
+        let mut recorder = Recorder::create(&path).unwrap();
+        for (ms, frame) in frames {
+            recorder.write(Duration::from_millis(*ms), frame).unwrap();
+        }
+        drop(recorder);
+        let recording = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        recording
+    }
+
+    fn frame(fill: u8) -> Vec<u8> {
+        vec![fill; panel().bytes()]
+    }
+
+    #[test]
+    fn round_trips_frames() {
+        let mut changed = frame(1);
+        changed[100..110].fill(7);
+        changed[5000] = 9;
+        let frames = vec![
+            (0, frame(1)),
+            (20, frame(1)),
+            (40, changed.clone()),
+            (60, frame(2)),
+        ];
+        let recording = Rc::new(record("round-trip", &frames));
+        assert_eq!(recording.header, Header::for_panel(panel()));
+        let kinds: Vec<Kind> =
+            recording.entries.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [Kind::Key, Kind::Repeat, Kind::Xor, Kind::Key]);
+        assert!(recording.entries[2].payload.len() < 30);
+
+        let mut decoder = Decoder::new(recording.clone());
+        for (i, (ms, frame)) in frames.iter().enumerate() {
+            assert_eq!(recording.time(i), Duration::from_millis(*ms));
+            assert!(decoder.seek(i).unwrap() == frame.as_slice());
+        }
+        // Seeking backward starts over from the key frame.
+        assert!(decoder.seek(2).unwrap() == changed.as_slice());
+    }
+
+    #[test]
+    fn playback_keeps_time() {
+        let frames: Vec<_> =
+            (0..4).map(|i| (100 * i, frame(i as u8))).collect();
+        let recording = Rc::new(record("playback", &frames));
+        assert_eq!(recording.loop_duration(), Duration::from_millis(400));
+        let mut playback = Playback::new(recording);
+        let mut at = |ms: u64| {
+            let info = FrameInfo {
+                time: Duration::from_millis(ms),
+                frame_number: 0,
+                down: -crate::prelude::Vec3::unit_y(),
+            };
+            playback.next_frame(&info)[0]
+        };
+        assert_eq!(at(0), 0);
+        assert_eq!(at(150), 1);
+        assert_eq!(at(399), 3);
+        assert_eq!(at(420), 0);
+        assert_eq!(at(250), 2);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Recording::parse(b"GIF89a".to_vec()).is_err());
+        let mut bytes = Vec::new();
+        Header::for_panel(panel()).write(&mut bytes).unwrap();
+        assert!(Recording::parse(bytes).is_err());
+    }
+}
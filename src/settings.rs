@@ -0,0 +1,613 @@
+// Renderer settings, fixed at startup.
+//
+// They come from a TOML file, then from options on the command line,
+// which win.  The file is the one `--config` names, or CUBE_CONFIG, or
+// else `cube.toml` in the current directory if there is one.  Every
+// key is optional; these are the defaults:
+//
+//     backface_cull = true
+//     alpha_blending = false
+//     sample_count = 4             # 1 turns MSAA off
+//     print_fps = true
+//     hdr_postprocessing = true
+//     handedness = "right"         # of the world, or "left"
+//     background = [0.0025, 0.00625, 0.015]  # linear RGB
+//     window_size = "1920x1080"
+//     profile = false              # print the time each pass takes
+//     profile_out = "profile.csv"  # and write a summary here (.json
+//                                  # works too); no default
+//     frame_graph = false          # graph frame times in the window
+//     stats_out = "stats.json"     # write frame statistics here; no
+//                                  # default
+//     overlays = []                # lines to debug the geometry: any
+//                                  # of "wireframe", "normals",
+//                                  # "bounds" and "frusta"
+//     preset = "look.toml"         # tweaks to start with (see
+//                                  # tweaks.rs); no default
+//     panel = "64"                 # LEDs per face, "64" or "64x32"
+//     wiring = "wiring.toml"       # how the panels are wired (see
+//                                  # wiring.rs); no default
+//     pattern = "plasma"           # the pattern to start with
+//     animations = []              # GIFs, APNGs and PNG directories
+//                                  # to add as patterns
+//     pattern_shaders = "patterns" # a directory of pattern shaders;
+//                                  # ./patterns if it's there
+//     opc = "7890"                 # serve OPC on [host:]port, "" for
+//                                  # the default; no default
+//     opc_channels = "1=0+1+2"     # the panels each OPC channel
+//                                  # drives (see opc.rs); no default
+//     dmx = "artnet"               # or "sacn", then ":host:port" to
+//                                  # listen on; no default
+//     dmx_universe = 0             # the first; the protocol's own if
+//                                  # left out
+//     dmx_order = "rgb"
+//     imu = "turns.csv"            # play IMU orientations from a
+//                                  # file, or "udp:[host:]port"
+//     imu_smoothing = 0.1          # seconds, for a UDP feed
+//     record = "leds.rec"          # record the LEDs (see
+//                                  # recording.rs); no default
+//     playback = "leds.rec"        # play a recording; no default
+//     shading = "disney,shadow16"  # see shading.rs
+//     shader_dir = "src"           # read the shaders from here and
+//                                  # reload them; no default
+//     poster_scale = 4             # F11's capture, times the window
+//
+//     [effects.fire]               # any effect's speed, palette and
+//     speed = 0.5                  # density (see effects.rs); what's
+//                                  # left out keeps the effect's own
+//
+// Each key is also an option, with dashes for underscores:
+// `--sample-count 1`, `--window-size 800x600`, `--background 0,0,0`.
+// The true/false ones are flags: `--print-fps` or `--no-print-fps`.
+// Lists are separated by commas: `--overlays wireframe,frusta`.  So
+// are effect parameters, each effect.key=value:
+// `--effects fire.speed=0.5,life.palette=#ff8000`.  They're added to
+// the file's.
+//
+// The keys from `panel` on can also come from the environment, as
+// CUBE_ and the key in capitals (CUBE_PATTERN=fire), for when neither
+// the file nor an option sets them.  CUBE_ANIMATIONS is a path list,
+// separated like PATH.
+// Whether the adapter can do the sample count is checked when the
+// device is made.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+
+use crate::effects::{self, ParamSettings};
+use crate::export::parse_size;
+use crate::overlays::Overlay;
+use crate::panel::Panel;
+use crate::shading::Shading;
+use crate::{dmx, opc, Hand};
+
+const DEFAULT_FILE: &str = "cube.toml";
+
+// What an MSAA sample count can be, adapter permitting.
+const SAMPLE_COUNTS: [u32; 5] = [1, 2, 4, 8, 16];
+
+// The options that fall back to environment variables.
+const FROM_ENV: [&str; 17] = [
+    "panel",
+    "wiring",
+    "pattern",
+    "animations",
+    "pattern-shaders",
+    "opc",
+    "opc-channels",
+    "dmx",
+    "dmx-universe",
+    "dmx-order",
+    "imu",
+    "imu-smoothing",
+    "record",
+    "playback",
+    "shading",
+    "shader-dir",
+    "poster-scale",
+];
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub backface_cull: bool,
+    pub alpha_blending: bool,
+    pub sample_count: u32,
+    pub print_fps: bool,
+    pub hdr_postprocessing: bool,
+    pub handedness: Hand,
+    pub background: [f64; 3],
+    #[serde(deserialize_with = "deserialize_size")]
+    pub window_size: (u32, u32),
+    pub profile: bool,
+    pub profile_out: Option<PathBuf>,
+    pub frame_graph: bool,
+    pub stats_out: Option<PathBuf>,
+    pub overlays: Vec<Overlay>,
+    pub preset: Option<PathBuf>,
+    pub effects: BTreeMap<String, ParamSettings>,
+    #[serde(deserialize_with = "deserialize_panel")]
+    pub panel: Panel,
+    pub wiring: Option<PathBuf>,
+    pub pattern: Option<String>,
+    pub animations: Vec<PathBuf>,
+    pub pattern_shaders: Option<PathBuf>,
+    pub opc: Option<String>,
+    pub opc_channels: Option<String>,
+    pub dmx: Option<String>,
+    pub dmx_universe: Option<u16>,
+    pub dmx_order: Option<String>,
+    pub imu: Option<String>,
+    pub imu_smoothing: Option<f32>,
+    pub record: Option<PathBuf>,
+    pub playback: Option<PathBuf>,
+    #[serde(deserialize_with = "deserialize_shading")]
+    pub shading: Shading,
+    pub shader_dir: Option<PathBuf>,
+    pub poster_scale: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backface_cull: true,
+            alpha_blending: false,
+            sample_count: 4,
+            print_fps: true,
+            hdr_postprocessing: true,
+            handedness: Hand::Right,
+            background: [0.00250, 0.00625, 0.01500],
+            window_size: (1920, 1080),
+            profile: false,
+            profile_out: None,
+            frame_graph: false,
+            stats_out: None,
+            overlays: Vec::new(),
+            preset: None,
+            effects: BTreeMap::new(),
+            panel: Panel::DEFAULT,
+            wiring: None,
+            pattern: None,
+            animations: Vec::new(),
+            pattern_shaders: None,
+            opc: None,
+            opc_channels: None,
+            dmx: None,
+            dmx_universe: None,
+            dmx_order: None,
+            imu: None,
+            imu_smoothing: None,
+            record: None,
+            playback: None,
+            shading: Shading::default(),
+            shader_dir: None,
+            poster_scale: 4,
+        }
+    }
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<(u32, u32), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let spec = String::deserialize(deserializer)?;
+    parse_size(&spec).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_panel<'de, D>(deserializer: D) -> Result<Panel, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let spec = String::deserialize(deserializer)?;
+    Panel::parse(&spec).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_shading<'de, D>(deserializer: D) -> Result<Shading, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let spec = String::deserialize(deserializer)?;
+    Shading::parse(&spec).map_err(serde::de::Error::custom)
+}
+
+impl Settings {
+    pub fn background_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.background;
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+
+    // Settings from a file's text, with `options` from the command
+    // line on top and `fallbacks` from the environment beneath.
+    fn parse(
+        text: &str,
+        options: toml::Table,
+        fallbacks: toml::Table,
+    ) -> Result<Self> {
+        let mut table = fallbacks;
+        merge(&mut table, toml::from_str(text)?);
+        merge(&mut table, options);
+        let settings: Self = toml::Value::Table(table).try_into()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !SAMPLE_COUNTS.contains(&self.sample_count) {
+            bail!(
+                "sample_count is {}, not one of {:?}",
+                self.sample_count,
+                SAMPLE_COUNTS
+            );
+        }
+        if self.background.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            bail!("background {:?} is outside 0 to 1", self.background);
+        }
+        check_extension("profile_out", &self.profile_out, &["csv", "json"])?;
+        check_extension("stats_out", &self.stats_out, &["json"])?;
+        check_extension("preset", &self.preset, &["toml"])?;
+        check_extension("wiring", &self.wiring, &["toml"])?;
+        if let Some(spec) = &self.opc_channels {
+            opc::ChannelMap::parse(spec).context("opc_channels")?;
+        }
+        if let Some(spec) = &self.dmx {
+            dmx::Configuration::parse(spec).context("dmx")?;
+        }
+        if let Some(order) = &self.dmx_order {
+            dmx::PixelOrder::parse(order).context("dmx_order")?;
+        }
+        if let Some(seconds) = self.imu_smoothing {
+            if Duration::try_from_secs_f32(seconds).is_err() {
+                bail!("imu_smoothing {} isn't a time in seconds", seconds);
+            }
+        }
+        if self.poster_scale == 0 {
+            bail!("poster_scale is 0");
+        }
+        for (name, params) in &self.effects {
+            if !effects::NAMES.contains(&name.as_str()) {
+                bail!(
+                    "effects.{} isn't an effect; choose one of: {}",
+                    name,
+                    effects::NAMES.join(", ")
+                );
+            }
+            params
+                .validate()
+                .with_context(|| format!("effects.{}", name))?;
+        }
+        Ok(())
+    }
+}
+
+// Put `options` into `table`.  Tables in both are merged, so an option
+// can change one key of one.
+fn merge(table: &mut toml::Table, options: toml::Table) {
+    for (key, value) in options {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(old)), toml::Value::Table(new)) => {
+                merge(old, new)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+// Check that the file named by the setting `key`, if any, has one of
+// `extensions`.
+fn check_extension(
+    key: &str,
+    path: &Option<PathBuf>,
+    extensions: &[&str],
+) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) if extensions.contains(&extension) => Ok(()),
+        _ => bail!(
+            "{} {} isn't .{}",
+            key,
+            path.display(),
+            extensions.join(" or .")
+        ),
+    }
+}
+
+// Split the options at the start of `args` from the command and its
+// arguments.  The options come back as TOML values, and the settings
+// file, if one was named.
+fn parse_options(
+    args: &[String],
+) -> Result<(toml::Table, Option<PathBuf>, &[String])> {
+    const FLAGS: [&str; 6] = [
+        "backface-cull",
+        "alpha-blending",
+        "print-fps",
+        "hdr-postprocessing",
+        "profile",
+        "frame-graph",
+    ];
+    const VALUES: [&str; 10] = [
+        "config",
+        "sample-count",
+        "background",
+        "handedness",
+        "window-size",
+        "profile-out",
+        "stats-out",
+        "overlays",
+        "preset",
+        "effects",
+    ];
+    let mut options = toml::Table::new();
+    let mut config = None;
+    let mut rest = args;
+    while let Some((arg, tail)) = rest.split_first() {
+        let Some(name) = arg.strip_prefix("--") else {
+            break;
+        };
+        rest = tail;
+        if FLAGS.contains(&name) {
+            options.insert(name.replace('-', "_"), true.into());
+            continue;
+        }
+        if let Some(flag) = name.strip_prefix("no-") {
+            if FLAGS.contains(&flag) {
+                options.insert(flag.replace('-', "_"), false.into());
+                continue;
+            }
+        }
+        if !VALUES.contains(&name) && !FROM_ENV.contains(&name) {
+            bail!("unknown option --{}", name);
+        }
+        let (value, tail) = rest
+            .split_first()
+            .ok_or_else(|| anyhow!("--{} needs a value", name))?;
+        rest = tail;
+        if name == "config" {
+            config = Some(PathBuf::from(value));
+            continue;
+        }
+        options.insert(name.replace('-', "_"), option_value(name, value)?);
+    }
+    Ok((options, config, rest))
+}
+
+// The option `name`'s `value` as TOML.
+fn option_value(name: &str, value: &str) -> Result<toml::Value> {
+    let bad = || format!("bad --{} \"{}\"", name, value);
+    Ok(match name {
+        "sample-count" | "dmx-universe" | "poster-scale" => {
+            value.parse::<i64>().with_context(bad)?.into()
+        }
+        "imu-smoothing" => value.parse::<f64>().with_context(bad)?.into(),
+        "background" => {
+            let color: Result<Vec<f64>, _> =
+                value.split(',').map(|c| c.trim().parse()).collect();
+            match color {
+                Ok(color) if color.len() == 3 => color.into(),
+                _ => bail!("{}; use R,G,B", bad()),
+            }
+        }
+        "overlays" | "animations" => value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<_>>()
+            .into(),
+        "effects" => effect_options(value)?.into(),
+        _ => value.into(),
+    })
+}
+
+// The FROM_ENV options that `var` has, as TOML.  `var` looks up an
+// environment variable.
+fn env_options(var: impl Fn(&str) -> Option<String>) -> Result<toml::Table> {
+    let mut options = toml::Table::new();
+    for name in FROM_ENV {
+        let key = name.replace('-', "_");
+        let var_name = format!("CUBE_{}", key.to_uppercase());
+        let Some(value) = var(&var_name) else {
+            continue;
+        };
+        let value = match name {
+            "animations" => std::env::split_paths(&value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .into(),
+            _ => option_value(name, &value).context(var_name)?,
+        };
+        options.insert(key, value);
+    }
+    Ok(options)
+}
+
+// "effect.key=value,..." as an `effects` table.  Values that are
+// numbers become numbers.
+fn effect_options(spec: &str) -> Result<toml::Table> {
+    let mut effects = toml::Table::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((effect, key, value)) =
+            entry.split_once('=').and_then(|(name, value)| {
+                let (effect, key) = name.trim().split_once('.')?;
+                Some((effect, key, value.trim()))
+            })
+        else {
+            bail!("bad --effects \"{}\"; use effect.key=value", entry);
+        };
+        let value: toml::Value = match value.parse::<f64>() {
+            Ok(number) => number.into(),
+            Err(_) => value.into(),
+        };
+        effects
+            .entry(effect)
+            .or_insert_with(|| toml::Table::new().into())
+            .as_table_mut()
+            .unwrap()
+            .insert(key.to_string(), value);
+    }
+    Ok(effects)
+}
+
+// Load the settings for `args`, the program's arguments, and return the
+// command and its arguments.
+pub fn init(args: &[String]) -> Result<&[String]> {
+    let (options, config, rest) = parse_options(args)?;
+    let fallbacks = env_options(|name| std::env::var(name).ok())?;
+    let path =
+        config.or_else(|| std::env::var_os("CUBE_CONFIG").map(PathBuf::from));
+    let settings = match &path {
+        Some(path) => load(path, options, fallbacks)?,
+        None if Path::new(DEFAULT_FILE).is_file() => {
+            load(Path::new(DEFAULT_FILE), options, fallbacks)?
+        }
+        None => Settings::parse("", options, fallbacks)?,
+    };
+    SETTINGS
+        .set(settings)
+        .map_err(|_| anyhow!("the settings are already in use"))?;
+    Ok(rest)
+}
+
+fn load(
+    path: &Path,
+    options: toml::Table,
+    fallbacks: toml::Table,
+) -> Result<Settings> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("can't read {}", path.display()))?;
+    Settings::parse(&text, options, fallbacks)
+        .with_context(|| format!("{}", path.display()))
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+pub fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Palette;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_override_the_file() {
+        let text = "sample_count = 1\nwindow_size = \"800x600\"\n";
+        let args = args(&[
+            "--sample-count",
+            "8",
+            "--no-print-fps",
+            "--handedness",
+            "left",
+            "--profile-out",
+            "p.json",
+            "--overlays",
+            "wireframe, frusta",
+            "--effects",
+            "fire.speed=2,fire.palette=#ff8000",
+            "render",
+            "--out",
+        ]);
+        let (options, config, rest) = parse_options(&args).unwrap();
+        assert_eq!(config, None);
+        assert_eq!(rest, ["render", "--out"]);
+        let text = format!("{}[effects.fire]\ndensity = 0.25\n", text);
+        let settings =
+            Settings::parse(&text, options, toml::Table::new()).unwrap();
+        assert_eq!(settings.sample_count, 8);
+        assert_eq!(settings.window_size, (800, 600));
+        assert!(!settings.print_fps);
+        assert_eq!(settings.handedness, Hand::Left);
+        assert!(settings.backface_cull);
+        assert_eq!(settings.profile_out, Some(PathBuf::from("p.json")));
+        assert_eq!(settings.overlays, [Overlay::Wireframe, Overlay::Frusta]);
+        let fire = settings.effects["fire"];
+        assert_eq!(fire.speed, Some(2.0));
+        assert_eq!(fire.palette, Some(Palette::Mono([255, 128, 0])));
+        assert_eq!(fire.density, Some(0.25));
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        let parse = |text| {
+            Settings::parse(text, toml::Table::new(), toml::Table::new())
+        };
+        assert_eq!(parse("").unwrap(), Settings::default());
+        assert!(parse("sample_count = 3").is_err());
+        assert!(parse("background = [0.0, 2.0, 0.0]").is_err());
+        assert!(parse("window_size = \"wide\"").is_err());
+        assert!(parse("fps = true").is_err());
+        assert!(parse("profile_out = \"profile.txt\"").is_err());
+        assert!(parse("stats_out = \"stats.csv\"").is_err());
+        assert!(parse("overlays = [\"grid\"]").is_err());
+        assert!(parse("preset = \"look.json\"").is_err());
+        assert!(parse("[effects.glow]\nspeed = 1.0").is_err());
+        assert!(parse("[effects.fire]\ndensity = 2.0").is_err());
+        assert!(parse("[effects.fire]\npalette = \"neon\"").is_err());
+        assert!(parse_options(&args(&["--effects", "fire=2"])).is_err());
+        let error = parse_options(&args(&["--samples", "4"])).unwrap_err();
+        assert_eq!(error.to_string(), "unknown option --samples");
+        assert!(parse_options(&args(&["--background", "1,2"])).is_err());
+        assert!(parse_options(&args(&["--sample-count"])).is_err());
+        assert!(parse("panel = \"64x\"").is_err());
+        assert!(parse("wiring = \"wiring.json\"").is_err());
+        assert!(parse("opc_channels = \"1=9\"").is_err());
+        assert!(parse("dmx = \"midi\"").is_err());
+        assert!(parse("dmx_universe = 70000").is_err());
+        assert!(parse("dmx_order = \"rgg\"").is_err());
+        assert!(parse("imu_smoothing = -1.0").is_err());
+        assert!(parse("shading = \"phong\"").is_err());
+        assert!(parse("poster_scale = 0").is_err());
+        assert!(parse_options(&args(&["--poster-scale", "big"])).is_err());
+    }
+
+    #[test]
+    fn the_environment_is_a_fallback() {
+        let var = |name: &str| match name {
+            "CUBE_PATTERN" => Some("fire".to_string()),
+            "CUBE_PANEL" => Some("32".to_string()),
+            "CUBE_DMX" => Some("sacn".to_string()),
+            "CUBE_DMX_UNIVERSE" => Some("3".to_string()),
+            "CUBE_ANIMATIONS" => Some("a.gif:pngs".to_string()),
+            "CUBE_IMU_SMOOTHING" => Some("0.5".to_string()),
+            _ => None,
+        };
+        let fallbacks = env_options(var).unwrap();
+        let (options, _, _) =
+            parse_options(&args(&["--panel", "64x32", "--shading", "classic"]))
+                .unwrap();
+        let text = "pattern = \"plasma\"\ndmx_order = \"grb\"\n";
+        let settings = Settings::parse(text, options, fallbacks).unwrap();
+        assert_eq!(settings.pattern.as_deref(), Some("plasma"));
+        assert_eq!(settings.panel, Panel::new(64, 32));
+        assert_eq!(settings.dmx.as_deref(), Some("sacn"));
+        assert_eq!(settings.dmx_universe, Some(3));
+        assert_eq!(settings.dmx_order.as_deref(), Some("grb"));
+        assert_eq!(
+            settings.animations,
+            [Path::new("a.gif"), Path::new("pngs")]
+        );
+        assert_eq!(settings.imu_smoothing, Some(0.5));
+        assert_eq!(settings.shading, Shading::parse("classic").unwrap());
+        assert_eq!(settings.poster_scale, 4);
+
+        let var = |name: &str| {
+            (name == "CUBE_POSTER_SCALE").then(|| "big".to_string())
+        };
+        let error = env_options(var).unwrap_err();
+        assert_eq!(error.to_string(), "CUBE_POSTER_SCALE");
+    }
+}
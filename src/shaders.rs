@@ -0,0 +1,121 @@
+// The scene and post-processing shaders, and reloading them.
+//
+// Normally the shaders are built in.  In development, the shader_dir
+// setting names a directory, usually `src`, to read common_shader.wgsl and
+// post_shaders.wgsl from instead.  The files are watched, and when
+// either changes the shader modules and every pipeline made from them
+// are rebuilt.  If the new shaders don't validate, the old pipelines
+// stay, the diagnostics are printed, and a red bar shows across the top
+// of the window until the shaders are fixed.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+
+// How often to look for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shader {
+    Common,
+    Post,
+}
+
+impl Shader {
+    const ALL: [Shader; 2] = [Shader::Common, Shader::Post];
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Shader::Common => "common_shader.wgsl",
+            Shader::Post => "post_shaders.wgsl",
+        }
+    }
+
+    fn builtin(self) -> &'static str {
+        match self {
+            Shader::Common => include_str!("common_shader.wgsl"),
+            Shader::Post => include_str!("post_shaders.wgsl"),
+        }
+    }
+}
+
+pub struct Shaders {
+    dir: Option<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+    last_poll: Instant,
+}
+
+impl Shaders {
+    // The shaders in `dir`, or the built-in ones without one.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            modified: vec![None; Shader::ALL.len()],
+            dir,
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Just the built-in shaders.
+    pub fn builtin() -> Self {
+        Self::new(None)
+    }
+
+    // True when a watched file has changed since the last call.  The
+    // first call notices every file.
+    pub fn changed(&mut self) -> bool {
+        let Some(dir) = &self.dir else {
+            return false;
+        };
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let mut changed = false;
+        for (shader, modified) in Shader::ALL.iter().zip(&mut self.modified) {
+            let path = dir.join(shader.file_name());
+            let time = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if time.is_some() && time != *modified {
+                *modified = time;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn source(&self, shader: Shader) -> Result<String> {
+        match &self.dir {
+            Some(dir) => {
+                let path = dir.join(shader.file_name());
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("can't read {}", path.display()))
+            }
+            None => Ok(shader.builtin().to_string()),
+        }
+    }
+
+    // Compile a shader module.  Errors carry naga's diagnostics.
+    pub fn compile(
+        &self,
+        device: &wgpu::Device,
+        shader: Shader,
+    ) -> Result<wgpu::ShaderModule> {
+        let source = self.source(shader)?;
+        let label = match &self.dir {
+            Some(dir) => dir.join(shader.file_name()),
+            None => Path::new(shader.file_name()).to_path_buf(),
+        };
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&label.display().to_string()),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            bail!("{}: {}", label.display(), error);
+        }
+        Ok(module)
+    }
+}
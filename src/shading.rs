@@ -0,0 +1,255 @@
+// Shading options.
+//
+// Each option is a WGSL override constant, so changing one means
+// pipelines made with different constants, not a branch per pixel.
+// The scene options go to the common shader and the tone map to the
+// post shader.  `State` and `Post` keep the pipelines they've made for
+// each, so switching back and forth is cheap.
+//
+// The shading setting picks the options at startup: a comma-separated list of
+//
+//     disney, classic                   BRDF or Lambert/Blinn-Phong
+//     precomputed-glow, live-glow       glow on the floor from the
+//                                       prefloor pass, or per pixel
+//     shadow1, shadow4, shadow16        shadow map samples
+//     exposure, reinhard, reinhard-extended, reinhard-luminance
+//                                       tone map
+//
+// Options not listed keep their defaults, which are the first of each
+// except shadow16 and reinhard-luminance.
+//
+// To compare two sets of scene options side by side, the left half of
+// the view is drawn with one and the right half with the other.  The
+// tone map covers the whole view.
+
+use std::fmt;
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+
+use crate::camera::Tile;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Model {
+    #[default]
+    Disney,
+    Classic,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Glow {
+    #[default]
+    Precomputed,
+    Live,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Shadows {
+    One,
+    Four,
+    #[default]
+    Sixteen,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ToneMap {
+    Exposure,
+    Reinhard,
+    ReinhardExtended,
+    #[default]
+    ReinhardLuminance,
+}
+
+// The options that go into the common shader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Scene {
+    pub model: Model,
+    pub glow: Glow,
+    pub shadows: Shadows,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Shading {
+    pub scene: Scene,
+    pub tone_map: ToneMap,
+}
+
+impl Model {
+    pub fn next(self) -> Self {
+        match self {
+            Model::Disney => Model::Classic,
+            Model::Classic => Model::Disney,
+        }
+    }
+}
+
+impl Glow {
+    pub fn next(self) -> Self {
+        match self {
+            Glow::Precomputed => Glow::Live,
+            Glow::Live => Glow::Precomputed,
+        }
+    }
+}
+
+impl Shadows {
+    pub fn next(self) -> Self {
+        match self {
+            Shadows::One => Shadows::Four,
+            Shadows::Four => Shadows::Sixteen,
+            Shadows::Sixteen => Shadows::One,
+        }
+    }
+
+    fn samples(self) -> u32 {
+        match self {
+            Shadows::One => 1,
+            Shadows::Four => 4,
+            Shadows::Sixteen => 16,
+        }
+    }
+}
+
+impl ToneMap {
+    pub fn next(self) -> Self {
+        match self {
+            ToneMap::Exposure => ToneMap::Reinhard,
+            ToneMap::Reinhard => ToneMap::ReinhardExtended,
+            ToneMap::ReinhardExtended => ToneMap::ReinhardLuminance,
+            ToneMap::ReinhardLuminance => ToneMap::Exposure,
+        }
+    }
+
+    // Values for the post shader's override constants.  TONE_MAP
+    // numbers the tone maps in this order.
+    pub fn constants(&self) -> [(&'static str, f64); 1] {
+        [("TONE_MAP", *self as u32 as f64)]
+    }
+}
+
+impl Scene {
+    // Values for the common shader's override constants.
+    pub fn constants(&self) -> [(&'static str, f64); 3] {
+        [
+            ("USE_BRDF_FLAG", (self.model == Model::Disney) as u32 as f64),
+            (
+                "PRECOMPUTE_GLOW_FLAG",
+                (self.glow == Glow::Precomputed) as u32 as f64,
+            ),
+            ("SHADOW_SAMPLES", self.shadows.samples() as f64),
+        ]
+    }
+}
+
+impl Shading {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut shading = Self::default();
+        let scene = &mut shading.scene;
+        for word in spec.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+            match word {
+                "disney" => scene.model = Model::Disney,
+                "classic" => scene.model = Model::Classic,
+                "precomputed-glow" => scene.glow = Glow::Precomputed,
+                "live-glow" => scene.glow = Glow::Live,
+                "shadow1" => scene.shadows = Shadows::One,
+                "shadow4" => scene.shadows = Shadows::Four,
+                "shadow16" => scene.shadows = Shadows::Sixteen,
+                "exposure" => shading.tone_map = ToneMap::Exposure,
+                "reinhard" => shading.tone_map = ToneMap::Reinhard,
+                "reinhard-extended" => {
+                    shading.tone_map = ToneMap::ReinhardExtended
+                }
+                "reinhard-luminance" => {
+                    shading.tone_map = ToneMap::ReinhardLuminance
+                }
+                _ => bail!("unknown shading option \"{}\"", word),
+            }
+        }
+        Ok(shading)
+    }
+}
+
+// The columns of a framebuffer `width` wide that the left and right
+// halves of the view cover.  A tile of a capture covers part of the
+// view, so may see only one half.
+pub fn split_columns(width: u32, tile: Option<&Tile>) -> [Range<u32>; 2] {
+    let (count, column) = tile.map_or((1, 0), |tile| (tile.count, tile.column));
+    // Where the split is, from the tile's left edge.
+    let split = (count * width / 2)
+        .saturating_sub(column * width)
+        .min(width);
+    [0..split, split..width]
+}
+
+// The same words the shading setting takes.
+impl fmt::Display for Scene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let model = match self.model {
+            Model::Disney => "disney",
+            Model::Classic => "classic",
+        };
+        let glow = match self.glow {
+            Glow::Precomputed => "precomputed-glow",
+            Glow::Live => "live-glow",
+        };
+        write!(f, "{},{},shadow{}", model, glow, self.shadows.samples())
+    }
+}
+
+impl fmt::Display for Shading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tone_map = match self.tone_map {
+            ToneMap::Exposure => "exposure",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::ReinhardExtended => "reinhard-extended",
+            ToneMap::ReinhardLuminance => "reinhard-luminance",
+        };
+        write!(f, "{},{}", self.scene, tone_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_what_it_prints() {
+        let shading = Shading::parse("classic, shadow4,exposure").unwrap();
+        assert_eq!(shading.scene.model, Model::Classic);
+        assert_eq!(shading.scene.glow, Glow::Precomputed);
+        assert_eq!(shading.scene.shadows, Shadows::Four);
+        assert_eq!(shading.tone_map, ToneMap::Exposure);
+        let printed = shading.to_string();
+        assert_eq!(printed, "classic,precomputed-glow,shadow4,exposure");
+        assert_eq!(Shading::parse(&printed).unwrap(), shading);
+        assert_eq!(Shading::parse("").unwrap(), Shading::default());
+        assert!(Shading::parse("disney,phong").is_err());
+    }
+
+    #[test]
+    fn defaults_match_the_shaders() {
+        let shading = Shading::default();
+        assert_eq!(
+            shading.scene.constants(),
+            [
+                ("USE_BRDF_FLAG", 1.0),
+                ("PRECOMPUTE_GLOW_FLAG", 1.0),
+                ("SHADOW_SAMPLES", 16.0),
+            ]
+        );
+        assert_eq!(shading.tone_map.constants(), [("TONE_MAP", 3.0)]);
+    }
+
+    #[test]
+    fn tiles_split_the_whole_view() {
+        assert_eq!(split_columns(100, None), [0..50, 50..100]);
+        let tile = |column| Tile {
+            column,
+            row: 0,
+            count: 3,
+        };
+        assert_eq!(split_columns(100, Some(&tile(0))), [0..100, 100..100]);
+        assert_eq!(split_columns(100, Some(&tile(1))), [0..50, 50..100]);
+        assert_eq!(split_columns(100, Some(&tile(2))), [0..0, 0..100]);
+    }
+}
@@ -0,0 +1,359 @@
+// Frame statistics.
+//
+// Every frame's time, from the end of the one before, is counted along
+// with the parts of it spent updating, waiting for the surface's next
+// texture and encoding.  The counts are histograms with buckets 1%
+// apart, so a run of any length takes the same space and its
+// percentiles are within 1%.  With `print_fps` set, the frame rate and
+// percentiles of the last second's frame times are printed once a
+// second.  A frame is a hitch when it takes HITCH_FACTOR times the
+// median of the HISTORY frames before it.
+//
+// With `stats_out` set (see settings.rs), a JSON summary of the whole
+// run is written there at the end, to compare between commits.  The
+// same numbers come out of `wgpu-cube bench`.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+// Frames the running median is over.  Also the most the frame graph
+// shows.
+pub const HISTORY: usize = 256;
+
+const HITCH_FACTOR: f32 = 2.0;
+
+// No hitches until there's this much history to judge by.
+const MIN_HISTORY: usize = 30;
+
+// The parts of a frame the renderer times.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimes {
+    pub update: Duration,
+    pub acquire: Duration, // blocked in `get_current_texture`
+    pub encode: Duration,  // encoding and submitting the passes
+}
+
+// Histogram buckets run from MIN_MS up, each BUCKET_RATIO times the
+// one before.  Times past the last bucket count in it.
+const MIN_MS: f32 = 0.001;
+const BUCKET_RATIO: f32 = 1.01;
+const BUCKETS: usize = 1620; // up to about 10 seconds
+
+// Counts of times in milliseconds.  The mean, min and max are exact.
+struct Histogram {
+    counts: Vec<u32>,
+    count: usize,
+    sum: f64,
+    min: f32,
+    max: f32,
+}
+
+pub struct Stats {
+    print: bool,
+    // since `start`
+    total: Histogram,
+    update: Histogram,
+    acquire: Histogram,
+    encode: Histogram,
+    second: Vec<f32>, // frame times since `prev_time`
+    recent: VecDeque<f32>,
+    hitches: usize,
+    start_time: Instant,
+    prev_time: Instant,
+    prev_hitches: usize,
+    last_frame_time: Instant,
+}
+
+// What the frames ran on, for the summary.
+#[derive(Serialize)]
+pub struct Setup {
+    pub width: u32,
+    pub height: u32,
+    pub sample_count: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub mean: f32,
+    pub min: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub max: f32,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    setup: &'a Setup,
+    frames: usize,
+    seconds: f64,
+    frames_per_second: f64,
+    hitches: usize,
+    // Percentiles of each, in milliseconds.
+    frame_ms: Percentiles,
+    update_ms: Percentiles,
+    acquire_ms: Percentiles,
+    encode_ms: Percentiles,
+}
+
+impl Percentiles {
+    const ZERO: Self = Self {
+        mean: 0.0,
+        min: 0.0,
+        p50: 0.0,
+        p95: 0.0,
+        p99: 0.0,
+        max: 0.0,
+    };
+
+    // Nearest-rank percentiles of `values`; all zero when there are
+    // none.
+    pub fn of(values: impl Iterator<Item = f32>) -> Self {
+        let mut values: Vec<f32> = values.collect();
+        if values.is_empty() {
+            return Self::ZERO;
+        }
+        values.sort_by(f32::total_cmp);
+        let n = values.len();
+        let rank = |p: usize| values[(p * n).div_ceil(100).max(1) - 1];
+        Self {
+            mean: values.iter().sum::<f32>() / n as f32,
+            min: values[0],
+            p50: rank(50),
+            p95: rank(95),
+            p99: rank(99),
+            max: values[n - 1],
+        }
+    }
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+            sum: 0.0,
+            min: f32::INFINITY,
+            max: 0.0,
+        }
+    }
+
+    fn add(&mut self, ms: f32) {
+        let bucket = (ms / MIN_MS).ln() / BUCKET_RATIO.ln();
+        // A negative or NaN bucket is 0.
+        self.counts[(bucket as usize).min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.sum += ms as f64;
+        self.min = self.min.min(ms);
+        self.max = self.max.max(ms);
+    }
+
+    // Nearest-rank percentiles, each the middle of its bucket.
+    fn percentiles(&self) -> Percentiles {
+        if self.count == 0 {
+            return Percentiles::ZERO;
+        }
+        let rank = |p: usize| {
+            let rank = (p * self.count).div_ceil(100).max(1);
+            let mut seen = 0;
+            let bucket = self
+                .counts
+                .iter()
+                .position(|&count| {
+                    seen += count as usize;
+                    seen >= rank
+                })
+                .unwrap();
+            let ms = MIN_MS * BUCKET_RATIO.powf(bucket as f32 + 0.5);
+            ms.clamp(self.min, self.max)
+        };
+        Percentiles {
+            mean: (self.sum / self.count as f64) as f32,
+            min: self.min,
+            p50: rank(50),
+            p95: rank(95),
+            p99: rank(99),
+            max: self.max,
+        }
+    }
+}
+
+impl Stats {
+    // With `print`, print a line about the frames once a second.
+    pub fn new(print: bool) -> Self {
+        let now = Instant::now();
+        Stats {
+            print,
+            total: Histogram::new(),
+            update: Histogram::new(),
+            acquire: Histogram::new(),
+            encode: Histogram::new(),
+            second: Vec::new(),
+            recent: VecDeque::with_capacity(HISTORY),
+            hitches: 0,
+            start_time: now,
+            prev_time: now,
+            prev_hitches: 0,
+            last_frame_time: now,
+        }
+    }
+
+    // Forget the frames so far, and start timing the next from now.
+    pub fn start(&mut self) {
+        *self = Self::new(self.print);
+    }
+
+    pub fn count_frame(&mut self, times: FrameTimes) {
+        let now = Instant::now();
+        let ms = |time: Duration| time.as_secs_f32() * 1000.0;
+        let total = ms(now - self.last_frame_time);
+        self.last_frame_time = now;
+
+        if self.recent.len() >= MIN_HISTORY
+            && total > self.hitch_ms().unwrap_or(f32::INFINITY)
+        {
+            self.hitches += 1;
+        }
+        if self.recent.len() == HISTORY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(total);
+        self.second.push(total);
+        self.total.add(total);
+        self.update.add(ms(times.update));
+        self.acquire.add(ms(times.acquire));
+        self.encode.add(ms(times.encode));
+
+        let dur = now.duration_since(self.prev_time);
+        if dur.as_secs() >= 1 {
+            if self.print {
+                self.print_second(dur);
+            }
+            self.prev_time = now;
+            self.second.clear();
+            self.prev_hitches = self.hitches;
+        }
+    }
+
+    fn print_second(&self, dur: Duration) {
+        let ms = Percentiles::of(self.second.iter().copied());
+        let mut line = format!(
+            "{:.2} frames/second, ms p50 {:.2} p95 {:.2} p99 {:.2}",
+            self.second.len() as f64 / dur.as_secs_f64(),
+            ms.p50,
+            ms.p95,
+            ms.p99,
+        );
+        let hitches = self.hitches - self.prev_hitches;
+        if hitches > 0 {
+            line += &format!(", {} hitches, worst {:.2} ms", hitches, ms.max);
+        }
+        println!("{}", line);
+    }
+
+    // How long a frame has to take to be a hitch now.
+    pub fn hitch_ms(&self) -> Option<f32> {
+        let median = Percentiles::of(self.recent.iter().copied()).p50;
+        (!self.recent.is_empty()).then_some(HITCH_FACTOR * median)
+    }
+
+    // The last HISTORY frames' times, oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = f32> + '_ {
+        self.recent.iter().copied()
+    }
+
+    pub fn frame_ms(&self) -> Percentiles {
+        self.total.percentiles()
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        let seconds = (self.last_frame_time - self.start_time).as_secs_f64();
+        self.total.count as f64 / seconds
+    }
+
+    pub fn hitches(&self) -> usize {
+        self.hitches
+    }
+
+    pub fn write(&self, path: &Path, setup: &Setup) -> Result<()> {
+        let summary = Summary {
+            setup,
+            frames: self.total.count,
+            seconds: (self.last_frame_time - self.start_time).as_secs_f64(),
+            frames_per_second: self.frames_per_second(),
+            hitches: self.hitches,
+            frame_ms: self.frame_ms(),
+            update_ms: self.update.percentiles(),
+            acquire_ms: self.acquire.percentiles(),
+            encode_ms: self.encode.percentiles(),
+        };
+        let text = serde_json::to_string_pretty(&summary)? + "\n";
+        std::fs::write(path, text)
+            .with_context(|| format!("can't write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let ms = Percentiles::of((1..=200).rev().map(|ms| ms as f32));
+        assert_eq!(
+            ms,
+            Percentiles {
+                mean: 100.5,
+                min: 1.0,
+                p50: 100.0,
+                p95: 190.0,
+                p99: 198.0,
+                max: 200.0,
+            }
+        );
+        assert_eq!(Percentiles::of([7.0].into_iter()).p99, 7.0);
+        assert_eq!(Percentiles::of(std::iter::empty()).max, 0.0);
+    }
+
+    #[test]
+    fn histograms_are_within_a_percent() {
+        let mut histogram = Histogram::new();
+        for ms in (1..=200).rev() {
+            histogram.add(ms as f32);
+        }
+        let ms = histogram.percentiles();
+        let exact = [(ms.p50, 100.0), (ms.p95, 190.0), (ms.p99, 198.0)];
+        for (ms, exact) in exact {
+            assert!((ms / exact - 1.0).abs() < 0.01, "{} {}", ms, exact);
+        }
+        assert_eq!((ms.mean, ms.min, ms.max), (100.5, 1.0, 200.0));
+
+        // However many frames, and however slow.
+        let mut histogram = Histogram::new();
+        for _ in 0..100_000 {
+            histogram.add(7.0);
+        }
+        histogram.add(60_000.0);
+        histogram.add(0.0);
+        assert_eq!(histogram.counts.len(), BUCKETS);
+        assert!((histogram.percentiles().p99 / 7.0 - 1.0).abs() < 0.01);
+        assert_eq!(Histogram::new().percentiles(), Percentiles::ZERO);
+    }
+
+    #[test]
+    fn counts_hitches() {
+        let mut stats = Stats::new(false);
+        stats.recent.extend([10.0; MIN_HISTORY]);
+        assert_eq!(stats.hitch_ms(), Some(20.0));
+        // Frames are timed from the last, so fake a slow one.
+        stats.last_frame_time -= Duration::from_millis(25);
+        stats.count_frame(FrameTimes::default());
+        assert_eq!(stats.hitches(), 1);
+        stats.count_frame(FrameTimes::default());
+        assert_eq!(stats.hitches(), 1);
+    }
+}
@@ -1,103 +1,158 @@
-const TEST_PATTERN_TYPE: i32 = 1;
+use crate::panel::panel;
+use crate::pattern::{FrameInfo, PatternRegistry, PatternSource};
 
-const FACE_COUNT: usize = 6;
-const SIDE: usize = 64;
-const CHANNEL_COUNT: usize = 4;
-const BYTES: usize = FACE_COUNT * SIDE * SIDE * CHANNEL_COUNT;
-pub type PixelArray = [u8; BYTES];
+pub const FACE_COUNT: usize = 6;
+pub const CHANNEL_COUNT: usize = 4;
+// One frame of LED data, `panel().bytes()` long.
+pub type PixelArray = [u8];
 
-pub struct TestPattern {
-    frame_number: usize,
-    data: PixelArray,
+pub fn register(registry: &mut PatternRegistry) {
+    registry.register("blank", || Box::new(Blank::new()));
+    registry.register("stripes", || Box::new(Stripes::new()));
+    registry.register("pinwheel", || Box::new(Pinwheel::new()));
+    registry.register("squares", || Box::new(Squares::new()));
+    registry.register("faces", || Box::new(Faces::new()));
 }
 
-impl TestPattern {
+pub fn index_4d(face: usize, row: usize, col: usize, chan: usize) -> usize {
+    panel().index(face, row, col) + chan
+}
+
+// All black, all opaque.
+pub fn black_frame() -> Vec<u8> {
+    let mut data = vec![0; panel().bytes()];
+    for i in (0..data.len()).step_by(CHANNEL_COUNT) {
+        data[i + CHANNEL_COUNT - 1] = 255;
+    }
+    data
+}
+
+// ----  blank  --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+pub struct Blank {
+    data: Vec<u8>,
+}
+
+impl Blank {
     pub fn new() -> Self {
-        let mut new = Self {
-            frame_number: 0,
-            // data: [255u8; BYTES],
-            data: [0; BYTES],
-        };
-        for i in (0..BYTES).step_by(CHANNEL_COUNT) {
-            new.data[i + CHANNEL_COUNT - 1] = 255;
+        Self {
+            data: black_frame(),
         }
-        new
     }
+}
 
-    pub fn next_frame(&mut self) -> &PixelArray {
-        match TEST_PATTERN_TYPE {
-            0 => self.next_frame_0(),
-            1 => self.next_frame_1(),
-            2 => self.next_frame_2(),
-            3 => self.next_frame_3(),
-            4 => self.next_frame_4(),
-            _ => panic!(),
-        }
+impl PatternSource for Blank {
+    fn name(&self) -> &str {
+        "blank"
     }
 
-    fn next_frame_0(&mut self) -> &PixelArray {
-        self.frame_number += 1;
+    fn next_frame(&mut self, _: &FrameInfo) -> &PixelArray {
         &self.data
     }
 
-    fn next_frame_1(&mut self) -> &PixelArray {
-        // self.write_row_column(self.frame_number, 0u8);
-        self.write_row_column(self.frame_number + SIDE - 7, 0u8);
-        self.write_row_column(self.frame_number + SIDE - 6, 255u8);
-        self.write_row_column(self.frame_number + SIDE - 5, 63u8);
-        self.write_row_column(self.frame_number + SIDE - 4, 15u8);
-        self.write_row_column(self.frame_number + SIDE - 3, 3u8);
-        self.write_row_column(self.frame_number + SIDE - 2, 3u8);
-        self.write_row_column(self.frame_number + SIDE - 1, 15u8);
-        self.write_row_column(self.frame_number + SIDE - 0, 63u8);
-
-        if self.frame_number == usize::MAX / 2 {
-            self.frame_number = 0;
-        } else {
-            self.frame_number += 1;
-        }
-        self.write_row_column(self.frame_number, 255u8);
+    fn current_frame(&self) -> &PixelArray {
         &self.data
     }
+}
 
-    pub fn current_frame(&self) -> &PixelArray {
-        &self.data
+// ----  stripes   ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+pub struct Stripes {
+    data: Vec<u8>,
+}
+
+impl Stripes {
+    pub fn new() -> Self {
+        Self {
+            data: black_frame(),
+        }
+    }
+
+    // A whole number of sweeps across both axes.
+    fn cycle() -> usize {
+        let panel = panel();
+        (panel.width / 2).max(1) * (panel.height / 2).max(1) * 2
     }
 
     fn write_row_column(&mut self, frame_number: usize, value: u8) {
         const HORIZ_CHANNEL: [usize; FACE_COUNT] = [2, 0, 2, 2, 1, 2];
         const VERT_CHANNEL: [usize; FACE_COUNT] = [1, 1, 1, 0, 0, 0];
-        const HALF_SIDE: usize = SIDE / 2;
 
-        let pos = frame_number % HALF_SIDE;
-        let dir = frame_number / HALF_SIDE % 2 != 0;
-        let (rc0, rc1) = match dir {
-            false => (pos, SIDE - pos - 1),
-            true => (HALF_SIDE - pos - 1, HALF_SIDE + pos),
+        // The two stripes, moving apart then together along `side`.
+        let stripes = |side: usize| {
+            let half_side = (side / 2).max(1);
+            let pos = frame_number % half_side;
+            let dir = frame_number / half_side % 2 != 0;
+            match dir {
+                false => (pos, side - pos - 1),
+                true => (half_side - pos - 1, side - half_side + pos),
+            }
         };
-        // BPP:  4 bytes per pixel
-        // BPFR: 64 pixels per face row
-        // BPCR: 6 face rows per row
-        const BPP: usize = CHANNEL_COUNT;
-        const BPFR: usize = SIDE * BPP;
-        const BPCR: usize = FACE_COUNT * BPFR;
-        fn index_4d(face: usize, row: usize, col: usize, chan: usize) -> usize {
-            BPCR * row + BPFR * (FACE_COUNT - face - 1) + BPP * col + chan
-        }
+        let panel = panel();
+        let (col0, col1) = stripes(panel.width);
+        let (row0, row1) = stripes(panel.height);
         for face in 0..FACE_COUNT {
-            for i in 0..SIDE {
+            for i in 0..panel.height {
                 // Horizontal stripes
-                self.data[index_4d(face, i, rc0, HORIZ_CHANNEL[face])] = value;
-                self.data[index_4d(face, i, rc1, HORIZ_CHANNEL[face])] = value;
-
+                self.data[index_4d(face, i, col0, HORIZ_CHANNEL[face])] = value;
+                self.data[index_4d(face, i, col1, HORIZ_CHANNEL[face])] = value;
+            }
+            for i in 0..panel.width {
                 // Vertical stripes
-                self.data[index_4d(face, rc0, i, VERT_CHANNEL[face])] = value;
-                self.data[index_4d(face, rc1, i, VERT_CHANNEL[face])] = value;
+                self.data[index_4d(face, row0, i, VERT_CHANNEL[face])] = value;
+                self.data[index_4d(face, row1, i, VERT_CHANNEL[face])] = value;
             }
         }
     }
+}
+
+impl PatternSource for Stripes {
+    fn name(&self) -> &str {
+        "stripes"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
+        // The stripes leave a fading trail, so each frame rewrites the
+        // trail behind the current position.
+        let cycle = Self::cycle();
+        let frame_number = info.frame_number % cycle + 8 * cycle;
+        self.write_row_column(frame_number - 8, 0u8);
+        self.write_row_column(frame_number - 7, 255u8);
+        self.write_row_column(frame_number - 6, 63u8);
+        self.write_row_column(frame_number - 5, 15u8);
+        self.write_row_column(frame_number - 4, 3u8);
+        self.write_row_column(frame_number - 3, 3u8);
+        self.write_row_column(frame_number - 2, 15u8);
+        self.write_row_column(frame_number - 1, 63u8);
+        self.write_row_column(frame_number, 255u8);
+        &self.data
+    }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  pinwheel  ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+pub struct Pinwheel {
+    data: Vec<u8>,
+}
+
+impl Pinwheel {
+    pub fn new() -> Self {
+        Self {
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Pinwheel {
+    fn name(&self) -> &str {
+        "pinwheel"
+    }
 
-    fn next_frame_2(&mut self) -> &PixelArray {
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
         const FACE_COLORS: [[u8; 3]; FACE_COUNT] = [
             [255, 0, 0],
             [0, 255, 0],
@@ -106,40 +161,22 @@ impl TestPattern {
             [255, 0, 255],
             [255, 255, 0],
         ];
-        const HALF_SIDE: usize = SIDE / 2;
-        const REP: usize = 4 * SIDE;
-        if self.frame_number == 4 * SIDE {
-            self.frame_number = 0;
-        } else {
-            self.frame_number += 1;
-        }
-
-        // BPP:  4 bytes per pixel
-        // BPFR: 64 pixels per face row
-        // BPCR: 6 face rows per row
-        const BPP: usize = CHANNEL_COUNT;
-        const BPFR: usize = SIDE * BPP;
-        const BPCR: usize = FACE_COUNT * BPFR;
-        fn index_4d(face: usize, row: usize, col: usize, chan: usize) -> usize {
-            BPCR * row + BPFR * (FACE_COUNT - face - 1) + BPP * col + chan
-        }
+        let panel = panel();
+        let rep = 4 * panel.width;
+        let frame_number = info.frame_number % (rep + 1);
 
         let angle = cgmath::Rad(
-            std::f32::consts::TAU * self.frame_number as f32 / REP as f32,
+            std::f32::consts::TAU * frame_number as f32 / rep as f32,
         );
         let rot = cgmath::Matrix2::<f32>::from_angle(-angle);
-        for face in 0..FACE_COUNT {
-            for i in 0..SIDE {
-                for j in 0..SIDE {
-                    let x = (j as f32 - HALF_SIDE as f32) + 0.5;
-                    let y = (HALF_SIDE as f32 - i as f32) + 0.5;
+        for (face, face_color) in FACE_COLORS.iter().enumerate() {
+            for i in 0..panel.height {
+                for j in 0..panel.width {
+                    let x = (j as f32 - (panel.width / 2) as f32) + 0.5;
+                    let y = ((panel.height / 2) as f32 - i as f32) + 0.5;
                     let v = cgmath::Vector2::new(x, y);
                     let v = rot * v;
-                    let color = if v.y > 0.0 {
-                        FACE_COLORS[face]
-                    } else {
-                        [0, 0, 0]
-                    };
+                    let color = if v.y > 0.0 { *face_color } else { [0, 0, 0] };
                     self.data[index_4d(face, i, j, 0)] = color[0];
                     self.data[index_4d(face, i, j, 1)] = color[1];
                     self.data[index_4d(face, i, j, 2)] = color[2];
@@ -149,7 +186,31 @@ impl TestPattern {
         &self.data
     }
 
-    fn next_frame_3(&mut self) -> &PixelArray {
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  squares   ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+pub struct Squares {
+    data: Vec<u8>,
+}
+
+impl Squares {
+    pub fn new() -> Self {
+        Self {
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Squares {
+    fn name(&self) -> &str {
+        "squares"
+    }
+
+    fn next_frame(&mut self, info: &FrameInfo) -> &PixelArray {
         const FACE_COLORS: [[u8; 3]; FACE_COUNT] = [
             [255, 0, 0],
             [0, 255, 0],
@@ -158,29 +219,23 @@ impl TestPattern {
             [255, 0, 255],
             [255, 255, 0],
         ];
-        if self.frame_number >= usize::MAX / 2 {
-            self.frame_number = 0;
-        } else {
-            self.frame_number += 1;
-        }
-        let lit_face = self.frame_number / 16 % FACE_COUNT;
-        let radius = (self.frame_number % 16 * 2) as i32;
-        const BPP: usize = CHANNEL_COUNT;
-        const BPFR: usize = SIDE * BPP;
-        const BPCR: usize = FACE_COUNT * BPFR;
-        fn index_4d(face: usize, row: usize, col: usize, chan: usize) -> usize {
-            BPCR * row + BPFR * (FACE_COUNT - face - 1) + BPP * col + chan
-        }
+        let panel = panel();
+        let frame_number = info.frame_number;
+        let lit_face = frame_number / 16 % FACE_COUNT;
+        let radius =
+            (frame_number % 16 * panel.width.min(panel.height) / 32) as i32;
+        let (center_row, center_col) = (panel.height / 2, panel.width / 2);
 
-        for face in 0..FACE_COUNT {
+        for (face, face_color) in FACE_COLORS.iter().enumerate() {
             let color = if face == lit_face {
-                FACE_COLORS[face]
+                *face_color
             } else {
                 [0, 0, 0]
             };
-            for i in 0..SIDE {
-                for j in 0..SIDE {
-                    let r = (i as i32 - 32).abs().max((j as i32 - 32).abs());
+            for i in 0..panel.height {
+                for j in 0..panel.width {
+                    let r = i.abs_diff(center_row).max(j.abs_diff(center_col))
+                        as i32;
                     let c = if r <= radius && 2 * r > radius {
                         color
                     } else {
@@ -195,7 +250,31 @@ impl TestPattern {
         &self.data
     }
 
-    fn next_frame_4(&mut self) -> &PixelArray {
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
+}
+
+// ----  faces  --- ---- ---- ---- ---- ---- ---- ---- ---- ---- ---- ----
+
+pub struct Faces {
+    data: Vec<u8>,
+}
+
+impl Faces {
+    pub fn new() -> Self {
+        Self {
+            data: black_frame(),
+        }
+    }
+}
+
+impl PatternSource for Faces {
+    fn name(&self) -> &str {
+        "faces"
+    }
+
+    fn next_frame(&mut self, _: &FrameInfo) -> &PixelArray {
         const FACE_COLORS: [[u8; 3]; FACE_COUNT] = [
             [0, 255, 255],
             [255, 0, 255],
@@ -204,22 +283,10 @@ impl TestPattern {
             [0, 255, 0],
             [0, 0, 255],
         ];
-        if self.frame_number >= usize::MAX / 2 {
-            self.frame_number = 0;
-        } else {
-            self.frame_number += 1;
-        }
-        const BPP: usize = CHANNEL_COUNT;
-        const BPFR: usize = SIDE * BPP;
-        const BPCR: usize = FACE_COUNT * BPFR;
-        fn index_4d(face: usize, row: usize, col: usize, chan: usize) -> usize {
-            BPCR * row + BPFR * (FACE_COUNT - face - 1) + BPP * col + chan
-        }
-
-        for face in 0..FACE_COUNT {
-            let color = FACE_COLORS[face];
-            for i in 0..SIDE {
-                for j in 0..SIDE {
+        let panel = panel();
+        for (face, color) in FACE_COLORS.iter().enumerate() {
+            for i in 0..panel.height {
+                for j in 0..panel.width {
                     self.data[index_4d(face, i, j, 0)] = color[0];
                     self.data[index_4d(face, i, j, 1)] = color[1];
                     self.data[index_4d(face, i, j, 2)] = color[2];
@@ -228,4 +295,8 @@ impl TestPattern {
         }
         &self.data
     }
+
+    fn current_frame(&self) -> &PixelArray {
+        &self.data
+    }
 }
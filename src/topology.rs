@@ -0,0 +1,287 @@
+// How the faces' LED grids connect.
+//
+// 2D effects (snakes, Life, ripples, rain) treat the cube as one grid
+// that wraps across the edges.  Stepping off a face lands on the
+// neighboring face's edge cell, and the direction of travel is rotated
+// to match that face's rows and columns.  The connections are derived
+// from `CubeModel`'s face transforms through the LED table, so they
+// agree with how the faces are drawn.
+
+use std::sync::OnceLock;
+
+use crate::cube_model::{self, CubeModel, FACE_LENGTH_MM};
+use crate::leds;
+use crate::panel::panel;
+use crate::prelude::*;
+
+// Directions in a face's own rows and columns.  Up is toward row 0,
+// left is toward column 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    pub fn turn_right(self) -> Self {
+        Self::ALL[(self as usize + 1) % 4]
+    }
+
+    #[cfg(test)]
+    pub fn reverse(self) -> Self {
+        Self::ALL[(self as usize + 2) % 4]
+    }
+
+    // (row, col) step
+    pub fn delta(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Right => (0, 1),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+        }
+    }
+
+    // In face coordinates, where rows run toward -y.
+    fn face_vector(self) -> Vec3 {
+        let (row, col) = self.delta();
+        Vec3::new(col as f32, -row as f32, 0.0)
+    }
+
+    fn from_face_vector(v: Vec3) -> Self {
+        if v.x.abs() > v.y.abs() {
+            if v.x > 0.0 {
+                Direction::Right
+            } else {
+                Direction::Left
+            }
+        } else if v.y > 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub face: usize,
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Cell {
+    pub fn new(face: usize, row: usize, col: usize) -> Self {
+        Self { face, row, col }
+    }
+
+    // `Panel::led_id`, for per-cell arrays
+    pub fn id(self) -> usize {
+        panel().led_id(self.face, self.row, self.col)
+    }
+}
+
+pub struct Topology {
+    // neighbors[4 * cell.id() + direction]
+    neighbors: Vec<(Cell, Direction)>,
+    inverses: Vec<Mat4>,
+    normals: Vec<Vec3>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        let xforms = CubeModel::make_face_xforms();
+        let inverses: Vec<Mat4> =
+            xforms.iter().map(|xf| xf.invert().unwrap()).collect();
+        let normals: Vec<Vec3> = xforms
+            .iter()
+            .map(|xf| (xf * Vec3::unit_z().extend(0.0)).truncate())
+            .collect();
+        let leds = leds::table();
+
+        let panel = panel();
+        let mut neighbors = Vec::with_capacity(4 * panel.led_count());
+        for led in leds {
+            let cell = Cell::new(led.face, led.row, led.col);
+            for dir in Direction::ALL {
+                let (dr, dc) = dir.delta();
+                let row = led.row as isize + dr;
+                let col = led.col as isize + dc;
+                if (0..panel.height as isize).contains(&row)
+                    && (0..panel.width as isize).contains(&col)
+                {
+                    let next = Cell::new(cell.face, row as _, col as _);
+                    neighbors.push((next, dir));
+                    continue;
+                }
+
+                // Off the edge: the next face is the one facing the way
+                // we were going, and we go on away from this face.
+                let heading = xforms[cell.face] * dir.face_vector().extend(0.0);
+                let heading = heading.truncate();
+                let face = facing(&normals, heading);
+                let local = inverses[face] * led.position.to_homogeneous();
+                let (row, col) = face_cell(local.x, local.y);
+                let away = inverses[face] * (-normals[cell.face]).extend(0.0);
+                let next_dir = Direction::from_face_vector(away.truncate());
+                neighbors.push((Cell::new(face, row, col), next_dir));
+            }
+        }
+        Self {
+            neighbors,
+            inverses,
+            normals,
+        }
+    }
+
+    // The cell one step from `cell` in `dir`, and the direction of
+    // travel in the new cell's face.
+    pub fn neighbor(&self, cell: Cell, dir: Direction) -> (Cell, Direction) {
+        self.neighbors[4 * cell.id() + dir as usize]
+    }
+
+    // The outward normal of `face`, in cube space.
+    pub fn normal(&self, face: usize) -> Vec3 {
+        self.normals[face]
+    }
+
+    // The cell where a ray from the cube's center in direction `v`
+    // meets the LEDs.
+    pub fn cell_toward(&self, v: Vec3) -> Cell {
+        let face = facing(&self.normals, v);
+        let s =
+            cube_model::FACE_LENGTH_MM / 2.0 + cube_model::FACE_DISPLACEMENT_MM;
+        let hit = v * (s / self.normals[face].dot(v));
+        let local = self.inverses[face] * hit.extend(1.0);
+        let (row, col) = face_cell(local.x, local.y);
+        Cell::new(face, row, col)
+    }
+}
+
+// The face whose normal is closest to `v`.
+fn facing(normals: &[Vec3], v: Vec3) -> usize {
+    (0..normals.len())
+        .max_by(|&a, &b| normals[a].dot(v).total_cmp(&normals[b].dot(v)))
+        .unwrap()
+}
+
+// The cell nearest to face coordinates (x, y).
+fn face_cell(x: f32, y: f32) -> (usize, usize) {
+    const HFL: f32 = FACE_LENGTH_MM / 2.0;
+    let panel = panel();
+    let (pitch_x, pitch_y) = panel.pitch_mm();
+    let index = |v: f32, pitch: f32, count: usize| {
+        (v / pitch).floor().clamp(0.0, count as f32 - 1.0) as usize
+    };
+    (
+        index(HFL - y, pitch_y, panel.height),
+        index(x + HFL, pitch_x, panel.width),
+    )
+}
+
+// The topology never changes, so it is built once.
+pub fn topology() -> &'static Topology {
+    static TOPOLOGY: OnceLock<Topology> = OnceLock::new();
+    TOPOLOGY.get_or_init(Topology::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_cells() -> impl Iterator<Item = Cell> {
+        leds::table()
+            .iter()
+            .map(|led| Cell::new(led.face, led.row, led.col))
+    }
+
+    // The cells on the way from `start` straight back to it.
+    fn edge_loop(start: Cell, dir: Direction) -> Vec<Cell> {
+        let panel = panel();
+        let mut cells = vec![start];
+        let (mut cell, mut d) = topology().neighbor(start, dir);
+        while (cell, d) != (start, dir) {
+            assert!(cells.len() < 4 * panel.width.max(panel.height));
+            cells.push(cell);
+            (cell, d) = topology().neighbor(cell, d);
+        }
+        cells
+    }
+
+    #[test]
+    fn step_and_back_returns() {
+        let topology = topology();
+        for cell in all_cells() {
+            for dir in Direction::ALL {
+                let (next, next_dir) = topology.neighbor(cell, dir);
+                let (back, back_dir) =
+                    topology.neighbor(next, next_dir.reverse());
+                assert_eq!((back, back_dir.reverse()), (cell, dir));
+            }
+        }
+    }
+
+    // Across an edge, neighbors are a bit farther apart because the
+    // faces are displaced from the cube's surface.
+    #[test]
+    fn neighbors_are_adjacent() {
+        let (pitch_x, pitch_y) = panel().pitch_mm();
+        let topology = topology();
+        let leds = leds::table();
+        for cell in all_cells() {
+            let here = leds.get(cell.face, cell.row, cell.col).position;
+            for dir in Direction::ALL {
+                let (next, _) = topology.neighbor(cell, dir);
+                let there = leds.get(next.face, next.row, next.col).position;
+                let distance = (there - here).magnitude();
+                assert!(
+                    distance < 4.0 * pitch_x.max(pitch_y),
+                    "{:?} {:?}",
+                    cell,
+                    dir
+                );
+            }
+        }
+    }
+
+    // Going straight comes back to the start after four panel widths
+    // on the default, square, panels.
+    #[test]
+    fn edge_loops_return_to_start() {
+        let panel = panel();
+        assert_eq!(panel.width, panel.height);
+        for cell in all_cells() {
+            for dir in Direction::ALL {
+                assert_eq!(edge_loop(cell, dir).len(), 4 * panel.width);
+            }
+        }
+    }
+
+    // Each loop crosses four faces, once each.
+    #[test]
+    fn edge_loops_visit_four_faces() {
+        for cell in all_cells() {
+            for dir in Direction::ALL {
+                let mut faces: Vec<usize> =
+                    edge_loop(cell, dir).iter().map(|c| c.face).collect();
+                faces.dedup();
+                if faces.len() > 1 && faces.first() == faces.last() {
+                    faces.pop();
+                }
+                let count = faces.len();
+                faces.sort();
+                faces.dedup();
+                assert_eq!((count, faces.len()), (4, 4), "{:?}", cell);
+            }
+        }
+    }
+}
@@ -11,6 +11,12 @@ const RANDOMIZE_AXIS: bool = true;
 
 const MOUSE_INACTIVE: Duration = Duration::from_millis(50);
 
+// How long the spin at start takes to turn the cube once in an export:
+// 1024 frames at 60 fps, for looping video.  Live, it turns about twice
+// as fast, and faster at higher frame rates.
+pub const SPIN_PERIOD: Duration =
+    Duration::from_nanos(1024 * 1_000_000_000 / 60);
+
 pub trait Responder {
     fn handle_window_event(&mut self, evt: &WindowEvent) -> bool;
 }
@@ -23,8 +29,17 @@ pub trait Manipulable {
     fn mouse_drag(&mut self, pos: &PhysicalPosition<f64>, t: Instant);
     fn mouse_up(&mut self, t: Instant);
     fn orientation(&mut self, t: Instant) -> Mat4;
+    // Restart the clock at `t` and turn by exactly the time that
+    // passes from then on, so an export's frames don't depend on the
+    // frame rate.
+    fn start_export(&mut self, t: Instant);
 }
 
+// Something that turns the cube and takes the window's mouse events.
+pub trait Controller: Manipulable + Responder {}
+
+impl<T: Manipulable + Responder> Controller for T {}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Trackball {
     cached_xform: Option<Mat4>,
@@ -33,6 +48,7 @@ pub struct Trackball {
     drag_dt: Duration,
     rot_per_dt: Option<Quaternion<f32>>,
     prev_orientation_time: Instant,
+    fixed_step: bool, // see start_export
 
     mouse_state: ElementState,
     viewport_size: PhysicalSize<u32>,
@@ -53,9 +69,11 @@ impl Trackball {
         //     None,
         // );
         let now = Instant::now();
+        let drag_dt = Duration::new(0, 1_000_000_000 / 60);
         let rotation_speed = if ROTATE_AT_START {
-            // full rotation every 1024 frames for looping video
-            Rad(std::f32::consts::TAU / 1024.0)
+            // full rotation every SPIN_PERIOD
+            let turns = drag_dt.as_secs_f64() / SPIN_PERIOD.as_secs_f64();
+            Rad(std::f32::consts::TAU * turns as f32)
         } else {
             Rad(0f32)
         };
@@ -83,8 +101,9 @@ impl Trackball {
                 rotation_axis,
                 rotation_speed,
             )),
-            drag_dt: Duration::new(0, 1_000_000_000 / 60),
+            drag_dt,
             prev_orientation_time: now,
+            fixed_step: false,
 
             mouse_state: ElementState::Released,
             viewport_size: *viewport_size,
@@ -97,6 +116,18 @@ impl Trackball {
         }
     }
 
+    // Turn the cube to `orientation` and stop it spinning.
+    pub fn set_orientation(&mut self, orientation: Quaternion<f32>) {
+        self.cur_orientation = orientation;
+        self.rot_per_dt = None;
+        self.cached_xform = None;
+    }
+
+    pub fn quaternion(&mut self, t: Instant) -> Quaternion<f32> {
+        self.orientation(t);
+        self.cur_orientation
+    }
+
     fn surface_point(&self, pos: &PhysicalPosition<f64>) -> Vec3 {
         // Implements the Bell virtual trackball in
         // Henriksen, Sporing, Hornbaek
@@ -179,11 +210,19 @@ impl Manipulable for Trackball {
             if let Some(vel) = self.rot_per_dt {
                 let dt = t.duration_since(self.prev_orientation_time);
                 if !dt.is_zero() {
-                    let dest = vel * self.cur_orientation;
                     let amount = dt.as_secs_f32() / self.drag_dt.as_secs_f32();
-                    self.cur_orientation =
-                        self.cur_orientation.nlerp(dest, amount);
-                    self.cur_orientation = vel * self.cur_orientation;
+                    if self.fixed_step {
+                        // `vel` is the turn per `drag_dt`, so the spin
+                        // doesn't depend on the frame rate.
+                        let turn = scale_rotation(vel, amount);
+                        self.cur_orientation =
+                            (turn * self.cur_orientation).normalize();
+                    } else {
+                        let dest = vel * self.cur_orientation;
+                        self.cur_orientation =
+                            self.cur_orientation.nlerp(dest, amount);
+                        self.cur_orientation = vel * self.cur_orientation;
+                    }
                     self.cached_xform = None;
                 }
             }
@@ -194,6 +233,11 @@ impl Manipulable for Trackball {
             .cached_xform
             .get_or_insert_with(|| self.cur_orientation.into())
     }
+
+    fn start_export(&mut self, t: Instant) {
+        self.prev_orientation_time = t;
+        self.fixed_step = true;
+    }
 }
 impl Responder for Trackball {
     fn handle_window_event(&mut self, evt: &WindowEvent) -> bool {
@@ -229,3 +273,14 @@ impl Responder for Trackball {
         }
     }
 }
+
+// The rotation `q` scaled by `amount`: half as far for 0.5, twice as
+// far for 2.
+fn scale_rotation(q: Quaternion<f32>, amount: f32) -> Quaternion<f32> {
+    let sin_half = q.v.magnitude();
+    if sin_half < 1e-7 {
+        return Quaternion::one();
+    }
+    let angle = 2.0 * sin_half.atan2(q.s);
+    Quaternion::from_axis_angle(q.v / sin_half, Rad(angle * amount))
+}
@@ -0,0 +1,241 @@
+// A panel for tuning the tweaks (see tweaks.rs) while the cube runs,
+// drawn with egui over the finished frame.  F1 shows and hides it.
+// While it's shown, it gets the mouse and keyboard first.
+//
+// The preset file it saves and loads is the `preset` setting's, or
+// tweaks.toml in the current directory.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::settings::settings;
+use crate::tweaks::Tweaks;
+
+const DEFAULT_PRESET: &str = "tweaks.toml";
+
+// The preset's part of the panel.
+struct Preset {
+    path: String,
+    status: String, // what the last save or load did
+    defaults: Tweaks,
+}
+
+pub struct TweakPanel {
+    pub shown: bool,
+    window: Arc<Window>,
+    context: egui::Context,
+    input: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    preset: Preset,
+}
+
+impl TweakPanel {
+    // `defaults` are what the Defaults button goes back to.
+    pub fn new(
+        window: Arc<Window>,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        defaults: &Tweaks,
+    ) -> Self {
+        let context = egui::Context::default();
+        let input = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            &window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, format, None, 1, false);
+        let path = match &settings().preset {
+            Some(path) => path.display().to_string(),
+            None => DEFAULT_PRESET.to_string(),
+        };
+        Self {
+            shown: false,
+            window,
+            context,
+            input,
+            renderer,
+            preset: Preset {
+                path,
+                status: String::new(),
+                defaults: defaults.clone(),
+            },
+        }
+    }
+
+    // True when the panel used `event`, and nothing else should.
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
+        self.shown && self.input.on_window_event(&self.window, event).consumed
+    }
+
+    // Show the panel, which edits `tweaks`, over `view`.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        tweaks: &mut Tweaks,
+    ) {
+        if !self.shown {
+            return;
+        }
+        let raw_input = self.input.take_egui_input(&self.window);
+        let preset = &mut self.preset;
+        let output = self.context.run(raw_input, |context| {
+            layout(context, preset, tweaks);
+        });
+        self.input
+            .handle_platform_output(&self.window, output.platform_output);
+        let jobs = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        let size = self.window.inner_size();
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("tweak_panel_encoder"),
+            });
+        let mut commands = self.renderer.update_buffers(
+            device,
+            queue,
+            &mut encoder,
+            &jobs,
+            &screen,
+        );
+        let render_pass =
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("tweak_panel_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        // egui wants a pass that doesn't borrow the encoder.
+        self.renderer.render(
+            &mut render_pass.forget_lifetime(),
+            &jobs,
+            &screen,
+        );
+        commands.push(encoder.finish());
+        queue.submit(commands);
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+fn layout(context: &egui::Context, preset: &mut Preset, tweaks: &mut Tweaks) {
+    egui::Window::new("Tweaks").show(context, |ui| {
+        egui::CollapsingHeader::new("Lights")
+            .default_open(true)
+            .show(ui, |ui| {
+                for (i, light) in tweaks.lights.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}", i));
+                        ui.color_edit_button_rgb(&mut light.color);
+                        ui.add(egui::Slider::new(
+                            &mut light.intensity,
+                            0.0..=2.0,
+                        ));
+                    });
+                }
+            });
+        egui::CollapsingHeader::new("Material").show(ui, |ui| {
+            let m = &mut tweaks.material;
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgb(&mut m.base_color);
+                ui.label("base color");
+            });
+            for (value, name) in [
+                (&mut m.subsurface, "subsurface"),
+                (&mut m.metallic, "metallic"),
+                (&mut m.specular, "specular"),
+                (&mut m.specular_tint, "specular tint"),
+                (&mut m.roughness, "roughness"),
+                (&mut m.anisotropic, "anisotropic"),
+                (&mut m.sheen, "sheen"),
+                (&mut m.sheen_tint, "sheen tint"),
+                (&mut m.clearcoat, "clearcoat"),
+                (&mut m.clearcoat_gloss, "clearcoat gloss"),
+            ] {
+                ui.add(egui::Slider::new(value, 0.0..=1.0).text(name));
+            }
+        });
+        egui::CollapsingHeader::new("Cube")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgba_unmultiplied(
+                        &mut tweaks.edge_color,
+                    );
+                    ui.label("edge color");
+                });
+                ui.add(
+                    egui::Slider::new(&mut tweaks.led_brightness, 0.0..=8.0)
+                        .text("LED brightness"),
+                );
+            });
+        egui::CollapsingHeader::new("Post")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::Slider::new(&mut tweaks.bloom, 0.0..=4.0)
+                        .text("bloom"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut tweaks.bloom_threshold, 0.0..=4.0)
+                        .text("bloom threshold"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut tweaks.white_point, 0.5..=10.0)
+                        .text("white point"),
+                );
+            });
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut preset.path);
+            if ui.button("Save").clicked() {
+                preset.status = match tweaks.save(Path::new(&preset.path)) {
+                    Ok(()) => format!("saved {}", preset.path),
+                    Err(e) => format!("{:#}", e),
+                };
+            }
+            if ui.button("Load").clicked() {
+                preset.status = match Tweaks::load(Path::new(&preset.path)) {
+                    Ok(mut loaded) => {
+                        loaded.keep_lights(&tweaks.lights);
+                        *tweaks = loaded;
+                        format!("loaded {}", preset.path)
+                    }
+                    Err(e) => format!("{:#}", e),
+                };
+            }
+            if ui.button("Defaults").clicked() {
+                *tweaks = preset.defaults.clone();
+                preset.status.clear();
+            }
+        });
+        if !preset.status.is_empty() {
+            ui.label(&preset.status);
+        }
+    });
+}
@@ -0,0 +1,243 @@
+// Values the shaders used to have built in, to tune while the cube
+// runs: the lights' colors and intensities, the default material, the
+// cube edges' color, the LEDs' brightness, the bloom and the tone map's
+// white point.  The tweak panel (see tweak_panel.rs) edits them, and
+// they're saved to and loaded from TOML preset files.  The `preset`
+// setting names one to start with.
+//
+// Every key of a preset is optional; what's left out keeps its
+// default.  These are the defaults:
+//
+//     led_brightness = 2.0
+//     bloom = 1.0            # how much of the bright color is added
+//     bloom_threshold = 1.0  # how bright an LED is before it blooms
+//     white_point = 2.0      # what the Reinhard tone maps map to white
+//     edge_color = [0.05, 0.05, 0.05, 1.0]
+//
+//     [material]             # material_defaults() in the shader
+//     base_color = [0.82, 0.67, 0.16]
+//     subsurface = 0.0
+//     ...
+//
+//     [[lights]]             # in the order Lights::new makes them
+//     intensity = 0.0
+//     color = [1.0, 1.0, 1.0]
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Material {
+    pub base_color: [f32; 3],
+    pub subsurface: f32,
+    pub metallic: f32,
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub roughness: f32,
+    pub anisotropic: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Light {
+    pub intensity: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tweaks {
+    pub led_brightness: f32,
+    pub bloom: f32,
+    pub bloom_threshold: f32,
+    pub white_point: f32,
+    pub edge_color: [f32; 4],
+    pub material: Material,
+    pub lights: Vec<Light>, // see keep_lights
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: [0.82, 0.67, 0.16],
+            subsurface: 0.0,
+            metallic: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            roughness: 0.5,
+            anisotropic: 0.0,
+            sheen: 0.5,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+        }
+    }
+}
+
+impl Default for Tweaks {
+    fn default() -> Self {
+        Self {
+            led_brightness: 2.0,
+            bloom: 1.0,
+            bloom_threshold: 1.0,
+            white_point: 2.0,
+            edge_color: [0.05, 0.05, 0.05, 1.0],
+            material: Material::default(),
+            lights: Vec::new(),
+        }
+    }
+}
+
+// The shaders' TweaksUniform.  The scalars come first so that
+// post_shaders.wgsl can declare just those.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TweaksUniformRaw {
+    edge_color: [f32; 4],
+    led_brightness: f32,
+    bloom: f32,
+    bloom_threshold: f32,
+    white_point: f32,
+    material: MaterialRaw,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialRaw {
+    base_color: [f32; 3],
+    subsurface: f32,
+    metallic: f32,
+    specular: f32,
+    specular_tint: f32,
+    roughness: f32,
+    anisotropic: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_gloss: f32,
+    _padding: [u32; 3],
+}
+
+impl Tweaks {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("{}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        std::fs::write(path, text)
+            .with_context(|| format!("can't write {}", path.display()))
+    }
+
+    // The lights a preset leaves out stay as `lights` has them.
+    pub fn keep_lights(&mut self, lights: &[Light]) {
+        if let Some(rest) = lights.get(self.lights.len()..) {
+            self.lights.extend_from_slice(rest);
+        }
+    }
+
+    fn to_raw(&self) -> TweaksUniformRaw {
+        let m = &self.material;
+        TweaksUniformRaw {
+            edge_color: self.edge_color,
+            led_brightness: self.led_brightness,
+            bloom: self.bloom,
+            bloom_threshold: self.bloom_threshold,
+            white_point: self.white_point,
+            material: MaterialRaw {
+                base_color: m.base_color,
+                subsurface: m.subsurface,
+                metallic: m.metallic,
+                specular: m.specular,
+                specular_tint: m.specular_tint,
+                roughness: m.roughness,
+                anisotropic: m.anisotropic,
+                sheen: m.sheen,
+                sheen_tint: m.sheen_tint,
+                clearcoat: m.clearcoat,
+                clearcoat_gloss: m.clearcoat_gloss,
+                _padding: [0; 3],
+            },
+        }
+    }
+}
+
+// The tweaks, where the shaders see them.
+pub struct TweaksUniform {
+    buffer: wgpu::Buffer,
+}
+
+impl TweaksUniform {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tweaks_uniform_buffer"),
+            size: std::mem::size_of::<TweaksUniformRaw>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { buffer }
+    }
+
+    pub fn resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, tweaks: &Tweaks) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::bytes_of(&tweaks.to_raw()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip() {
+        let tweaks = Tweaks {
+            bloom: 0.25,
+            material: Material {
+                roughness: 0.75,
+                ..Material::default()
+            },
+            lights: vec![Light {
+                intensity: 0.5,
+                color: [1.0, 0.5, 0.0],
+            }],
+            ..Tweaks::default()
+        };
+        let text = toml::to_string_pretty(&tweaks).unwrap();
+        assert_eq!(toml::from_str::<Tweaks>(&text).unwrap(), tweaks);
+
+        // What's left out keeps its default.
+        let partial: Tweaks = toml::from_str("white_point = 4.0").unwrap();
+        assert_eq!(partial.white_point, 4.0);
+        assert_eq!(partial.material, Material::default());
+        assert!(toml::from_str::<Tweaks>("exposure = 1.0").is_err());
+
+        let mut partial = tweaks.clone();
+        partial.lights.clear();
+        partial.keep_lights(&tweaks.lights);
+        assert_eq!(partial, tweaks);
+    }
+
+    #[test]
+    fn uniform_matches_the_shaders() {
+        // TweaksUniform in common_shader.wgsl: a vec4, four f32s, then
+        // Material, whose vec3 aligns it to 16 bytes.
+        assert_eq!(std::mem::size_of::<TweaksUniformRaw>(), 96);
+        assert_eq!(std::mem::offset_of!(TweaksUniformRaw, material), 32);
+    }
+}
@@ -0,0 +1,324 @@
+// How the physical panels are wired.
+//
+// The panels are chained: the byte stream from the controller fills
+// panel 0, then panel 1, and so on.  Each panel can sit on any face,
+// turned in 90 degree steps and possibly mirrored, and can scan its
+// LEDs by rows or by columns, optionally serpentine (every other line
+// runs backward).  The default wiring has panel n on face n, upright,
+// scanning rows left to right.
+//
+// The wiring setting names a TOML file with one `[[panel]]` table per panel,
+// in chain order:
+//
+//     [[panel]]
+//     face = 2          # which face it's on
+//     rotation = 90     # degrees clockwise, seen from outside
+//     mirror = false    # flipped left to right before rotating
+//     scan = "rows"     # or "columns"
+//     serpentine = true
+//
+// Frames keep each face upright, so turns, mirroring and scan order
+// only matter where a byte stream arrives (OPC and DMX) and for the
+// decal, which is printed on the panels and turns with them.  The chain
+// order also sets where each face is in a frame and in the decal image:
+// the panels are laid side by side, right to left.  Quarter turns need
+// square panels.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::panel::{panel, Panel};
+use crate::test_pattern::FACE_COUNT;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scan {
+    #[default]
+    Rows,
+    Columns,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PanelWiring {
+    pub face: usize,
+    #[serde(default)]
+    pub rotation: u32, // degrees clockwise
+    #[serde(default)]
+    pub mirror: bool,
+    #[serde(default)]
+    pub scan: Scan,
+    #[serde(default)]
+    pub serpentine: bool,
+}
+
+impl PanelWiring {
+    fn upright(face: usize) -> Self {
+        Self {
+            face,
+            rotation: 0,
+            mirror: false,
+            scan: Scan::Rows,
+            serpentine: false,
+        }
+    }
+
+    fn quarter_turns(self) -> u32 {
+        self.rotation / 90 % 4
+    }
+
+    // Map a point from the panel's own frame, x across and y down, to
+    // the face's.  `extent` is the panel's largest x and y.
+    fn to_face(self, (x, y): (f32, f32), extent: (f32, f32)) -> (f32, f32) {
+        let (mut x, mut y, mut extent) = (x, y, extent);
+        if self.mirror {
+            x = extent.0 - x;
+        }
+        for _ in 0..self.quarter_turns() {
+            (x, y) = (extent.1 - y, x);
+            extent = (extent.1, extent.0);
+        }
+        (x, y)
+    }
+
+    // The inverse of `to_face`.  `extent` is the face's largest x and y.
+    fn to_panel(self, (x, y): (f32, f32), extent: (f32, f32)) -> (f32, f32) {
+        let (mut x, mut y, mut extent) = (x, y, extent);
+        for _ in 0..self.quarter_turns() {
+            (x, y) = (y, extent.0 - x);
+            extent = (extent.1, extent.0);
+        }
+        if self.mirror {
+            x = extent.0 - x;
+        }
+        (x, y)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WiringFile {
+    panel: Vec<PanelWiring>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wiring {
+    size: Panel,
+    panels: [PanelWiring; FACE_COUNT], // in chain order
+    positions: [usize; FACE_COUNT],    // each face's place in the chain
+}
+
+impl Wiring {
+    pub fn upright(size: Panel) -> Self {
+        let panels = std::array::from_fn(PanelWiring::upright);
+        Self::new(size, panels).unwrap()
+    }
+
+    pub fn load(path: &Path, size: Panel) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        Self::parse(&text, size).with_context(|| format!("{}", path.display()))
+    }
+
+    pub fn parse(text: &str, size: Panel) -> Result<Self> {
+        let file: WiringFile = toml::from_str(text)?;
+        let Ok(panels) = file.panel.try_into() else {
+            bail!("expected {} panels", FACE_COUNT);
+        };
+        Self::new(size, panels)
+    }
+
+    fn new(size: Panel, panels: [PanelWiring; FACE_COUNT]) -> Result<Self> {
+        let mut positions = [usize::MAX; FACE_COUNT];
+        for (n, wiring) in panels.iter().enumerate() {
+            if wiring.face >= FACE_COUNT {
+                bail!("panel {}: no face {}", n, wiring.face);
+            }
+            if positions[wiring.face] != usize::MAX {
+                bail!("panel {}: face {} already has a panel", n, wiring.face);
+            }
+            if wiring.rotation % 90 != 0 {
+                bail!("panel {}: rotation must be a multiple of 90", n);
+            }
+            if wiring.quarter_turns() % 2 == 1 && size.width != size.height {
+                bail!("panel {}: only square panels can turn 90 degrees", n);
+            }
+            positions[wiring.face] = n;
+        }
+        Ok(Self {
+            size,
+            panels,
+            positions,
+        })
+    }
+
+    // The face, row and column lit by the `pixel`th pixel of the
+    // byte stream.
+    pub fn locate(&self, pixel: usize) -> (usize, usize, usize) {
+        let face_pixels = self.size.width * self.size.height;
+        self.locate_on(pixel / face_pixels, pixel % face_pixels)
+    }
+
+    // The face, row and column lit by the `pixel`th pixel sent to the
+    // `n`th panel in the chain.
+    pub fn locate_on(&self, n: usize, pixel: usize) -> (usize, usize, usize) {
+        let wiring = &self.panels[n];
+        let (width, height) = (self.size.width, self.size.height);
+        let (mut line, mut step) = match wiring.scan {
+            Scan::Rows => (pixel / width, pixel % width),
+            Scan::Columns => (pixel / height, pixel % height),
+        };
+        let line_length = match wiring.scan {
+            Scan::Rows => width,
+            Scan::Columns => height,
+        };
+        if wiring.serpentine && line % 2 == 1 {
+            step = line_length - step - 1;
+        }
+        if wiring.scan == Scan::Columns {
+            (line, step) = (step, line);
+        }
+        let extent = ((width - 1) as f32, (height - 1) as f32);
+        let (col, row) = wiring.to_face((step as f32, line as f32), extent);
+        (wiring.face, row as usize, col as usize)
+    }
+
+    // The panel's place in the chain.
+    pub fn position(&self, face: usize) -> usize {
+        self.positions[face]
+    }
+
+    // Where a face is in a frame and in the decal image, in panels from
+    // the left.
+    pub fn slot(&self, face: usize) -> usize {
+        FACE_COUNT - self.position(face) - 1
+    }
+
+    // The face at `slot`.
+    pub fn face_at(&self, slot: usize) -> usize {
+        self.panels[FACE_COUNT - slot - 1].face
+    }
+
+    // An affine map from a face's decal coordinates, 0 to 1 with y up,
+    // to the decal image, whose panels are one unit wide and in their
+    // slots.  Rows are `[x, y, 1]` coefficients.
+    pub fn decal_xform(&self, face: usize) -> [[f32; 3]; 2] {
+        let wiring = &self.panels[self.position(face)];
+        let slot = self.slot(face) as f32;
+        let map = |x: f32, y: f32| {
+            let (px, py) = wiring.to_panel((x, 1.0 - y), (1.0, 1.0));
+            (slot + px, 1.0 - py)
+        };
+        let (o, dx, dy) = (map(0.0, 0.0), map(1.0, 0.0), map(0.0, 1.0));
+        [[dx.0 - o.0, dy.0 - o.0, o.0], [dx.1 - o.1, dy.1 - o.1, o.1]]
+    }
+}
+
+static WIRING: OnceLock<Wiring> = OnceLock::new();
+
+// Choose the wiring.  This fails once anything has used it.
+pub fn set(wiring: Wiring) -> Result<()> {
+    WIRING
+        .set(wiring)
+        .map_err(|_| anyhow::anyhow!("the wiring is already in use"))
+}
+
+pub fn wiring() -> &'static Wiring {
+    WIRING.get_or_init(|| Wiring::upright(panel()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_upright() -> String {
+        (0..FACE_COUNT)
+            .map(|face| format!("[[panel]]\nface = {}\n", face))
+            .collect()
+    }
+
+    #[test]
+    fn upright_is_row_major() {
+        let size = Panel::new(4, 3);
+        let wiring = Wiring::parse(&all_upright(), size).unwrap();
+        assert_eq!(wiring, Wiring::upright(size));
+        assert_eq!(wiring.locate(0), (0, 0, 0));
+        assert_eq!(wiring.locate(5), (0, 1, 1));
+        assert_eq!(wiring.locate(12 * 3 + 11), (3, 2, 3));
+    }
+
+    #[test]
+    fn rejects_bad_wiring() {
+        let size = Panel::new(4, 4);
+        let twice = all_upright().replace("face = 1", "face = 0");
+        assert!(Wiring::parse(&twice, size).is_err());
+        let short = "[[panel]]\nface = 0\n";
+        assert!(Wiring::parse(short, size).is_err());
+        let crooked =
+            all_upright().replace("face = 1", "face = 1\nrotation = 45");
+        assert!(Wiring::parse(&crooked, size).is_err());
+        let turned =
+            all_upright().replace("face = 1", "face = 1\nrotation = 90");
+        assert!(Wiring::parse(&turned, size).is_ok());
+        assert!(Wiring::parse(&turned, Panel::new(4, 3)).is_err());
+    }
+
+    #[test]
+    fn turns_mirrors_and_snakes() {
+        let size = Panel::new(3, 3);
+        let text = all_upright().replace(
+            "face = 0",
+            "face = 0\nrotation = 90\nmirror = true\nserpentine = true",
+        );
+        let wiring = Wiring::parse(&text, size).unwrap();
+        // Mirrored, the first row runs right to left; turned a quarter
+        // clockwise, that's the right column running bottom to top.
+        assert_eq!(wiring.locate(0), (0, 2, 2));
+        assert_eq!(wiring.locate(2), (0, 0, 2));
+        // The second row snakes back.
+        assert_eq!(wiring.locate(3), (0, 0, 1));
+
+        let text = all_upright()
+            .replace("face = 1", "face = 1\nscan = \"columns\"")
+            .replace("face = 0", "face = 9")
+            .replace("face = 5", "face = 0")
+            .replace("face = 9", "face = 5");
+        let wiring = Wiring::parse(&text, size).unwrap();
+        assert_eq!(wiring.position(0), 5);
+        assert_eq!((wiring.slot(0), wiring.slot(5)), (0, 5));
+        assert_eq!((wiring.face_at(0), wiring.face_at(5)), (0, 5));
+        assert_eq!(wiring.locate(0), (5, 0, 0));
+        assert_eq!(wiring.locate(9 + 1), (1, 1, 0));
+    }
+
+    #[test]
+    fn every_led_is_lit_once() {
+        let size = Panel::new(4, 4);
+        let text = all_upright()
+            .replace("face = 2", "face = 2\nrotation = 270\nserpentine = true")
+            .replace("face = 4", "face = 4\nmirror = true\nscan = \"columns\"");
+        let wiring = Wiring::parse(&text, size).unwrap();
+        let mut seen = vec![false; size.led_count()];
+        for pixel in 0..size.led_count() {
+            let (face, row, col) = wiring.locate(pixel);
+            let id = size.led_id(face, row, col);
+            assert!(!seen[id]);
+            seen[id] = true;
+        }
+    }
+
+    #[test]
+    fn decal_follows_the_panel() {
+        let size = Panel::new(4, 4);
+        let wiring = Wiring::upright(size);
+        // Upright, face 0's decal is the rightmost panel's.
+        assert_eq!(wiring.decal_xform(0), [[1.0, 0.0, 5.0], [0.0, 1.0, 0.0]]);
+        let text =
+            all_upright().replace("face = 0", "face = 0\nrotation = 180");
+        let wiring = Wiring::parse(&text, size).unwrap();
+        assert_eq!(wiring.decal_xform(0), [[-1.0, 0.0, 6.0], [0.0, -1.0, 1.0]]);
+    }
+}